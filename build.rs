@@ -0,0 +1,85 @@
+//! Generates the kernel symbol table embedded by `src/symbols.rs`.
+//!
+//! The table is built from the `nm` output of a previous build, passed in the
+//! `RUSTOS_SYMBOL_MAP` environment variable. Without it no table is embedded.
+//! The table is padded to a multiple of `KSYMS_ALIGN` bytes, so regenerating
+//! it from a map of the same code keeps its size and the kernel layout.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const KSYMS_ALIGN: usize = 64 * 1024;
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 16;
+
+/// Parse `nm -n -C` output, keeping text symbols.
+fn parse_map(map: &str) -> Vec<(u64, String)> {
+    let mut symbols: Vec<(u64, String)> = map
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let address = u64::from_str_radix(parts.next()?, 16).ok()?;
+            let kind = parts.next()?;
+            let name = parts.next()?;
+            if kind != "T" && kind != "t" {
+                return None;
+            }
+            Some((address, strip_hash(name).to_string()))
+        })
+        .collect();
+    symbols.sort();
+    symbols.dedup_by_key(|(address, _)| *address);
+    symbols
+}
+
+/// Remove the `::h0123456789abcdef` suffix of legacy mangled Rust symbols.
+fn strip_hash(name: &str) -> &str {
+    match name.rfind("::h") {
+        Some(index) if name.len() - index == 19 => &name[..index],
+        _ => name,
+    }
+}
+
+fn encode(symbols: &[(u64, String)]) -> Vec<u8> {
+    let count = symbols.len();
+    let size = HEADER_SIZE + symbols.iter().map(|(_, name)| ENTRY_SIZE + name.len()).sum::<usize>();
+    let padded_size = (size + KSYMS_ALIGN - 1) / KSYMS_ALIGN * KSYMS_ALIGN;
+
+    let mut table = Vec::with_capacity(padded_size);
+    table.extend_from_slice(b"KSYM");
+    table.extend_from_slice(&(count as u32).to_le_bytes());
+
+    let mut name_offset = HEADER_SIZE + count * ENTRY_SIZE;
+    for (address, name) in symbols {
+        table.extend_from_slice(&address.to_le_bytes());
+        table.extend_from_slice(&(name_offset as u32).to_le_bytes());
+        table.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        name_offset += name.len();
+    }
+    for (_, name) in symbols {
+        table.extend_from_slice(name.as_bytes());
+    }
+
+    table.resize(padded_size, 0);
+    table
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RUSTOS_SYMBOL_MAP");
+    // Read by `option_env!` in `src/build_cmdline.rs`, rebuild when it changes.
+    println!("cargo:rerun-if-env-changed=RUSTOS_CMDLINE");
+
+    let table = match env::var("RUSTOS_SYMBOL_MAP") {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            let map = fs::read_to_string(&path).expect("failed to read RUSTOS_SYMBOL_MAP");
+            encode(&parse_map(&map))
+        }
+        Err(_) => Vec::new(),
+    };
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("ksyms.bin"), table).expect("failed to write ksyms.bin");
+}
@@ -1,7 +1,18 @@
 pub mod gdt;
 pub mod memory;
 pub mod interrupts;
+pub mod exceptions;
+pub mod irq;
+pub mod interrupt_stats;
+pub mod isr;
 pub mod heap;
+pub mod backtrace;
+pub mod context;
+pub mod ps2;
+pub mod pci;
+pub mod bga;
+
+pub use isr::{REGISTER_COUNT, REGISTER_NAMES};
 
 
 use x86_64::instructions::interrupts::without_interrupts;
@@ -18,6 +29,10 @@ where
     without_interrupts(f)
 }
 
+pub fn interrupts_enabled() -> bool {
+    x86_64::instructions::interrupts::are_enabled()
+}
+
 pub fn halt() {
     x86_64::instructions::hlt()
 }
@@ -0,0 +1,48 @@
+use x86_64::VirtAddr;
+
+/// Register holding the frame pointer, as named in `REGISTER_NAMES`.
+pub const FRAME_POINTER_REGISTER: &str = "rbp";
+
+/// Returns the frame pointer of the calling function.
+///
+/// Only meaningful because the target spec disables frame pointer
+/// elimination.
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let rbp: usize;
+    unsafe { llvm_asm!("mov %rbp, $0" : "=r"(rbp) ::: "volatile") };
+    rbp
+}
+
+fn readable(addr: usize) -> bool {
+    match VirtAddr::try_new(addr as u64) {
+        Ok(addr) => super::memory::is_mapped(addr).unwrap_or(true),
+        Err(_) => false,
+    }
+}
+
+/// Follow the chain of saved frame pointers starting at `frame_pointer`.
+///
+/// `f` is called with the return address of every frame until it returns
+/// `false` or the chain ends. Each frame stores the caller's frame pointer at
+/// `[rbp]` and the return address at `[rbp + 8]`. The walk stops at a null,
+/// misaligned or unmapped frame pointer, and when the chain stops moving up
+/// the stack, so a corrupted stack cannot make it loop or fault.
+pub fn walk_frames<F: FnMut(usize) -> bool>(mut frame_pointer: usize, mut f: F) {
+    let word = core::mem::size_of::<usize>();
+    while frame_pointer != 0 && frame_pointer % word == 0 {
+        if !readable(frame_pointer) || !readable(frame_pointer + word) {
+            break;
+        }
+
+        let frame = frame_pointer as *const usize;
+        let (next, return_address) = unsafe { (*frame, *frame.add(1)) };
+        if return_address == 0 || !f(return_address) {
+            break;
+        }
+        if next <= frame_pointer {
+            break;
+        }
+        frame_pointer = next;
+    }
+}
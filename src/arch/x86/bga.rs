@@ -0,0 +1,141 @@
+//! Bochs graphics adapter, the VBE display emulated by QEMU and Bochs.
+//!
+//! Modes are set through the VBE DISPI registers, which unlike VESA BIOS
+//! calls work from long mode. This is the only display adapter supported:
+//! on other hardware, or QEMU with `-vga` other than `std`, `init` finds no
+//! device and only the VGA text console is available.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB, UnusedPhysFrame,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::{pci, read_port_u16, write_port};
+use crate::framebuffer::FramebufferError;
+
+const PCI_VENDOR: u16 = 0x1234;
+const PCI_DEVICE: u16 = 0x1111;
+
+const DISPI_INDEX: u16 = 0x01ce;
+const DISPI_DATA: u16 = 0x01cf;
+
+const INDEX_ID: u16 = 0;
+const INDEX_XRES: u16 = 1;
+const INDEX_YRES: u16 = 2;
+const INDEX_BPP: u16 = 3;
+const INDEX_ENABLE: u16 = 4;
+const INDEX_VIRT_WIDTH: u16 = 6;
+const INDEX_X_OFFSET: u16 = 8;
+const INDEX_Y_OFFSET: u16 = 9;
+
+/// Oldest interface version with 32 bits per pixel.
+const ID_MIN: u16 = 0xb0c4;
+const ID_MAX: u16 = 0xb0cf;
+
+const ENABLED: u16 = 0x01;
+const LINEAR_FRAMEBUFFER: u16 = 0x40;
+
+pub const MAX_WIDTH: usize = 1600;
+pub const MAX_HEIGHT: usize = 1200;
+pub const BITS_PER_PIXEL: usize = 32;
+
+/// Where the framebuffer memory is mapped.
+pub const FRAMEBUFFER_START: usize = 0x_5555_0000_0000;
+
+// Size of the mapping made by `init`, 0 if there is no adapter.
+static MAPPED_SIZE: AtomicU64 = AtomicU64::new(0);
+
+/// A mode set by `set_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub width: usize,
+    pub height: usize,
+    /// Bytes from one line to the next.
+    pub stride: usize,
+    pub address: VirtAddr,
+}
+
+fn read_register(index: u16) -> u16 {
+    write_port(DISPI_INDEX, index);
+    read_port_u16(DISPI_DATA)
+}
+
+fn write_register(index: u16, value: u16) {
+    write_port(DISPI_INDEX, index);
+    write_port(DISPI_DATA, value);
+}
+
+pub fn is_present() -> bool {
+    let id = read_register(INDEX_ID);
+    id >= ID_MIN && id <= ID_MAX
+}
+
+/// Map the framebuffer memory of the adapter, if there is one, at
+/// `FRAMEBUFFER_START`.
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), FramebufferError> {
+    if !is_present() {
+        return Err(FramebufferError::NoDevice);
+    }
+    let bar = pci::find_device(PCI_VENDOR, PCI_DEVICE)
+        .and_then(|device| device.memory_bar(0))
+        .ok_or(FramebufferError::NoDevice)?;
+
+    let start = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(bar.address));
+    let end = PhysFrame::containing_address(PhysAddr::new(bar.address + bar.size - 1));
+    let first_page = Page::containing_address(VirtAddr::new(FRAMEBUFFER_START as u64));
+    // Only the CPU writes the framebuffer, so write-through caching is safe.
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::WRITE_THROUGH;
+    for (i, frame) in PhysFrame::range_inclusive(start, end).enumerate() {
+        let frame = unsafe { UnusedPhysFrame::new(frame) };
+        mapper
+            .map_to(first_page + i as u64, frame, flags, frame_allocator)
+            .map_err(|_| FramebufferError::MappingFailed)?
+            .flush();
+    }
+    MAPPED_SIZE.store(bar.size, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Switch to a linear framebuffer mode with 32 bits per pixel.
+pub fn set_mode(width: usize, height: usize) -> Result<Mode, FramebufferError> {
+    let size = MAPPED_SIZE.load(Ordering::SeqCst) as usize;
+    if size == 0 {
+        return Err(FramebufferError::NoDevice);
+    }
+    if width == 0
+        || height == 0
+        || width > MAX_WIDTH
+        || height > MAX_HEIGHT
+        || width * height * BITS_PER_PIXEL / 8 > size
+    {
+        return Err(FramebufferError::UnsupportedMode);
+    }
+
+    write_register(INDEX_ENABLE, 0);
+    write_register(INDEX_XRES, width as u16);
+    write_register(INDEX_YRES, height as u16);
+    write_register(INDEX_BPP, BITS_PER_PIXEL as u16);
+    write_register(INDEX_VIRT_WIDTH, width as u16);
+    write_register(INDEX_X_OFFSET, 0);
+    write_register(INDEX_Y_OFFSET, 0);
+    write_register(INDEX_ENABLE, ENABLED | LINEAR_FRAMEBUFFER);
+
+    if usize::from(read_register(INDEX_XRES)) != width
+        || usize::from(read_register(INDEX_YRES)) != height
+    {
+        write_register(INDEX_ENABLE, 0);
+        return Err(FramebufferError::UnsupportedMode);
+    }
+
+    Ok(Mode {
+        width,
+        height,
+        stride: width * BITS_PER_PIXEL / 8,
+        address: VirtAddr::new(FRAMEBUFFER_START as u64),
+    })
+}
@@ -0,0 +1,92 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::irq::IrqReturn;
+use super::isr::TrapFrame;
+
+/// Software interrupt raised to switch threads, see `reschedule`.
+pub const RESCHEDULE_VECTOR: u8 = 0xf0;
+
+/// Initial flags of a thread: interrupts enabled.
+const INITIAL_RFLAGS: u64 = 0x202;
+
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+
+/// Switch threads when the current interrupt returns.
+pub(crate) fn request_reschedule() {
+    NEED_RESCHED.store(true, Ordering::SeqCst);
+}
+
+/// Handler of `RESCHEDULE_VECTOR`.
+pub(crate) fn reschedule_handler(_vector: u8) -> IrqReturn {
+    request_reschedule();
+    IrqReturn::Handled
+}
+
+/// Give up the CPU to the next ready thread.
+///
+/// Also works with interrupts disabled, they stay disabled once the
+/// thread runs again.
+pub fn reschedule() {
+    // The vector is an immediate, keep it in sync with `RESCHEDULE_VECTOR`.
+    unsafe { llvm_asm!("int $$0xf0" :::: "volatile") };
+}
+
+/// Called by `rustos_isr_dispatch` before returning from an interrupt.
+///
+/// Returns the trap frame to resume, which lives on the stack of the thread
+/// picked by the scheduler.
+pub(crate) fn switch_point(frame: &mut TrapFrame) -> *mut TrapFrame {
+    let frame = frame as *mut TrapFrame;
+    if !NEED_RESCHED.swap(false, Ordering::SeqCst) {
+        return frame;
+    }
+    crate::thread::schedule(frame as usize) as *mut TrapFrame
+}
+
+fn read_ss() -> u16 {
+    let ss: u16;
+    unsafe { llvm_asm!("mov %ss, $0" : "=r"(ss)) };
+    ss
+}
+
+/// Prepare `stack` so that switching to the returned context calls
+/// `entry(arg)` on it with interrupts enabled.
+pub(crate) fn init_stack(stack: &mut [u8], entry: extern "C" fn(usize) -> !, arg: usize) -> usize {
+    let top = (stack.as_mut_ptr() as usize + stack.len()) & !0xf;
+    // As if `entry` had been called: the return address slot is below a
+    // 16 byte aligned boundary.
+    let rsp = top - 8;
+    let frame_address = (rsp - core::mem::size_of::<TrapFrame>()) & !0xf;
+    assert!(frame_address >= stack.as_ptr() as usize, "thread stack too small");
+
+    let frame = TrapFrame {
+        rax: 0,
+        rbx: 0,
+        rcx: 0,
+        rdx: 0,
+        rsi: 0,
+        rdi: arg as u64,
+        rbp: 0,
+        r8: 0,
+        r9: 0,
+        r10: 0,
+        r11: 0,
+        r12: 0,
+        r13: 0,
+        r14: 0,
+        r15: 0,
+        vector: u64::from(RESCHEDULE_VECTOR),
+        error_code: 0,
+        rip: entry as usize as u64,
+        cs: u64::from(x86_64::instructions::segmentation::cs().0),
+        rflags: INITIAL_RFLAGS,
+        rsp: rsp as u64,
+        ss: u64::from(read_ss()),
+    };
+    unsafe {
+        // A zero return address ends backtraces of the thread.
+        (rsp as *mut u64).write(0);
+        (frame_address as *mut TrapFrame).write(frame);
+    }
+    frame_address
+}
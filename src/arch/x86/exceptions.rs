@@ -0,0 +1,157 @@
+use core::fmt;
+
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::VirtAddr;
+
+use crate::interrupts::handlers::{Exception, RustosExcStackframe};
+
+/// Longest possible x86 instruction.
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+/// Decoded exception error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Selector error code pushed by #TS, #NP, #SS and #GP.
+    Selector {
+        external: bool,
+        table: DescriptorTable,
+        index: u16,
+    },
+    PageFault(PageFaultErrorCode),
+    Raw(u64),
+}
+
+pub fn decode_error_code(exception: Exception, error_code: u64) -> ErrorCode {
+    match exception {
+        Exception::InvalidTss
+        | Exception::SegmentNotPresent
+        | Exception::StackSegmentFault
+        | Exception::GeneralProtectionFault if error_code != 0 => {
+            let table = if error_code & 0b10 != 0 {
+                DescriptorTable::Idt
+            } else if error_code & 0b100 != 0 {
+                DescriptorTable::Ldt
+            } else {
+                DescriptorTable::Gdt
+            };
+            ErrorCode::Selector {
+                external: error_code & 1 != 0,
+                table,
+                index: ((error_code >> 3) & 0x1fff) as u16,
+            }
+        }
+        Exception::PageFault => {
+            ErrorCode::PageFault(PageFaultErrorCode::from_bits_truncate(error_code))
+        }
+        _ => ErrorCode::Raw(error_code),
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorCode::Selector { external, table: DescriptorTable::Idt, index } => {
+                write!(f, "IDT vector {}", index)?;
+                if *external {
+                    write!(f, ", external event")?;
+                }
+                Ok(())
+            }
+            ErrorCode::Selector { external, table, index } => {
+                write!(f, "{:?} selector index {}", table, index)?;
+                if *external {
+                    write!(f, ", external event")?;
+                }
+                Ok(())
+            }
+            ErrorCode::PageFault(flags) => write!(f, "{:?}", flags),
+            ErrorCode::Raw(code) => write!(f, "{:#x}", code),
+        }
+    }
+}
+
+/// Whether the bytes at the faulting instruction pointer can be read.
+///
+/// The first and last byte an instruction can span must be mapped. A page
+/// fault raised while fetching the instruction, or touching the
+/// instruction's own bytes, means reading them would fault again.
+pub fn instruction_readable(exception: Exception, frame: &RustosExcStackframe) -> bool {
+    let ip = frame.instruction_pointer;
+    let last = ip.wrapping_add(MAX_INSTRUCTION_LEN - 1);
+    if last < ip || !mapped(ip) || !mapped(last) {
+        return false;
+    }
+    if exception != Exception::PageFault {
+        return true;
+    }
+
+    let error_code = frame.errcode.unwrap_or(0);
+    if PageFaultErrorCode::from_bits_truncate(error_code)
+        .contains(PageFaultErrorCode::INSTRUCTION_FETCH)
+    {
+        return false;
+    }
+
+    match frame.fault_address {
+        Some(address) => address < ip || address >= ip + MAX_INSTRUCTION_LEN,
+        None => true,
+    }
+}
+
+fn mapped(addr: usize) -> bool {
+    match VirtAddr::try_new(addr as u64) {
+        Ok(addr) => super::memory::is_mapped(addr).unwrap_or(true),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    #[test_case]
+    fn test_decode_gdt_selector() {
+        serial_print!("test_decode_gdt_selector...");
+        assert_eq!(
+            decode_error_code(Exception::GeneralProtectionFault, 0x10),
+            ErrorCode::Selector {
+                external: false,
+                table: DescriptorTable::Gdt,
+                index: 2,
+            }
+        );
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_decode_idt_selector() {
+        serial_print!("test_decode_idt_selector...");
+        assert_eq!(
+            decode_error_code(Exception::SegmentNotPresent, (33 << 3) | 0b11),
+            ErrorCode::Selector {
+                external: true,
+                table: DescriptorTable::Idt,
+                index: 33,
+            }
+        );
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_decode_zero_gp_error_code() {
+        serial_print!("test_decode_zero_gp_error_code...");
+        assert_eq!(
+            decode_error_code(Exception::GeneralProtectionFault, 0),
+            ErrorCode::Raw(0)
+        );
+        serial_println!("[ok]");
+    }
+}
@@ -0,0 +1,144 @@
+use core::arch::x86_64::_rdtsc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::interrupts::PIC_1_OFFSET;
+use super::irq::{self, IRQ_LINES};
+use crate::{serial_print, serial_println};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorStats {
+    /// Number of times the vector fired, spurious interrupts included.
+    pub count: u64,
+    /// Number of spurious interrupts detected on the vector.
+    pub spurious: u64,
+    /// TSC cycles spent in handlers.
+    pub total_cycles: u64,
+    /// Longest time spent in a single handler run, in TSC cycles.
+    pub max_cycles: u64,
+}
+
+impl VectorStats {
+    pub fn average_cycles(&self) -> u64 {
+        let handled = self.count.saturating_sub(self.spurious);
+        if handled == 0 {
+            0
+        } else {
+            self.total_cycles / handled
+        }
+    }
+}
+
+/// Counters of one vector. They are updated without locking, as NMI, #DB and
+/// #BP can interrupt a handler that is in the middle of recording.
+struct AtomicStats {
+    count: AtomicU64,
+    spurious: AtomicU64,
+    total_cycles: AtomicU64,
+    max_cycles: AtomicU64,
+}
+
+impl AtomicStats {
+    fn load(&self) -> VectorStats {
+        // `count` is bumped first, so reading it last never shows more
+        // spurious interrupts than interrupts.
+        let spurious = self.spurious.load(Ordering::Relaxed);
+        VectorStats {
+            spurious,
+            total_cycles: self.total_cycles.load(Ordering::Relaxed),
+            max_cycles: self.max_cycles.load(Ordering::Relaxed),
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.spurious.store(0, Ordering::Relaxed);
+        self.total_cycles.store(0, Ordering::Relaxed);
+        self.max_cycles.store(0, Ordering::Relaxed);
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_STATS: AtomicStats = AtomicStats {
+    count: AtomicU64::new(0),
+    spurious: AtomicU64::new(0),
+    total_cycles: AtomicU64::new(0),
+    max_cycles: AtomicU64::new(0),
+};
+
+static STATS: [AtomicStats; 256] = [EMPTY_STATS; 256];
+
+/// Current value of the time stamp counter.
+#[inline]
+pub fn timestamp() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Account one handler run of `vector` that took `cycles` TSC cycles.
+pub(crate) fn record(vector: u8, cycles: u64) {
+    let stats = &STATS[usize::from(vector)];
+    stats.count.fetch_add(1, Ordering::Relaxed);
+    stats.total_cycles.fetch_add(cycles, Ordering::Relaxed);
+    let mut max = stats.max_cycles.load(Ordering::Relaxed);
+    while cycles > max {
+        match stats.max_cycles.compare_exchange_weak(max, cycles, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(current) => max = current,
+        }
+    }
+}
+
+/// Account a spurious interrupt on `vector`.
+pub(crate) fn record_spurious(vector: u8) {
+    let stats = &STATS[usize::from(vector)];
+    stats.count.fetch_add(1, Ordering::Relaxed);
+    stats.spurious.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Statistics of a single vector.
+pub fn vector_stats(vector: u8) -> VectorStats {
+    STATS[usize::from(vector)].load()
+}
+
+/// Reset all counters.
+pub fn reset() {
+    for stats in STATS.iter() {
+        stats.reset();
+    }
+}
+
+/// Print a `/proc/interrupts` style table of every vector that fired or has
+/// a handler registered.
+pub fn print_report() {
+    serial_println!(
+        "{:>4} {:>4} {:>12} {:>9} {:>12} {:>12}  {}",
+        "VEC", "IRQ", "COUNT", "SPURIOUS", "AVG CYCLES", "MAX CYCLES", "HANDLERS"
+    );
+    for (vector, stats) in STATS.iter().enumerate() {
+        let vector = vector as u8;
+        let stats = stats.load();
+        let mut handlers = irq::handler_names(vector).peekable();
+        if stats.count == 0 && handlers.peek().is_none() {
+            continue;
+        }
+
+        let irq_line = vector.wrapping_sub(PIC_1_OFFSET);
+        if irq_line < IRQ_LINES {
+            serial_print!("{:>4} {:>4}", vector, irq_line);
+        } else {
+            serial_print!("{:>4} {:>4}", vector, "-");
+        }
+        serial_print!(
+            " {:>12} {:>9} {:>12} {:>12} ",
+            stats.count, stats.spurious, stats.average_cycles(), stats.max_cycles
+        );
+        for (i, name) in handlers.enumerate() {
+            if i == 0 {
+                serial_print!(" {}", name);
+            } else {
+                serial_print!(", {}", name);
+            }
+        }
+        serial_println!();
+    }
+}
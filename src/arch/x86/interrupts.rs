@@ -2,16 +2,30 @@ use spin;
 
 use lazy_static::lazy_static;
 use pic8259_simple::ChainedPics;
-use x86_64::structures::idt::{PageFaultErrorCode, InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::idt::InterruptDescriptorTable;
 
+use super::context;
 use super::gdt;
-//use crate::interrupts::handlers;
+use super::irq::{self, IrqReturn};
+use super::isr::{self, TrapFrame};
+use super::ps2;
+use crate::serial;
+use crate::interrupts::handlers::{self, Exception, RustosExcStackframe, RustosRegisters};
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
-pub const TIMER_INTERRUPT_ID: u8 = PIC_1_OFFSET;
-pub const KBD_INTERRUPT_ID: u8 = PIC_1_OFFSET + 1;
+pub const TIMER_IRQ: u8 = 0;
+pub const KBD_IRQ: u8 = 1;
+/// Shared by COM2 and COM4.
+pub const SERIAL_IRQ_2: u8 = 3;
+/// Shared by COM1 and COM3.
+pub const SERIAL_IRQ_1: u8 = 4;
+pub const MOUSE_IRQ: u8 = 12;
+
+pub const TIMER_INTERRUPT_ID: u8 = PIC_1_OFFSET + TIMER_IRQ;
+pub const KBD_INTERRUPT_ID: u8 = PIC_1_OFFSET + KBD_IRQ;
+pub const MOUSE_INTERRUPT_ID: u8 = PIC_1_OFFSET + MOUSE_IRQ;
 
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
@@ -19,94 +33,199 @@ pub static PICS: spin::Mutex<ChainedPics> =
 lazy_static! {
     pub static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(x86_breakpoint_handler);
-	unsafe {
-        idt.double_fault.set_handler_fn(double_fault_handler)
-	   .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
-	}
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        idt.stack_segment_fault.set_handler_fn(stack_seg_interrupt_handler);
-        idt.alignment_check.set_handler_fn(align_check_interrupt_handler);
-        idt[usize::from(TIMER_INTERRUPT_ID)].set_handler_fn(timer_interrupt_handler);
-        idt[usize::from(KBD_INTERRUPT_ID)].set_handler_fn(kbd_interrupt_handler);
+        unsafe {
+            idt.divide_error.set_handler_fn(isr::stub(0));
+            idt.debug.set_handler_fn(isr::stub(1));
+            idt.non_maskable_interrupt.set_handler_fn(isr::stub(2));
+            idt.breakpoint.set_handler_fn(isr::stub(3));
+            idt.overflow.set_handler_fn(isr::stub(4));
+            idt.bound_range_exceeded.set_handler_fn(isr::stub(5));
+            idt.invalid_opcode.set_handler_fn(isr::stub(6));
+            idt.device_not_available.set_handler_fn(isr::stub(7));
+            idt[9].set_handler_fn(isr::stub(9));
+            idt.double_fault.set_handler_fn(isr::stub(8))
+                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.invalid_tss.set_handler_fn(isr::stub(10));
+            idt.segment_not_present.set_handler_fn(isr::stub(11));
+            idt.stack_segment_fault.set_handler_fn(isr::stub(12));
+            idt.general_protection_fault.set_handler_fn(isr::stub(13));
+            idt.page_fault.set_handler_fn(isr::stub(14));
+            idt.x87_floating_point.set_handler_fn(isr::stub(16));
+            idt.alignment_check.set_handler_fn(isr::stub(17));
+            idt.machine_check.set_handler_fn(isr::stub(18));
+            idt.simd_floating_point.set_handler_fn(isr::stub(19));
+            idt.virtualization.set_handler_fn(isr::stub(20));
+            idt.security_exception.set_handler_fn(isr::stub(30));
+            for &vector in &[21, 28, 29] {
+                isr::install_reserved_stub(&mut idt, vector);
+            }
+        }
+        isr::install_irq_stubs(&mut idt);
 
         idt
     };
 }
 
-//impl Into<handlers::RustosExcStackframe> for &mut InterruptStackFrame {
-//    fn into(self) -> handlers::RustosExcStackframe {
-//        handlers::RustosExcStackframe {
-//            instruction_pointer: self.instruction_pointer.as_u64() as usize,
-//            code_segment: self.code_segment as usize,
-//            cpu_flags: self.cpu_flags as usize,
-//            stack_pointer: self.stack_pointer.as_u64() as usize,
-//            stack_segment: self.stack_segment as usize,
-//            errcode: None
-//        }
-//    }
-//}
-
-extern "x86-interrupt" fn x86_breakpoint_handler(
-    stack_frame: &mut InterruptStackFrame)
-{
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+fn exception_from_vector(vector: u8) -> Exception {
+    match vector {
+        0 => Exception::DivideError,
+        1 => Exception::Debug,
+        2 => Exception::NonMaskableInterrupt,
+        3 => Exception::Breakpoint,
+        4 => Exception::Overflow,
+        5 => Exception::BoundRangeExceeded,
+        6 => Exception::InvalidOpcode,
+        7 => Exception::DeviceNotAvailable,
+        8 => Exception::DoubleFault,
+        9 => Exception::CoprocessorSegmentOverrun,
+        10 => Exception::InvalidTss,
+        11 => Exception::SegmentNotPresent,
+        12 => Exception::StackSegmentFault,
+        13 => Exception::GeneralProtectionFault,
+        14 => Exception::PageFault,
+        16 => Exception::X87FloatingPoint,
+        17 => Exception::AlignmentCheck,
+        18 => Exception::MachineCheck,
+        19 => Exception::SimdFloatingPoint,
+        20 => Exception::Virtualization,
+        21 => Exception::ControlProtection,
+        28 => Exception::HypervisorInjection,
+        29 => Exception::VmmCommunication,
+        30 => Exception::SecurityException,
+        _ => Exception::Reserved,
+    }
+}
+
+/// Whether the CPU pushes an error code for exception `vector`.
+pub fn has_error_code(vector: u8) -> bool {
+    match vector {
+        8 | 10..=14 | 17 | 21 | 29 | 30 => true,
+        _ => false,
+    }
+}
+
+impl From<&TrapFrame> for RustosExcStackframe {
+    fn from(frame: &TrapFrame) -> RustosExcStackframe {
+        let vector = frame.vector as u8;
+        let mut registers = [0; super::REGISTER_COUNT];
+        for (register, value) in registers.iter_mut().zip(frame.registers().iter()) {
+            *register = *value as usize;
+        }
+
+        RustosExcStackframe {
+            vector: vector as usize,
+            instruction_pointer: frame.rip as usize,
+            code_segment: frame.cs as usize,
+            cpu_flags: frame.rflags as usize,
+            stack_pointer: frame.rsp as usize,
+            stack_segment: frame.ss as usize,
+            errcode: if has_error_code(vector) { Some(frame.error_code) } else { None },
+            fault_address: None,
+            registers: RustosRegisters(registers),
+        }
+    }
 }
 
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: &mut InterruptStackFrame, _error_code: u64) -> !
-{
-    println!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
-    crate::arch::x86::halt_loop()
+impl TrapFrame {
+    /// Copy the state a handler may have modified back into the trap frame.
+    fn update_from(&mut self, frame: &RustosExcStackframe) {
+        self.rip = frame.instruction_pointer as u64;
+        self.rflags = frame.cpu_flags as u64;
+        self.rsp = frame.stack_pointer as u64;
+        let mut registers = [0; super::REGISTER_COUNT];
+        for (register, value) in registers.iter_mut().zip(frame.registers.0.iter()) {
+            *register = *value as u64;
+        }
+        self.set_registers(&registers);
+    }
 }
 
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: &mut InterruptStackFrame,
-    error_code: PageFaultErrorCode)
-{
-    use super::halt_loop;
+/// Called by the interrupt entry stubs for CPU exceptions.
+pub(crate) fn dispatch_exception(trap_frame: &mut TrapFrame) {
     use x86_64::registers::control::Cr2;
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    halt_loop();
+    let start = super::interrupt_stats::timestamp();
+    let vector = trap_frame.vector as u8;
+    let exception = exception_from_vector(vector);
+    let mut frame = RustosExcStackframe::from(&*trap_frame);
+    if exception == Exception::PageFault {
+        frame.fault_address = Some(Cr2::read().as_u64() as usize);
+    }
+
+    handlers::handle_exception(exception, &mut frame);
+
+    match exception {
+        Exception::DoubleFault | Exception::MachineCheck => {
+            panic!("returned from handler of unrecoverable exception {:?}", exception);
+        }
+        _ => trap_frame.update_from(&frame),
+    }
+    super::interrupt_stats::record(vector, super::interrupt_stats::timestamp() - start);
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(
-    _stack_frame: &mut InterruptStackFrame)
-{
-    unsafe { PICS.lock().notify_end_of_interrupt(TIMER_INTERRUPT_ID) }
+fn timer_interrupt_handler(_vector: u8) -> IrqReturn {
+    crate::thread::tick();
+    IrqReturn::Handled
 }
 
-extern "x86-interrupt" fn kbd_interrupt_handler(
-    _stack_frame: &mut InterruptStackFrame)
-{
-    let scancode = crate::arch::x86::read_port_u8(0x60);
-    crate::cooperative::keyboard::add_scancode(scancode);
-    unsafe { PICS.lock().notify_end_of_interrupt(KBD_INTERRUPT_ID) }
+fn kbd_interrupt_handler(_vector: u8) -> IrqReturn {
+    match ps2::read_byte(ps2::Port::First) {
+        Some(scancode) => {
+            crate::cooperative::keyboard::add_scancode(scancode);
+            IrqReturn::Handled
+        }
+        None => IrqReturn::NotHandled,
+    }
 }
 
-extern "x86-interrupt" fn align_check_interrupt_handler(
-    _stack_frame: &mut InterruptStackFrame,
-    err_code: u64)
-{
-    println!("Align check exception!");
-    crate::arch::x86::halt_loop()
+fn serial_interrupt_handler(vector: u8) -> IrqReturn {
+    if serial::handle_interrupt(vector - PIC_1_OFFSET) {
+        IrqReturn::Handled
+    } else {
+        IrqReturn::NotHandled
+    }
 }
 
-extern "x86-interrupt" fn stack_seg_interrupt_handler(
-    _stack_frame: &mut InterruptStackFrame,
-    err_code: u64)
-{
-    println!("Stack Seg check exception!");
-    crate::arch::x86::halt_loop()
+fn mouse_interrupt_handler(_vector: u8) -> IrqReturn {
+    match ps2::read_byte(ps2::Port::Second) {
+        Some(byte) => {
+            crate::cooperative::mouse::add_byte(byte);
+            IrqReturn::Handled
+        }
+        None => IrqReturn::NotHandled,
+    }
 }
 
 pub fn init_interrupts() {
     IDT.load();
     unsafe {PICS.lock().initialize() };
+    irq::mask_all();
+    if let Err(err) = ps2::init(ps2::Config::default()) {
+        crate::error!("ps2 initialization failed: {:?}", err);
+    }
+    irq::request_irq(TIMER_IRQ, "timer", timer_interrupt_handler)
+        .expect("failed to register the timer handler");
+    irq::request_irq(KBD_IRQ, "keyboard", kbd_interrupt_handler)
+        .expect("failed to register the keyboard handler");
+    if ps2::device(ps2::Port::Second).map_or(false, ps2::DeviceType::is_mouse) {
+        match ps2::mouse::enable() {
+            Ok(_) => {
+                irq::request_irq(MOUSE_IRQ, "mouse", mouse_interrupt_handler)
+                    .expect("failed to register the mouse handler");
+            }
+            Err(err) => crate::warn!("failed to enable the PS/2 mouse: {:?}", err),
+        }
+    }
+    serial::init();
+    for &line in &[SERIAL_IRQ_1, SERIAL_IRQ_2] {
+        if serial::present_ports().any(|com| com.irq_line() == line) {
+            irq::request_irq(line, "serial", serial_interrupt_handler)
+                .expect("failed to register the serial handler");
+        }
+    }
+    serial::enable_interrupts();
+    irq::request_vector(context::RESCHEDULE_VECTOR, "reschedule", context::reschedule_handler)
+        .expect("failed to register the reschedule handler");
     x86_64::instructions::interrupts::enable();
 }
 
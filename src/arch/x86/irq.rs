@@ -0,0 +1,315 @@
+use spin::RwLock;
+
+use super::interrupts::{PICS, PIC_1_OFFSET, PIC_2_OFFSET};
+use super::{read_port_u8, write_port_u8};
+
+/// Number of lines served by the chained PICs.
+pub const IRQ_LINES: u8 = 16;
+
+/// First vector that is not used by CPU exceptions or the PICs.
+pub const FIRST_FREE_VECTOR: u8 = PIC_2_OFFSET + 8;
+
+/// Maximum number of handlers that can be registered at the same time.
+///
+/// The table is statically sized so that registration and dispatch never
+/// touch the heap, which may not exist yet when the first handlers are
+/// installed.
+pub const MAX_IRQ_ACTIONS: usize = 64;
+
+const PIC_1_COMMAND: u16 = 0x20;
+const PIC_1_DATA: u16 = 0x21;
+const PIC_2_COMMAND: u16 = 0xa0;
+const PIC_2_DATA: u16 = 0xa1;
+const CASCADE_LINE: u8 = 2;
+
+const PIC_READ_ISR: u8 = 0x0b;
+const PIC_EOI: u8 = 0x20;
+
+/// Lowest priority line of each PIC, raised when an interrupt request
+/// disappears before it is acknowledged.
+const SPURIOUS_LINES: [u8; 2] = [7, 15];
+
+/// Value returned by an interrupt handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// The interrupt was raised by the handler's device.
+    Handled,
+    /// The interrupt was not for this handler, e.g. on a shared line.
+    NotHandled,
+}
+
+/// Interrupt handler, called with the vector that fired.
+///
+/// Handlers run with interrupts disabled, must not block or allocate and
+/// must not register or free handlers themselves.
+pub type IrqHandler = fn(vector: u8) -> IrqReturn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line number is not served by the PICs.
+    InvalidLine,
+    /// The vector is reserved for exceptions or the PICs.
+    InvalidVector,
+    /// The vector is already claimed.
+    VectorInUse,
+    /// All vectors are claimed.
+    NoFreeVector,
+    /// The handler table is full.
+    TableFull,
+}
+
+#[derive(Clone, Copy)]
+struct IrqAction {
+    vector: u8,
+    name: &'static str,
+    handler: IrqHandler,
+    enabled: bool,
+}
+
+static ACTIONS: RwLock<[Option<IrqAction>; MAX_IRQ_ACTIONS]> =
+    RwLock::new([None; MAX_IRQ_ACTIONS]);
+
+/// A registered interrupt handler.
+///
+/// The handler stays installed until the handle is passed to `free_irq`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IrqHandle {
+    slot: usize,
+    vector: u8,
+}
+
+impl IrqHandle {
+    pub fn vector(&self) -> u8 {
+        self.vector
+    }
+
+    /// Resume calling the handler when its vector fires.
+    pub fn enable(&self) {
+        set_enabled(self.slot, true);
+    }
+
+    /// Stop calling the handler without releasing its vector.
+    pub fn disable(&self) {
+        set_enabled(self.slot, false);
+    }
+}
+
+fn set_enabled(slot: usize, enabled: bool) {
+    super::no_interrupts(|| {
+        if let Some(action) = ACTIONS.write()[slot].as_mut() {
+            action.enabled = enabled;
+        }
+    })
+}
+
+fn insert(action: IrqAction) -> Result<IrqHandle, IrqError> {
+    let mut actions = ACTIONS.write();
+    let slot = actions.iter().position(Option::is_none).ok_or(IrqError::TableFull)?;
+    actions[slot] = Some(action);
+    Ok(IrqHandle {
+        slot,
+        vector: action.vector,
+    })
+}
+
+fn vector_in_use(actions: &[Option<IrqAction>], vector: u8) -> bool {
+    actions.iter().flatten().any(|action| action.vector == vector)
+}
+
+/// Register `handler` on PIC line `line` and unmask the line.
+///
+/// Lines can be shared: every enabled handler on the line is called each
+/// time it fires.
+pub fn request_irq(
+    line: u8,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<IrqHandle, IrqError> {
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine);
+    }
+
+    let action = IrqAction {
+        vector: PIC_1_OFFSET + line,
+        name,
+        handler,
+        enabled: true,
+    };
+    let handle = super::no_interrupts(|| insert(action))?;
+    unmask_irq(line);
+    Ok(handle)
+}
+
+/// Claim `vector` exclusively for `handler`.
+///
+/// Only vectors starting at `FIRST_FREE_VECTOR` can be claimed. They are not
+/// connected to the PICs and are meant to be raised by software.
+pub fn request_vector(
+    vector: u8,
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<IrqHandle, IrqError> {
+    if vector < FIRST_FREE_VECTOR {
+        return Err(IrqError::InvalidVector);
+    }
+
+    let action = IrqAction {
+        vector,
+        name,
+        handler,
+        enabled: true,
+    };
+    super::no_interrupts(|| {
+        if vector_in_use(&*ACTIONS.read(), vector) {
+            return Err(IrqError::VectorInUse);
+        }
+        insert(action)
+    })
+}
+
+/// Claim the first unused vector for `handler`.
+pub fn allocate_vector(
+    name: &'static str,
+    handler: IrqHandler,
+) -> Result<IrqHandle, IrqError> {
+    super::no_interrupts(|| {
+        let vector = {
+            let actions = ACTIONS.read();
+            (FIRST_FREE_VECTOR..=255)
+                .find(|&vector| !vector_in_use(&*actions, vector))
+                .ok_or(IrqError::NoFreeVector)?
+        };
+        insert(IrqAction {
+            vector,
+            name,
+            handler,
+            enabled: true,
+        })
+    })
+}
+
+/// Unregister a handler.
+///
+/// PIC lines are masked again once their last handler is freed.
+pub fn free_irq(handle: IrqHandle) {
+    let line_unused = super::no_interrupts(|| {
+        let mut actions = ACTIONS.write();
+        actions[handle.slot] = None;
+        !vector_in_use(&*actions, handle.vector)
+    });
+
+    if line_unused && handle.vector < FIRST_FREE_VECTOR {
+        mask_irq(handle.vector - PIC_1_OFFSET);
+    }
+}
+
+/// Names of the handlers registered on `vector`, in dispatch order.
+pub fn handler_names(vector: u8) -> impl Iterator<Item = &'static str> {
+    let actions = super::no_interrupts(|| *ACTIONS.read());
+    (0..MAX_IRQ_ACTIONS)
+        .filter_map(move |slot| actions[slot])
+        .filter(move |action| action.vector == vector)
+        .map(|action| action.name)
+}
+
+fn line_port(line: u8) -> (u16, u8) {
+    if line < 8 {
+        (PIC_1_DATA, line)
+    } else {
+        (PIC_2_DATA, line - 8)
+    }
+}
+
+/// Stop the PICs from raising interrupts on `line`.
+pub fn mask_irq(line: u8) {
+    assert!(line < IRQ_LINES, "invalid IRQ line {}", line);
+    let (port, bit) = line_port(line);
+    super::no_interrupts(|| {
+        write_port_u8(port, read_port_u8(port) | (1 << bit));
+    })
+}
+
+/// Allow the PICs to raise interrupts on `line`.
+///
+/// Unmasking a line of the secondary PIC also unmasks the cascade line.
+pub fn unmask_irq(line: u8) {
+    assert!(line < IRQ_LINES, "invalid IRQ line {}", line);
+    let (port, bit) = line_port(line);
+    super::no_interrupts(|| {
+        write_port_u8(port, read_port_u8(port) & !(1 << bit));
+        if line >= 8 {
+            write_port_u8(PIC_1_DATA, read_port_u8(PIC_1_DATA) & !(1 << CASCADE_LINE));
+        }
+    })
+}
+
+pub fn is_masked(line: u8) -> bool {
+    assert!(line < IRQ_LINES, "invalid IRQ line {}", line);
+    let (port, bit) = line_port(line);
+    read_port_u8(port) & (1 << bit) != 0
+}
+
+/// Mask every PIC line until a handler is registered on it.
+pub(crate) fn mask_all() {
+    write_port_u8(PIC_1_DATA, !(1 << CASCADE_LINE));
+    write_port_u8(PIC_2_DATA, 0xff);
+}
+
+/// Whether `line` is really being serviced by its PIC.
+fn in_service(line: u8) -> bool {
+    let (command, bit) = if line < 8 {
+        (PIC_1_COMMAND, line)
+    } else {
+        (PIC_2_COMMAND, line - 8)
+    };
+    write_port_u8(command, PIC_READ_ISR);
+    read_port_u8(command) & (1 << bit) != 0
+}
+
+/// Detect spurious interrupts on IRQ7 and IRQ15.
+///
+/// They must not be acknowledged on the PIC that raised them, but a spurious
+/// IRQ15 still has to be acknowledged on the primary PIC, which saw a real
+/// interrupt on the cascade line.
+fn handle_spurious(vector: u8) -> bool {
+    let line = vector.wrapping_sub(PIC_1_OFFSET);
+    if !SPURIOUS_LINES.contains(&line) || in_service(line) {
+        return false;
+    }
+
+    if line >= 8 {
+        write_port_u8(PIC_1_COMMAND, PIC_EOI);
+    }
+    super::interrupt_stats::record_spurious(vector);
+    true
+}
+
+/// Called by the interrupt entry stubs for every non-exception vector.
+///
+/// Runs every enabled handler registered on `vector` and acknowledges PIC
+/// interrupts afterwards, so handlers never send EOIs themselves. Returns
+/// `IrqReturn::Handled` if any handler claimed the interrupt.
+pub(crate) fn dispatch(vector: u8) -> IrqReturn {
+    if handle_spurious(vector) {
+        return IrqReturn::NotHandled;
+    }
+
+    let start = super::interrupt_stats::timestamp();
+    let mut ret = IrqReturn::NotHandled;
+    {
+        let actions = ACTIONS.read();
+        for action in actions.iter().flatten() {
+            if action.vector == vector && action.enabled {
+                if (action.handler)(vector) == IrqReturn::Handled {
+                    ret = IrqReturn::Handled;
+                }
+            }
+        }
+    }
+
+    if vector < FIRST_FREE_VECTOR {
+        unsafe { PICS.lock().notify_end_of_interrupt(vector) }
+    }
+    super::interrupt_stats::record(vector, super::interrupt_stats::timestamp() - start);
+    ret
+}
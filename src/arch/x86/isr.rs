@@ -0,0 +1,191 @@
+use x86_64::structures::idt::{Entry, HandlerFunc, InterruptDescriptorTable, InterruptStackFrame};
+
+/// Size in bytes of each entry stub in `rustos_isr_stubs`.
+const STUB_SIZE: u64 = 16;
+
+// Every vector gets a small entry stub that pushes a dummy error code (for
+// vectors where the CPU does not push one) and the vector number, then jumps
+// to a common routine that saves the general purpose registers and calls
+// `rustos_isr_dispatch` with a pointer to the resulting `TrapFrame`. The
+// dispatcher returns the frame to restore, which belongs to another thread
+// if the scheduler switched threads.
+//
+// The stubs are padded to `STUB_SIZE` bytes so their addresses can be computed
+// from the vector number.
+global_asm!(r#"
+.section .text
+
+.global rustos_isr_common
+rustos_isr_common:
+    push %r15
+    push %r14
+    push %r13
+    push %r12
+    push %r11
+    push %r10
+    push %r9
+    push %r8
+    push %rbp
+    push %rdi
+    push %rsi
+    push %rdx
+    push %rcx
+    push %rbx
+    push %rax
+    mov %rsp, %rdi
+    cld
+    call rustos_isr_dispatch
+    mov %rax, %rsp
+    pop %rax
+    pop %rbx
+    pop %rcx
+    pop %rdx
+    pop %rsi
+    pop %rdi
+    pop %rbp
+    pop %r8
+    pop %r9
+    pop %r10
+    pop %r11
+    pop %r12
+    pop %r13
+    pop %r14
+    pop %r15
+    add $16, %rsp
+    iretq
+
+.p2align 4
+.global rustos_isr_stubs
+rustos_isr_stubs:
+.set vector, 0
+.rept 256
+    .p2align 4
+    .if !((vector == 8) || ((vector >= 10) && (vector <= 14)) || (vector == 17) || (vector == 21) || (vector == 29) || (vector == 30))
+    pushq $0
+    .endif
+    pushq $vector
+    jmp rustos_isr_common
+    .set vector, vector + 1
+.endr
+"#);
+
+extern "C" {
+    static rustos_isr_stubs: u8;
+}
+
+/// Number of general purpose registers saved in a `TrapFrame`.
+pub const REGISTER_COUNT: usize = 15;
+
+/// Names of the general purpose registers, in `TrapFrame` order.
+pub const REGISTER_NAMES: [&str; REGISTER_COUNT] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp",
+    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+];
+
+/// Register state saved on interrupt entry.
+///
+/// The layout must match the push order of `rustos_isr_common` and the
+/// frame pushed by the CPU.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub vector: u64,
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    pub fn registers(&self) -> [u64; REGISTER_COUNT] {
+        [
+            self.rax, self.rbx, self.rcx, self.rdx, self.rsi, self.rdi, self.rbp,
+            self.r8, self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15,
+        ]
+    }
+
+    pub fn set_registers(&mut self, registers: &[u64; REGISTER_COUNT]) {
+        let [rax, rbx, rcx, rdx, rsi, rdi, rbp, r8, r9, r10, r11, r12, r13, r14, r15] =
+            *registers;
+        self.rax = rax;
+        self.rbx = rbx;
+        self.rcx = rcx;
+        self.rdx = rdx;
+        self.rsi = rsi;
+        self.rdi = rdi;
+        self.rbp = rbp;
+        self.r8 = r8;
+        self.r9 = r9;
+        self.r10 = r10;
+        self.r11 = r11;
+        self.r12 = r12;
+        self.r13 = r13;
+        self.r14 = r14;
+        self.r15 = r15;
+    }
+}
+
+/// Returns the address of the entry stub for `vector`.
+pub fn stub_address(vector: u8) -> u64 {
+    let base = unsafe { &rustos_isr_stubs as *const u8 as u64 };
+    base + u64::from(vector) * STUB_SIZE
+}
+
+/// Returns the entry stub for `vector` as an IDT handler of type `F`.
+///
+/// This function is unsafe because `F` must be one of the handler function
+/// pointer types of `InterruptDescriptorTable`, and the caller must make sure
+/// the entry it is installed in matches `vector`.
+pub unsafe fn stub<F: Copy>(vector: u8) -> F {
+    let address = stub_address(vector);
+    core::mem::transmute_copy(&address)
+}
+
+/// Points exception `vector` of `idt` to its entry stub, for the
+/// exceptions the `x86_64` crate still has as reserved entries, like #CP.
+///
+/// This function is unsafe because it writes the entry through a raw view
+/// of the table, so `vector` must be an exception vector.
+pub unsafe fn install_reserved_stub(idt: &mut InterruptDescriptorTable, vector: u8) {
+    // The table is 256 entries that only differ in their handler type.
+    let entries = &mut *(idt as *mut InterruptDescriptorTable as *mut [Entry<HandlerFunc>; 256]);
+    entries[usize::from(vector)].set_handler_fn(stub(vector));
+}
+
+/// Points every non-exception vector of `idt` to its entry stub.
+pub fn install_irq_stubs(idt: &mut InterruptDescriptorTable) {
+    for vector in super::interrupts::PIC_1_OFFSET..=255 {
+        let handler: extern "x86-interrupt" fn(&mut InterruptStackFrame) =
+            unsafe { stub(vector) };
+        idt[usize::from(vector)].set_handler_fn(handler);
+    }
+}
+
+#[no_mangle]
+extern "C" fn rustos_isr_dispatch(frame: &mut TrapFrame) -> *mut TrapFrame {
+    let vector = frame.vector as u8;
+    if vector < super::interrupts::PIC_1_OFFSET {
+        super::interrupts::dispatch_exception(frame);
+        frame
+    } else {
+        super::irq::dispatch(vector);
+        super::context::switch_point(frame)
+    }
+}
@@ -5,6 +5,11 @@ use x86_64::structures::paging::{Size4KiB, FrameAllocator};
 use x86_64::structures::paging::{UnusedPhysFrame, PhysFrame, MapperAllSizes, OffsetPageTable};
 use x86_64::{VirtAddr, PhysAddr};
 
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+static PHYSICAL_MEMORY_MAPPED: AtomicBool = AtomicBool::new(false);
+
 pub struct BootInfoFrameAllocator<I>
 where I: Iterator<Item = PhysFrame>
 {
@@ -52,10 +57,21 @@ fn frame_to_page(frame: PhysFrame, physical_memory_offset: u64) -> VirtAddr {
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoid aliasing `&mut` references (which is undefined behavior).
 pub unsafe fn init(physical_memory_offset: u64) -> impl MapperAllSizes {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset, Ordering::SeqCst);
+    PHYSICAL_MEMORY_MAPPED.store(true, Ordering::SeqCst);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, VirtAddr::new(physical_memory_offset))
 }
 
+/// Virtual address of physical address `addr` in the physical memory
+/// mapping, or `None` if `init` was not called yet.
+pub fn phys_to_virt(addr: PhysAddr) -> Option<VirtAddr> {
+    if !PHYSICAL_MEMORY_MAPPED.load(Ordering::SeqCst) {
+        return None;
+    }
+    Some(VirtAddr::new(addr.as_u64() + PHYSICAL_MEMORY_OFFSET.load(Ordering::SeqCst)))
+}
+
 /// Returns a mutable reference to the active level 4 table.
 ///
 /// This function is unsafe because the caller must guarantee that the
@@ -120,6 +136,85 @@ pub unsafe fn translate_addr_full_mapping(addr: VirtAddr, physical_memory_offset
     translate_addr_inner(addr, physical_memory_offset)
 }
 
+/// Returns whether `addr` is mapped in the active page table.
+///
+/// Unlike `translate_addr_full_mapping` this never panics, so it can be used
+/// from fault handlers. Returns `None` if `init` was not called yet, as the
+/// page tables cannot be inspected without the physical memory mapping.
+pub fn is_mapped(addr: VirtAddr) -> Option<bool> {
+    use x86_64::structures::paging::PageTableFlags;
+    use x86_64::registers::control::Cr3;
+
+    if !PHYSICAL_MEMORY_MAPPED.load(Ordering::SeqCst) {
+        return None;
+    }
+    let physical_memory_offset = PHYSICAL_MEMORY_OFFSET.load(Ordering::SeqCst);
+
+    let (level_4_table_frame, _) = Cr3::read();
+    let table_indexes = [
+        addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()
+    ];
+    let mut frame = level_4_table_frame;
+
+    for &index in &table_indexes {
+        let virt = frame.start_address().as_u64() + physical_memory_offset;
+        let table_ptr: *const PageTable = VirtAddr::new(virt).as_ptr();
+        let table = unsafe {&*table_ptr};
+
+        let entry = &table[index];
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return Some(false);
+        }
+        if flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Some(true);
+        }
+        frame = PhysFrame::containing_address(entry.addr());
+    }
+
+    Some(true)
+}
+
+/// Make the 4 KiB page containing `addr` present or not, keeping the frame it
+/// maps, so accesses to it fault until it is made present again.
+///
+/// Returns `false` if the page is not mapped by a 4 KiB page or `init` was
+/// not called yet.
+///
+/// Unsafe because code may still be using the page.
+pub unsafe fn set_page_present(addr: VirtAddr, present: bool) -> bool {
+    use x86_64::structures::paging::PageTableFlags;
+    use x86_64::registers::control::Cr3;
+
+    if !PHYSICAL_MEMORY_MAPPED.load(Ordering::SeqCst) {
+        return false;
+    }
+    let physical_memory_offset = PHYSICAL_MEMORY_OFFSET.load(Ordering::SeqCst);
+
+    let (mut frame, _) = Cr3::read();
+    for &index in &[addr.p4_index(), addr.p3_index(), addr.p2_index()] {
+        let virt = frame.start_address().as_u64() + physical_memory_offset;
+        let table = &*VirtAddr::new(virt).as_ptr::<PageTable>();
+        let flags = table[index].flags();
+        if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+            return false;
+        }
+        frame = PhysFrame::containing_address(table[index].addr());
+    }
+
+    let virt = frame.start_address().as_u64() + physical_memory_offset;
+    let table = &mut *VirtAddr::new(virt).as_mut_ptr::<PageTable>();
+    let entry = &mut table[addr.p1_index()];
+    if entry.is_unused() {
+        return false;
+    }
+    let mut flags = entry.flags();
+    flags.set(PageTableFlags::PRESENT, present);
+    entry.set_flags(flags);
+    x86_64::instructions::tlb::flush(addr);
+    true
+}
+
 /// Creates an example mapping for the given page to frame `0xb8000`.
 pub fn create_example_mapping(
     page: Page,
@@ -132,6 +227,6 @@ pub fn create_example_mapping(
     let flags = Flags::PRESENT | Flags::WRITABLE;
 
     let map_to_result = mapper.map_to(page, frame, flags, frame_allocator);
-    println!("{:?}", map_to_result);
+    crate::debug!("{:?}", map_to_result);
     map_to_result.expect("map_to failed").flush();
 }
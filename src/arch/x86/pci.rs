@@ -0,0 +1,118 @@
+//! Access to the PCI configuration space through I/O ports.
+
+use super::{no_interrupts, read_port_u32, write_port};
+
+const CONFIG_ADDRESS: u16 = 0xcf8;
+const CONFIG_DATA: u16 = 0xcfc;
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+const VENDOR_ID: u8 = 0x00;
+const DEVICE_ID: u8 = 0x02;
+const HEADER_TYPE: u8 = 0x0e;
+const BAR0: u8 = 0x10;
+
+const NO_VENDOR: u16 = 0xffff;
+const MULTI_FUNCTION: u8 = 1 << 7;
+const BAR_IO_SPACE: u32 = 1 << 0;
+const BAR_TYPE_64: u32 = 0b10 << 1;
+const BAR_MEMORY_FLAGS: u32 = 0xf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+/// Memory range decoded by a base address register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBar {
+    pub address: u64,
+    pub size: u64,
+}
+
+impl PciAddress {
+    fn config_address(self, offset: u8) -> u32 {
+        CONFIG_ENABLE
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & 0xfc)
+    }
+
+    pub fn read_u32(self, offset: u8) -> u32 {
+        no_interrupts(|| {
+            write_port(CONFIG_ADDRESS, self.config_address(offset));
+            read_port_u32(CONFIG_DATA)
+        })
+    }
+
+    pub fn write_u32(self, offset: u8, value: u32) {
+        no_interrupts(|| {
+            write_port(CONFIG_ADDRESS, self.config_address(offset));
+            write_port(CONFIG_DATA, value);
+        })
+    }
+
+    pub fn read_u16(self, offset: u8) -> u16 {
+        (self.read_u32(offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn read_u8(self, offset: u8) -> u8 {
+        (self.read_u32(offset) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn vendor_id(self) -> u16 {
+        self.read_u16(VENDOR_ID)
+    }
+
+    pub fn device_id(self) -> u16 {
+        self.read_u16(DEVICE_ID)
+    }
+
+    /// The memory range of base address register `index`, or `None` for
+    /// I/O ports and unused registers.
+    pub fn memory_bar(self, index: u8) -> Option<MemoryBar> {
+        let offset = BAR0 + index * 4;
+        let low = self.read_u32(offset);
+        if low & BAR_IO_SPACE != 0 {
+            return None;
+        }
+        let high = if low & BAR_TYPE_64 != 0 { self.read_u32(offset + 4) } else { 0 };
+
+        // The register reads back which address bits are writable.
+        self.write_u32(offset, !0);
+        let mask = self.read_u32(offset) & !BAR_MEMORY_FLAGS;
+        self.write_u32(offset, low);
+        if mask == 0 {
+            return None;
+        }
+
+        Some(MemoryBar {
+            address: u64::from(high) << 32 | u64::from(low & !BAR_MEMORY_FLAGS),
+            size: u64::from(!mask) + 1,
+        })
+    }
+}
+
+/// Every function present on the bus, by brute force.
+pub fn devices() -> impl Iterator<Item = PciAddress> {
+    (0..=255u8)
+        .flat_map(|bus| (0..32u8).map(move |device| (bus, device)))
+        .flat_map(|(bus, device)| {
+            let first = PciAddress { bus, device, function: 0 };
+            let functions = if first.vendor_id() == NO_VENDOR {
+                0
+            } else if first.read_u8(HEADER_TYPE) & MULTI_FUNCTION != 0 {
+                8
+            } else {
+                1
+            };
+            (0..functions).map(move |function| PciAddress { bus, device, function })
+        })
+        .filter(|address| address.vendor_id() != NO_VENDOR)
+}
+
+pub fn find_device(vendor_id: u16, device_id: u16) -> Option<PciAddress> {
+    devices().find(|address| address.vendor_id() == vendor_id && address.device_id() == device_id)
+}
@@ -0,0 +1,498 @@
+//! Driver for the 8042 PS/2 controller.
+//!
+//! Devices are talked to by polling with their interrupt line masked, so
+//! commands work the same before and after interrupts are enabled.
+
+pub mod keyboard;
+pub mod mouse;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use super::interrupts::{KBD_IRQ, MOUSE_IRQ};
+use super::{io_wait, irq, read_port_u8, write_port_u8};
+use crate::keyboard::ScancodeSet;
+
+const DATA_PORT: u16 = 0x60;
+/// Status register on reads, command register on writes.
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// The byte in the output buffer comes from the second port.
+const STATUS_SECOND_PORT_DATA: u8 = 1 << 5;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_SECOND: u8 = 0xa7;
+const CMD_ENABLE_SECOND: u8 = 0xa8;
+const CMD_TEST_SECOND: u8 = 0xa9;
+const CMD_SELF_TEST: u8 = 0xaa;
+const CMD_TEST_FIRST: u8 = 0xab;
+const CMD_DISABLE_FIRST: u8 = 0xad;
+const CMD_ENABLE_FIRST: u8 = 0xae;
+const CMD_WRITE_SECOND: u8 = 0xd4;
+
+const CONFIG_FIRST_IRQ: u8 = 1 << 0;
+const CONFIG_SECOND_IRQ: u8 = 1 << 1;
+const CONFIG_SECOND_CLOCK_DISABLED: u8 = 1 << 5;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+const DEVICE_IDENTIFY: u8 = 0xf2;
+const DEVICE_ENABLE_SCANNING: u8 = 0xf4;
+const DEVICE_DISABLE_SCANNING: u8 = 0xf5;
+const DEVICE_RESET: u8 = 0xff;
+const DEVICE_RESET_PASSED: u8 = 0xaa;
+
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+
+/// Times a byte is sent again when the device asks for it.
+const MAX_RESENDS: usize = 3;
+
+/// Status register polls before giving up, `io_wait` makes each take about
+/// a microsecond.
+const TIMEOUT: u32 = 100_000;
+/// Devices may take up to a second to run their self-test after a reset.
+const RESET_TIMEOUT: u32 = 10 * TIMEOUT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// Where keyboards are usually plugged in.
+    First,
+    /// Where mice are usually plugged in.
+    Second,
+}
+
+impl Port {
+    /// PIC line the port raises interrupts on.
+    pub fn irq_line(self) -> u8 {
+        match self {
+            Port::First => KBD_IRQ,
+            Port::Second => MOUSE_IRQ,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Port::First => 0,
+            Port::Second => 1,
+        }
+    }
+}
+
+/// Device type reported by the identify command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    /// Old AT keyboards do not answer identify.
+    AtKeyboard,
+    Mf2Keyboard,
+    /// MF2 keyboard behind a translating controller.
+    Mf2KeyboardTranslated,
+    StandardMouse,
+    ScrollMouse,
+    FiveButtonMouse,
+    Unknown(u8, u8),
+}
+
+impl DeviceType {
+    fn from_id(id: &[u8]) -> DeviceType {
+        match *id {
+            [] => DeviceType::AtKeyboard,
+            [0x00] => DeviceType::StandardMouse,
+            [0x03] => DeviceType::ScrollMouse,
+            [0x04] => DeviceType::FiveButtonMouse,
+            [0xab, 0x83] => DeviceType::Mf2Keyboard,
+            [0xab, 0x41] | [0xab, 0xc1] => DeviceType::Mf2KeyboardTranslated,
+            [first] => DeviceType::Unknown(first, 0),
+            [first, second, ..] => DeviceType::Unknown(first, second),
+        }
+    }
+
+    pub fn is_keyboard(self) -> bool {
+        match self {
+            DeviceType::AtKeyboard
+            | DeviceType::Mf2Keyboard
+            | DeviceType::Mf2KeyboardTranslated => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_mouse(self) -> bool {
+        match self {
+            DeviceType::StandardMouse | DeviceType::ScrollMouse | DeviceType::FiveButtonMouse => {
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// `init` has not run or failed.
+    NotInitialized,
+    /// No working device on the port.
+    NoDevice,
+    /// The controller or device did not answer in time.
+    Timeout,
+    /// The controller self-test returned this instead of 0x55.
+    SelfTestFailed(u8),
+    /// The interface test of a port returned this error code.
+    PortTestFailed(Port, u8),
+    /// The device kept asking for bytes to be sent again.
+    TooManyResends,
+    UnexpectedResponse(u8),
+    InvalidArgument,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Have the controller translate set 2 scancodes from the keyboard into
+    /// set 1.
+    pub translation: bool,
+    /// Scancode set the keyboard is switched to.
+    pub scancode_set: ScancodeSet,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            translation: true,
+            scancode_set: ScancodeSet::Set2,
+        }
+    }
+}
+
+struct Controller {
+    initialized: bool,
+    translation: bool,
+    devices: [Option<DeviceType>; 2],
+    keyboard_set: ScancodeSet,
+}
+
+static CONTROLLER: Mutex<Controller> = Mutex::new(Controller {
+    initialized: false,
+    translation: false,
+    devices: [None; 2],
+    keyboard_set: ScancodeSet::Set2,
+});
+
+// Read by interrupt handlers, which must not take the controller lock.
+static DUAL_CHANNEL: AtomicBool = AtomicBool::new(false);
+
+fn status() -> u8 {
+    read_port_u8(STATUS_PORT)
+}
+
+fn wait_input_empty() -> Result<(), Ps2Error> {
+    for _ in 0..TIMEOUT {
+        if status() & STATUS_INPUT_FULL == 0 {
+            return Ok(());
+        }
+        io_wait();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn write_command(command: u8) -> Result<(), Ps2Error> {
+    wait_input_empty()?;
+    write_port_u8(COMMAND_PORT, command);
+    Ok(())
+}
+
+fn write_data(byte: u8) -> Result<(), Ps2Error> {
+    wait_input_empty()?;
+    write_port_u8(DATA_PORT, byte);
+    Ok(())
+}
+
+/// Read an answer of the controller itself.
+fn read_data() -> Result<u8, Ps2Error> {
+    for _ in 0..TIMEOUT {
+        if status() & STATUS_OUTPUT_FULL != 0 {
+            return Ok(read_port_u8(DATA_PORT));
+        }
+        io_wait();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn flush_output() {
+    while status() & STATUS_OUTPUT_FULL != 0 {
+        read_port_u8(DATA_PORT);
+    }
+}
+
+fn source_port(status: u8) -> Port {
+    if DUAL_CHANNEL.load(Ordering::Relaxed) && status & STATUS_SECOND_PORT_DATA != 0 {
+        Port::Second
+    } else {
+        Port::First
+    }
+}
+
+/// Read the byte waiting from `port`, if any.
+///
+/// Meant for interrupt handlers: interrupts can be latched while the
+/// output buffer is empty or holds a byte of the other port.
+pub fn read_byte(port: Port) -> Option<u8> {
+    let status = status();
+    if status & STATUS_OUTPUT_FULL != 0 && source_port(status) == port {
+        Some(read_port_u8(DATA_PORT))
+    } else {
+        None
+    }
+}
+
+impl Controller {
+    /// Hand a byte that arrived while polling for something else to the
+    /// driver of its port.
+    fn forward(&self, port: Port, byte: u8) {
+        if !self.initialized {
+            return;
+        }
+        match port {
+            Port::First => crate::cooperative::keyboard::add_scancode(byte),
+            Port::Second => crate::cooperative::mouse::add_byte(byte),
+        }
+    }
+
+    fn read_device(&self, port: Port, timeout: u32) -> Result<u8, Ps2Error> {
+        for _ in 0..timeout {
+            let status = status();
+            if status & STATUS_OUTPUT_FULL != 0 {
+                let byte = read_port_u8(DATA_PORT);
+                let source = source_port(status);
+                if source == port {
+                    return Ok(byte);
+                }
+                self.forward(source, byte);
+            }
+            io_wait();
+        }
+        Err(Ps2Error::Timeout)
+    }
+
+    /// Send `byte` to the device on `port` and wait for it to be acknowledged.
+    fn send(&self, port: Port, byte: u8) -> Result<(), Ps2Error> {
+        for _ in 0..=MAX_RESENDS {
+            if port == Port::Second {
+                write_command(CMD_WRITE_SECOND)?;
+            }
+            write_data(byte)?;
+            loop {
+                match self.read_device(port, TIMEOUT)? {
+                    ACK => return Ok(()),
+                    RESEND => break,
+                    // Sent by the device before it saw the command.
+                    other => self.forward(port, other),
+                }
+            }
+        }
+        Err(Ps2Error::TooManyResends)
+    }
+
+    fn read_config(&self) -> Result<u8, Ps2Error> {
+        write_command(CMD_READ_CONFIG)?;
+        read_data()
+    }
+
+    fn write_config(&self, config: u8) -> Result<(), Ps2Error> {
+        write_command(CMD_WRITE_CONFIG)?;
+        write_data(config)
+    }
+
+    fn init(&mut self, config: Config) -> Result<(), Ps2Error> {
+        // Translating set 1 scancodes again would garble them.
+        if config.translation && config.scancode_set != ScancodeSet::Set2 {
+            return Err(Ps2Error::InvalidArgument);
+        }
+        self.initialized = false;
+        self.devices = [None; 2];
+        DUAL_CHANNEL.store(false, Ordering::Relaxed);
+
+        write_command(CMD_DISABLE_FIRST)?;
+        write_command(CMD_DISABLE_SECOND)?;
+        flush_output();
+
+        let mut controller_config = self.read_config()?;
+        controller_config &= !(CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ | CONFIG_TRANSLATION);
+        self.write_config(controller_config)?;
+
+        write_command(CMD_SELF_TEST)?;
+        match read_data()? {
+            SELF_TEST_PASSED => {}
+            result => return Err(Ps2Error::SelfTestFailed(result)),
+        }
+        // Some controllers reset themselves during the self-test.
+        self.write_config(controller_config)?;
+
+        // The second clock only turns on if the second port exists.
+        let mut dual_channel = false;
+        if controller_config & CONFIG_SECOND_CLOCK_DISABLED != 0 {
+            write_command(CMD_ENABLE_SECOND)?;
+            dual_channel = self.read_config()? & CONFIG_SECOND_CLOCK_DISABLED == 0;
+            write_command(CMD_DISABLE_SECOND)?;
+        }
+
+        let mut working = [false; 2];
+        for &(port, test) in &[(Port::First, CMD_TEST_FIRST), (Port::Second, CMD_TEST_SECOND)] {
+            if port == Port::Second && !dual_channel {
+                continue;
+            }
+            write_command(test)?;
+            match read_data()? {
+                PORT_TEST_PASSED => working[port.index()] = true,
+                result => crate::warn!("{:?}", Ps2Error::PortTestFailed(port, result)),
+            }
+        }
+        if !working.iter().any(|&working| working) {
+            return Err(Ps2Error::NoDevice);
+        }
+
+        if working[0] {
+            write_command(CMD_ENABLE_FIRST)?;
+        }
+        if working[1] {
+            write_command(CMD_ENABLE_SECOND)?;
+        }
+        DUAL_CHANNEL.store(dual_channel, Ordering::Relaxed);
+
+        for &port in &[Port::First, Port::Second] {
+            if working[port.index()] {
+                self.devices[port.index()] = self.init_device(port).ok();
+            }
+        }
+
+        if working[0] {
+            controller_config |= CONFIG_FIRST_IRQ;
+        }
+        if working[1] {
+            controller_config |= CONFIG_SECOND_IRQ;
+        }
+        if config.translation {
+            controller_config |= CONFIG_TRANSLATION;
+        }
+        self.write_config(controller_config)?;
+        self.translation = config.translation;
+        self.initialized = true;
+
+        if self.devices[0].map_or(false, DeviceType::is_keyboard) {
+            // Old keyboards only speak set 2 and reject the command.
+            if self.set_scancode_set(config.scancode_set).is_err() {
+                self.keyboard_set = ScancodeSet::Set2;
+            }
+            self.send(Port::First, DEVICE_ENABLE_SCANNING)?;
+        }
+        Ok(())
+    }
+
+    /// Reset and identify the device on `port`, leaving scanning disabled.
+    fn init_device(&self, port: Port) -> Result<DeviceType, Ps2Error> {
+        self.send(port, DEVICE_RESET)?;
+        match self.read_device(port, RESET_TIMEOUT)? {
+            DEVICE_RESET_PASSED => {}
+            result => return Err(Ps2Error::UnexpectedResponse(result)),
+        }
+        // Mice follow up with their ID.
+        let _ = self.read_device(port, TIMEOUT);
+
+        self.send(port, DEVICE_DISABLE_SCANNING)?;
+        self.send(port, DEVICE_IDENTIFY)?;
+        let mut id = [0; 2];
+        let mut len = 0;
+        while len < id.len() {
+            match self.read_device(port, TIMEOUT) {
+                Ok(byte) => id[len] = byte,
+                Err(_) => break,
+            }
+            len += 1;
+        }
+        Ok(DeviceType::from_id(&id[..len]))
+    }
+
+    fn set_scancode_set(&mut self, set: ScancodeSet) -> Result<(), Ps2Error> {
+        self.send(Port::First, keyboard::SCANCODE_SET)?;
+        self.send(Port::First, keyboard::scancode_set_number(set))?;
+        self.keyboard_set = set;
+        Ok(())
+    }
+
+    fn check_port(&self, port: Port) -> Result<(), Ps2Error> {
+        if !self.initialized {
+            Err(Ps2Error::NotInitialized)
+        } else if self.devices[port.index()].is_none() {
+            Err(Ps2Error::NoDevice)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Run `f` with the controller locked and the interrupt line of `port`
+/// masked, so answers of the device are not taken by its handler.
+fn with_port<F, R>(port: Port, f: F) -> Result<R, Ps2Error>
+where
+    F: FnOnce(&mut Controller) -> Result<R, Ps2Error>,
+{
+    let mut controller = CONTROLLER.lock();
+    controller.check_port(port)?;
+
+    let line = port.irq_line();
+    let was_masked = irq::is_masked(line);
+    irq::mask_irq(line);
+    let result = f(&mut controller);
+    if !was_masked {
+        irq::unmask_irq(line);
+    }
+    result
+}
+
+/// Test the controller and reset and identify the attached devices.
+///
+/// Must run before the interrupt lines of the ports are unmasked.
+pub fn init(config: Config) -> Result<(), Ps2Error> {
+    CONTROLLER.lock().init(config)
+}
+
+pub fn is_initialized() -> bool {
+    CONTROLLER.lock().initialized
+}
+
+/// Whether the controller has a second port.
+pub fn is_dual_channel() -> bool {
+    DUAL_CHANNEL.load(Ordering::Relaxed)
+}
+
+/// Whether the controller translates keyboard scancodes to set 1.
+pub fn translation() -> bool {
+    CONTROLLER.lock().translation
+}
+
+/// The device found on `port` by `init`.
+pub fn device(port: Port) -> Option<DeviceType> {
+    CONTROLLER.lock().devices[port.index()]
+}
+
+/// Send the command `bytes` to the device on `port`, then read
+/// `response.len()` bytes of answer.
+///
+/// Every byte sent must be acknowledged, and is sent again if the device
+/// asks for it.
+pub fn command(port: Port, bytes: &[u8], response: &mut [u8]) -> Result<(), Ps2Error> {
+    with_port(port, |controller| {
+        for &byte in bytes {
+            controller.send(port, byte)?;
+        }
+        for byte in response.iter_mut() {
+            *byte = controller.read_device(port, TIMEOUT)?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,145 @@
+//! Commands understood by PS/2 keyboards.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::{with_port, Port, Ps2Error, CONTROLLER};
+use crate::keyboard::{Modifiers, ScancodeSet};
+use crate::thread::{self, ThreadError};
+
+const SET_LEDS: u8 = 0xed;
+pub(super) const SCANCODE_SET: u8 = 0xf0;
+const SET_TYPEMATIC: u8 = 0xf3;
+
+/// Argument of `SCANCODE_SET` asking for the current set.
+const GET_SCANCODE_SET: u8 = 0;
+
+/// Highest typematic rate code, the slowest repeat rate.
+pub const MAX_REPEAT_RATE: u8 = 0x1f;
+
+/// Timer ticks between checks for LED requests.
+const LED_POLL_TICKS: u64 = 1;
+
+/// `LED_REQUEST` when there is nothing to send.
+const NO_LED_REQUEST: u8 = 0xff;
+
+/// LED bits asked for by `request_leds`.
+static LED_REQUEST: AtomicU8 = AtomicU8::new(NO_LED_REQUEST);
+
+pub(super) fn scancode_set_number(set: ScancodeSet) -> u8 {
+    match set {
+        ScancodeSet::Set1 => 1,
+        ScancodeSet::Set2 => 2,
+    }
+}
+
+/// Keyboard LEDs that are lit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl Leds {
+    /// LEDs showing the lock state of `modifiers`.
+    pub fn from_modifiers(modifiers: &Modifiers) -> Leds {
+        Leds {
+            scroll_lock: modifiers.scroll_lock,
+            num_lock: modifiers.num_lock,
+            caps_lock: modifiers.caps_lock,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        (self.scroll_lock as u8) | (self.num_lock as u8) << 1 | (self.caps_lock as u8) << 2
+    }
+}
+
+/// Delay before a held key starts repeating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatDelay {
+    Ms250,
+    Ms500,
+    Ms750,
+    Ms1000,
+}
+
+/// Light `leds`, waiting for the keyboard to acknowledge.
+///
+/// This can take long enough to stall an executor, tasks should use
+/// `request_leds`.
+pub fn set_leds(leds: Leds) -> Result<(), Ps2Error> {
+    super::command(Port::First, &[SET_LEDS, leds.bits()], &mut [])
+}
+
+/// Have the thread started by `spawn_led_thread` light `leds`, without
+/// waiting. Only the latest request is sent.
+pub fn request_leds(leds: Leds) {
+    LED_REQUEST.store(leds.bits(), Ordering::SeqCst);
+}
+
+/// Start the thread sending the LEDs of `request_leds` to the keyboard.
+pub fn spawn_led_thread() -> Result<(), ThreadError> {
+    // The handle is dropped, nobody joins the thread.
+    thread::spawn("ps2-leds", || loop {
+        let bits = LED_REQUEST.swap(NO_LED_REQUEST, Ordering::SeqCst);
+        if bits != NO_LED_REQUEST {
+            if let Err(err) = super::command(Port::First, &[SET_LEDS, bits], &mut []) {
+                crate::warn!("failed to set the keyboard LEDs: {:?}", err);
+            }
+        }
+        thread::sleep(LED_POLL_TICKS);
+    })?;
+    Ok(())
+}
+
+/// Set how held keys repeat.
+///
+/// `rate` goes from 0 for 30 repeats per second to `MAX_REPEAT_RATE` for 2.
+pub fn set_typematic(delay: RepeatDelay, rate: u8) -> Result<(), Ps2Error> {
+    if rate > MAX_REPEAT_RATE {
+        return Err(Ps2Error::InvalidArgument);
+    }
+    let delay = match delay {
+        RepeatDelay::Ms250 => 0,
+        RepeatDelay::Ms500 => 1,
+        RepeatDelay::Ms750 => 2,
+        RepeatDelay::Ms1000 => 3,
+    };
+    super::command(Port::First, &[SET_TYPEMATIC, delay << 5 | rate], &mut [])
+}
+
+/// Switch the keyboard to `set`.
+///
+/// Set 1 is refused while the controller translates, see `Config`.
+pub fn set_scancode_set(set: ScancodeSet) -> Result<(), Ps2Error> {
+    with_port(Port::First, |controller| {
+        if controller.translation && set != ScancodeSet::Set2 {
+            return Err(Ps2Error::InvalidArgument);
+        }
+        controller.set_scancode_set(set)
+    })
+}
+
+/// Ask the keyboard which scancode set it sends.
+pub fn scancode_set() -> Result<ScancodeSet, Ps2Error> {
+    let mut response = [0];
+    super::command(Port::First, &[SCANCODE_SET, GET_SCANCODE_SET], &mut response)?;
+    // The answer is translated like scancodes are.
+    match response[0] {
+        1 | 0x43 => Ok(ScancodeSet::Set1),
+        2 | 0x41 => Ok(ScancodeSet::Set2),
+        other => Err(Ps2Error::UnexpectedResponse(other)),
+    }
+}
+
+/// Scancode set of the bytes read from the keyboard port, which is set 1
+/// whenever the controller translates.
+pub fn received_scancode_set() -> ScancodeSet {
+    let controller = CONTROLLER.lock();
+    if controller.translation {
+        ScancodeSet::Set1
+    } else {
+        controller.keyboard_set
+    }
+}
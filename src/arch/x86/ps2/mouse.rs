@@ -0,0 +1,88 @@
+//! Commands understood by PS/2 mice.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::{Port, Ps2Error};
+use crate::mouse::PacketFormat;
+
+const GET_ID: u8 = 0xf2;
+const SET_SAMPLE_RATE: u8 = 0xf3;
+const ENABLE_REPORTING: u8 = 0xf4;
+const DISABLE_REPORTING: u8 = 0xf5;
+const SET_DEFAULTS: u8 = 0xf6;
+
+const WHEEL_ID: u8 = 3;
+const FIVE_BUTTON_ID: u8 = 4;
+
+/// Sample rates that unlock the wheel, then the extra buttons.
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+
+/// Sample rates accepted by the mouse, in reports per second.
+pub const SAMPLE_RATES: [u8; 7] = [10, 20, 40, 60, 80, 100, 200];
+pub const DEFAULT_SAMPLE_RATE: u8 = 100;
+
+const STANDARD: u8 = 0;
+const WHEEL: u8 = 1;
+const FIVE_BUTTON: u8 = 2;
+
+// Read when creating streams, possibly with the controller locked.
+static FORMAT: AtomicU8 = AtomicU8::new(STANDARD);
+
+/// Format of the packets the mouse sends.
+pub fn packet_format() -> PacketFormat {
+    match FORMAT.load(Ordering::Relaxed) {
+        WHEEL => PacketFormat::Wheel,
+        FIVE_BUTTON => PacketFormat::FiveButton,
+        _ => PacketFormat::Standard,
+    }
+}
+
+fn id() -> Result<u8, Ps2Error> {
+    let mut id = [0];
+    super::command(Port::Second, &[GET_ID], &mut id)?;
+    Ok(id[0])
+}
+
+pub fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    if !SAMPLE_RATES.contains(&rate) {
+        return Err(Ps2Error::InvalidArgument);
+    }
+    super::command(Port::Second, &[SET_SAMPLE_RATE, rate], &mut [])
+}
+
+fn knock(sequence: &[u8]) -> Result<u8, Ps2Error> {
+    for &rate in sequence {
+        set_sample_rate(rate)?;
+    }
+    id()
+}
+
+/// Switch on the wheel and extra buttons the mouse supports, then have it
+/// start sending packets.
+pub fn enable() -> Result<PacketFormat, Ps2Error> {
+    super::command(Port::Second, &[DISABLE_REPORTING, SET_DEFAULTS], &mut [])?;
+
+    let mut format = PacketFormat::Standard;
+    if knock(&WHEEL_SEQUENCE)? == WHEEL_ID {
+        format = PacketFormat::Wheel;
+        if knock(&FIVE_BUTTON_SEQUENCE)? == FIVE_BUTTON_ID {
+            format = PacketFormat::FiveButton;
+        }
+    }
+    set_sample_rate(DEFAULT_SAMPLE_RATE)?;
+
+    let format_id = match format {
+        PacketFormat::Standard => STANDARD,
+        PacketFormat::Wheel => WHEEL,
+        PacketFormat::FiveButton => FIVE_BUTTON,
+    };
+    FORMAT.store(format_id, Ordering::Relaxed);
+    super::command(Port::Second, &[ENABLE_REPORTING], &mut [])?;
+    Ok(format)
+}
+
+/// Stop the mouse from sending packets.
+pub fn disable() -> Result<(), Ps2Error> {
+    super::command(Port::Second, &[DISABLE_REPORTING], &mut [])
+}
@@ -0,0 +1,105 @@
+use core::fmt;
+
+use crate::arch::backtrace::{frame_pointer, walk_frames, FRAME_POINTER_REGISTER};
+use crate::interrupts::handlers::RustosExcStackframe;
+use crate::symbols;
+
+/// Maximum number of frames recorded in a `Backtrace`.
+pub const MAX_FRAMES: usize = 32;
+
+/// Return addresses of a call chain, innermost first.
+///
+/// Capturing does not allocate, so backtraces can be taken in panic and
+/// fault handlers.
+pub struct Backtrace {
+    frames: [usize; MAX_FRAMES],
+    len: usize,
+    /// Whether the first frame is the exact faulting instruction rather than
+    /// a return address.
+    exact_first: bool,
+}
+
+impl Backtrace {
+    /// Capture the call chain of the caller.
+    #[inline(always)]
+    pub fn capture() -> Backtrace {
+        Backtrace::from_frame_pointer(frame_pointer())
+    }
+
+    pub fn from_frame_pointer(frame_pointer: usize) -> Backtrace {
+        let mut backtrace = Backtrace {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+            exact_first: false,
+        };
+        backtrace.walk(frame_pointer);
+        backtrace
+    }
+
+    /// Call chain of the code that raised an exception.
+    pub fn from_exception(frame: &RustosExcStackframe) -> Backtrace {
+        let mut backtrace = Backtrace {
+            frames: [0; MAX_FRAMES],
+            len: 1,
+            exact_first: true,
+        };
+        backtrace.frames[0] = frame.instruction_pointer;
+        if let Some(frame_pointer) = frame.registers.get(FRAME_POINTER_REGISTER) {
+            backtrace.walk(frame_pointer);
+        }
+        backtrace
+    }
+
+    fn walk(&mut self, frame_pointer: usize) {
+        walk_frames(frame_pointer, |return_address| {
+            self.frames[self.len] = return_address;
+            self.len += 1;
+            self.len < MAX_FRAMES
+        });
+    }
+
+    pub fn frames(&self) -> &[usize] {
+        &self.frames[..self.len]
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Backtrace:")?;
+        for (i, &address) in self.frames().iter().enumerate() {
+            write!(f, "  #{:<2} {:#018x}", i, address)?;
+
+            // Return addresses point past the call, which may already be
+            // the start of the next function.
+            let lookup_address = if i == 0 && self.exact_first { address } else { address - 1 };
+            match symbols::lookup(lookup_address) {
+                Some((symbol, offset)) => {
+                    let offset = offset + address - lookup_address;
+                    writeln!(f, " {}+{:#x}", symbol.name, offset)?
+                }
+                None => writeln!(f, " <unknown>")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    #[inline(never)]
+    fn nested_capture() -> Backtrace {
+        Backtrace::capture()
+    }
+
+    #[test_case]
+    fn test_capture_walks_callers() {
+        serial_print!("test_capture_walks_callers...");
+        let backtrace = nested_capture();
+        assert!(backtrace.frames().len() >= 2);
+        assert!(backtrace.frames().iter().all(|&address| address != 0));
+        serial_println!("[ok]");
+    }
+}
@@ -0,0 +1,46 @@
+//! Build-time kernel command line, whitespace separated `key=value` options.
+//!
+//! The bootloader cannot pass a command line at boot, so it is taken from
+//! the `RUSTOS_CMDLINE` environment variable when the kernel is built, and
+//! changing it means rebuilding, like `RUSTOS_CMDLINE=loglevel=debug cargo xrun`.
+
+/// The command line the kernel was built with.
+pub fn get() -> &'static str {
+    option_env!("RUSTOS_CMDLINE").unwrap_or("")
+}
+
+/// Value of option `key`, the last one if given more than once.
+pub fn value(key: &str) -> Option<&'static str> {
+    find(get(), key)
+}
+
+fn find<'a>(cmdline: &'a str, key: &str) -> Option<&'a str> {
+    cmdline
+        .split_whitespace()
+        .filter_map(|option| {
+            let mut parts = option.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), value) if name == key => Some(value.unwrap_or("")),
+                _ => None,
+            }
+        })
+        .last()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    #[test_case]
+    fn test_find_options() {
+        serial_print!("test_find_options...");
+        let cmdline = "quiet loglevel=info,ps2=trace  serial=off loglevel=debug";
+        assert_eq!(find(cmdline, "loglevel"), Some("debug"));
+        assert_eq!(find(cmdline, "serial"), Some("off"));
+        assert_eq!(find(cmdline, "quiet"), Some(""));
+        assert_eq!(find(cmdline, "log"), None);
+        assert_eq!(find("", "loglevel"), None);
+        serial_println!("[ok]");
+    }
+}
@@ -0,0 +1,222 @@
+//! Kernel console that `print!` writes to.
+//!
+//! Output goes to every enabled sink: the VGA kernel terminal, COM1, the
+//! framebuffer console and an in-memory ring by default. More can be
+//! registered, and any can be toggled at runtime.
+
+pub mod ring;
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::{Mutex, RwLock};
+
+use crate::arch::{self, no_interrupts};
+use crate::serial::{self, ComPort};
+
+pub use ring::RingSink;
+
+/// Maximum number of sinks, the default ones included.
+pub const MAX_SINKS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleError {
+    TooManySinks,
+    AlreadyRegistered,
+    NoSuchSink,
+}
+
+/// Destination of console output.
+///
+/// Called from any context, interrupt handlers included, with interrupts
+/// enabled or not. It must not block on locks held with interrupts enabled
+/// or allocate.
+pub trait Sink: Sync {
+    fn write_str(&self, s: &str);
+}
+
+/// The VGA kernel terminal, `vga_buffer::WRITER`.
+pub struct VgaSink;
+
+impl Sink for VgaSink {
+    fn write_str(&self, s: &str) {
+        no_interrupts(|| crate::vga_buffer::WRITER.lock().write_string(s));
+    }
+}
+
+/// A serial port.
+pub struct SerialSink(pub ComPort);
+
+impl Sink for SerialSink {
+    fn write_str(&self, s: &str) {
+        serial::write(self.0, s.as_bytes());
+    }
+}
+
+/// The framebuffer text console, which drops output until a mode is set.
+pub struct FramebufferSink;
+
+impl Sink for FramebufferSink {
+    fn write_str(&self, s: &str) {
+        crate::framebuffer::console::with_console(|console| console.write_string(s));
+    }
+}
+
+static VGA: VgaSink = VgaSink;
+static SERIAL: SerialSink = SerialSink(ComPort::Com1);
+static FRAMEBUFFER: FramebufferSink = FramebufferSink;
+/// Recent console output, registered as "ring".
+pub static RING: RingSink = RingSink::new();
+
+struct Registration {
+    name: &'static str,
+    sink: &'static dyn Sink,
+    enabled: AtomicBool,
+}
+
+// Only locked with interrupts disabled, so printing from a handler never
+// finds it locked by the code it interrupted.
+static SINKS: RwLock<[Option<Registration>; MAX_SINKS]> = RwLock::new([
+    Some(Registration {
+        name: "vga",
+        sink: &VGA,
+        enabled: AtomicBool::new(true),
+    }),
+    Some(Registration {
+        name: "serial",
+        sink: &SERIAL,
+        enabled: AtomicBool::new(true),
+    }),
+    Some(Registration {
+        name: "framebuffer",
+        sink: &FRAMEBUFFER,
+        enabled: AtomicBool::new(true),
+    }),
+    Some(Registration {
+        name: "ring",
+        sink: &RING,
+        enabled: AtomicBool::new(true),
+    }),
+    None,
+    None,
+    None,
+    None,
+]);
+
+/// Add a sink under a unique `name`.
+pub fn register(name: &'static str, sink: &'static dyn Sink, enabled: bool) -> Result<(), ConsoleError> {
+    no_interrupts(|| {
+        let mut sinks = SINKS.write();
+        if sinks.iter().flatten().any(|registration| registration.name == name) {
+            return Err(ConsoleError::AlreadyRegistered);
+        }
+        let slot = sinks
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(ConsoleError::TooManySinks)?;
+        *slot = Some(Registration {
+            name,
+            sink,
+            enabled: AtomicBool::new(enabled),
+        });
+        Ok(())
+    })
+}
+
+pub fn unregister(name: &str) -> Result<(), ConsoleError> {
+    no_interrupts(|| {
+        let mut sinks = SINKS.write();
+        let slot = sinks
+            .iter_mut()
+            .find(|slot| slot.as_ref().map_or(false, |registration| registration.name == name))
+            .ok_or(ConsoleError::NoSuchSink)?;
+        *slot = None;
+        Ok(())
+    })
+}
+
+pub fn set_enabled(name: &str, enabled: bool) -> Result<(), ConsoleError> {
+    no_interrupts(|| {
+        let sinks = SINKS.read();
+        let registration = sinks
+            .iter()
+            .flatten()
+            .find(|registration| registration.name == name)
+            .ok_or(ConsoleError::NoSuchSink)?;
+        registration.enabled.store(enabled, Ordering::SeqCst);
+        Ok(())
+    })
+}
+
+/// Whether sink `name` is enabled, `None` if there is no such sink.
+pub fn is_enabled(name: &str) -> Option<bool> {
+    no_interrupts(|| {
+        SINKS
+            .read()
+            .iter()
+            .flatten()
+            .find(|registration| registration.name == name)
+            .map(|registration| registration.enabled.load(Ordering::SeqCst))
+    })
+}
+
+/// Hands each piece of formatted output to the sinks enabled when printing
+/// started.
+struct FanOut([Option<&'static dyn Sink>; MAX_SINKS]);
+
+impl FanOut {
+    fn enabled() -> FanOut {
+        let mut sinks = [None; MAX_SINKS];
+        no_interrupts(|| {
+            let registrations = SINKS.read();
+            let enabled = registrations
+                .iter()
+                .flatten()
+                .filter(|registration| registration.enabled.load(Ordering::Relaxed));
+            for (slot, registration) in sinks.iter_mut().zip(enabled) {
+                *slot = Some(registration.sink);
+            }
+        });
+        FanOut(sinks)
+    }
+}
+
+impl fmt::Write for FanOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for sink in self.0.iter().flatten() {
+            sink.write_str(s);
+        }
+        Ok(())
+    }
+}
+
+/// Keeps concurrent prints from interleaving.
+static PRINT_LOCK: Mutex<()> = Mutex::new(());
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    // The holder may have been interrupted, do not wait for it then.
+    let _guard = if arch::interrupts_enabled() {
+        Some(PRINT_LOCK.lock())
+    } else {
+        PRINT_LOCK.try_lock()
+    };
+    // Sinks run with the interrupt state of the caller, so the serial port
+    // keeps sending from its interrupt handler.
+    FanOut::enabled().write_fmt(args).unwrap();
+}
+
+/// Prints to every enabled console sink.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::console::_print(format_args!($($arg)*)));
+}
+
+/// Prints to every enabled console sink, with a newline.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,75 @@
+use spin::Mutex;
+
+use super::Sink;
+use crate::arch::no_interrupts;
+
+/// Bytes kept by a `RingSink`.
+pub const RING_SIZE: usize = 16 * 1024;
+
+struct Ring {
+    bytes: [u8; RING_SIZE],
+    /// Where the next byte goes.
+    head: usize,
+    len: usize,
+}
+
+/// Keeps the last `RING_SIZE` bytes written in memory, dropping the oldest.
+pub struct RingSink {
+    ring: Mutex<Ring>,
+}
+
+impl RingSink {
+    pub const fn new() -> RingSink {
+        RingSink {
+            ring: Mutex::new(Ring {
+                bytes: [0; RING_SIZE],
+                head: 0,
+                len: 0,
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        no_interrupts(|| self.ring.lock().len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        no_interrupts(|| {
+            let mut ring = self.ring.lock();
+            ring.head = 0;
+            ring.len = 0;
+        })
+    }
+
+    /// Copy the newest bytes that fit to `out`, oldest first, returning
+    /// how many were copied.
+    pub fn copy_to(&self, out: &mut [u8]) -> usize {
+        no_interrupts(|| {
+            let ring = self.ring.lock();
+            let count = ring.len.min(out.len());
+            let start = (ring.head + RING_SIZE - count) % RING_SIZE;
+            for (i, byte) in out[..count].iter_mut().enumerate() {
+                *byte = ring.bytes[(start + i) % RING_SIZE];
+            }
+            count
+        })
+    }
+}
+
+impl Sink for RingSink {
+    fn write_str(&self, s: &str) {
+        no_interrupts(|| {
+            let mut ring = self.ring.lock();
+            for &byte in s.as_bytes() {
+                let head = ring.head;
+                ring.bytes[head] = byte;
+                ring.head = (head + 1) % RING_SIZE;
+                ring.len = (ring.len + 1).min(RING_SIZE);
+            }
+        })
+    }
+}
@@ -1,4 +1,12 @@
 pub mod task;
 pub mod keyboard;
+pub mod mouse;
+pub mod serial;
+pub mod terminal;
 pub mod simple_executor;
 pub mod executor;
+pub mod join_handle;
+pub mod sync;
+pub mod channel;
+pub mod irq_stream;
+mod wake_queue;
@@ -0,0 +1,9 @@
+//! Channels to pass values between tasks.
+//!
+//! `mpsc` queues values from many senders to one receiver, and its bounded
+//! variant can be fed from interrupt handlers. `oneshot` passes a single
+//! value, `broadcast` hands a copy of every value to each receiver.
+
+pub mod mpsc;
+pub mod oneshot;
+pub mod broadcast;
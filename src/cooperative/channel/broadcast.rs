@@ -0,0 +1,192 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{future, stream::Stream, task::AtomicWaker};
+
+/// No receiver exists, the value is handed back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// All senders are gone and the receiver saw every value.
+    Closed,
+    /// The receiver fell behind and missed this many values, it continues
+    /// with the oldest one still buffered.
+    Lagged(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+struct State<T> {
+    /// The last values sent, the oldest one has sequence number `head`.
+    buffer: VecDeque<T>,
+    head: u64,
+    senders: usize,
+    receivers: Vec<Arc<AtomicWaker>>,
+}
+
+impl<T> State<T> {
+    /// Sequence number of the next value sent.
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    fn wake_receivers(&self) {
+        for waker in &self.receivers {
+            waker.wake();
+        }
+    }
+}
+
+struct Shared<T> {
+    capacity: usize,
+    state: spin::Mutex<State<T>>,
+}
+
+/// Create a channel delivering every value to every receiver.
+///
+/// The last `capacity` values are kept for receivers that fall behind.
+/// Panics if `capacity` is zero.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast channel capacity must not be zero");
+    let shared = Arc::new(Shared {
+        capacity,
+        state: spin::Mutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            head: 0,
+            senders: 1,
+            receivers: Vec::new(),
+        }),
+    });
+    let sender = Sender { shared };
+    let receiver = sender.subscribe();
+    (sender, receiver)
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// Send `value` to all receivers, returning how many there are.
+    ///
+    /// Never waits: the oldest value is dropped if the buffer is full.
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = self.shared.state.lock();
+        if state.receivers.is_empty() {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == self.shared.capacity {
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        state.wake_receivers();
+        Ok(state.receivers.len())
+    }
+
+    /// Create a receiver for values sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let waker = Arc::new(AtomicWaker::new());
+        let mut state = self.shared.state.lock();
+        state.receivers.push(waker.clone());
+        Receiver {
+            shared: self.shared.clone(),
+            next: state.tail(),
+            waker,
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.shared.state.lock().receivers.len()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.shared.state.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.wake_receivers();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Sequence number of the next value to receive.
+    next: u64,
+    waker: Arc<AtomicWaker>,
+}
+
+impl<T: Clone> Receiver<T> {
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Result<T, RecvError>> {
+        self.waker.register(cx.waker());
+        match self.try_recv() {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+            Err(TryRecvError::Lagged(missed)) => Poll::Ready(Err(RecvError::Lagged(missed))),
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let state = self.shared.state.lock();
+        if self.next < state.head {
+            let missed = state.head - self.next;
+            self.next = state.head;
+            return Err(TryRecvError::Lagged(missed));
+        }
+        if self.next < state.tail() {
+            let value = state.buffer[(self.next - state.head) as usize].clone();
+            self.next += 1;
+            return Ok(value);
+        }
+        if state.senders == 0 {
+            return Err(TryRecvError::Closed);
+        }
+        Err(TryRecvError::Empty)
+    }
+}
+
+/// Yields `Err(RecvError::Lagged)` when values were missed and ends once the
+/// channel is closed.
+impl<T: Clone> Stream for Receiver<T> {
+    type Item = Result<T, RecvError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match futures_util::ready!(self.get_mut().poll_recv(cx)) {
+            Err(RecvError::Closed) => Poll::Ready(None),
+            result => Poll::Ready(Some(result)),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let waker = &self.waker;
+        self.shared.state.lock().receivers.retain(|other| !Arc::ptr_eq(other, waker));
+    }
+}
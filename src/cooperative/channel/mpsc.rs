@@ -0,0 +1,420 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use crossbeam_queue::{ArrayQueue, PushError};
+use futures_util::{
+    future,
+    sink::Sink,
+    stream::Stream,
+    task::AtomicWaker,
+};
+
+/// The receiver is gone, the value is handed back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// A bounded channel has no room left.
+    Full(T),
+    /// The receiver is gone.
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    /// All senders are gone and no values are left.
+    Disconnected,
+}
+
+/// Error of the `Sink` implementations: the receiver is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+enum Queue<T> {
+    /// Preallocated, pushing never allocates or blocks.
+    Bounded(ArrayQueue<T>),
+    Unbounded(spin::Mutex<VecDeque<T>>),
+}
+
+struct Chan<T> {
+    queue: Queue<T>,
+    senders: AtomicUsize,
+    /// The receiver was closed or dropped.
+    closed: AtomicBool,
+    recv_waker: AtomicWaker,
+    /// Senders waiting for room in a bounded channel.
+    send_wakers: spin::Mutex<Vec<Waker>>,
+}
+
+impl<T> Chan<T> {
+    fn new(queue: Queue<T>) -> Arc<Chan<T>> {
+        Arc::new(Chan {
+            queue,
+            senders: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+            recv_waker: AtomicWaker::new(),
+            send_wakers: spin::Mutex::new(Vec::new()),
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Queue `value` without waiting. Safe in interrupt context for bounded
+    /// channels.
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.is_closed() {
+            return Err(TrySendError::Closed(value));
+        }
+        match &self.queue {
+            Queue::Bounded(queue) => queue
+                .push(value)
+                .map_err(|PushError(value)| TrySendError::Full(value))?,
+            Queue::Unbounded(queue) => queue.lock().push_back(value),
+        }
+        self.recv_waker.wake();
+        Ok(())
+    }
+
+    fn is_full(&self) -> bool {
+        match &self.queue {
+            Queue::Bounded(queue) => queue.is_full(),
+            Queue::Unbounded(_) => false,
+        }
+    }
+
+    /// Wait until the channel has room.
+    fn poll_send_ready(&self, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        if self.is_closed() {
+            return Poll::Ready(Err(Closed));
+        }
+        if !self.is_full() {
+            return Poll::Ready(Ok(()));
+        }
+
+        {
+            let mut wakers = self.send_wakers.lock();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+
+        if self.is_closed() {
+            Poll::Ready(Err(Closed))
+        } else if !self.is_full() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn wake_senders(&self) {
+        let wakers = core::mem::replace(&mut *self.send_wakers.lock(), Vec::new());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        match &self.queue {
+            Queue::Bounded(queue) => {
+                let value = queue.pop().ok();
+                if value.is_some() {
+                    self.wake_senders();
+                }
+                value
+            }
+            Queue::Unbounded(queue) => queue.lock().pop_front(),
+        }
+    }
+
+    fn add_sender(self: &Arc<Self>) -> Arc<Chan<T>> {
+        self.senders.fetch_add(1, Ordering::SeqCst);
+        self.clone()
+    }
+
+    fn drop_sender(&self) {
+        if self.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.recv_waker.wake();
+        }
+    }
+}
+
+/// Create a channel holding at most `capacity` values.
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let chan = Chan::new(Queue::Bounded(ArrayQueue::new(capacity)));
+    let sender = Sender {
+        chan: chan.clone(),
+        pending: None,
+    };
+    (sender, Receiver { chan })
+}
+
+/// Create a channel whose senders never wait.
+pub fn unbounded<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let chan = Chan::new(Queue::Unbounded(spin::Mutex::new(VecDeque::new())));
+    (UnboundedSender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// Sending end of a bounded channel.
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+    /// Value passed to `Sink::start_send` that did not fit yet.
+    pending: Option<T>,
+}
+
+// `pending` is never pinned.
+impl<T> Unpin for Sender<T> {}
+
+impl<T> Sender<T> {
+    /// Send `value`, waiting for room in the channel.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        future::poll_fn(|cx| loop {
+            if let Err(Closed) = futures_util::ready!(self.chan.poll_send_ready(cx)) {
+                return Poll::Ready(Err(SendError(value.take().expect("polled after completion"))));
+            }
+            match self.chan.try_send(value.take().expect("polled after completion")) {
+                Ok(()) => return Poll::Ready(Ok(())),
+                Err(TrySendError::Closed(rejected)) => return Poll::Ready(Err(SendError(rejected))),
+                // Another sender took the room.
+                Err(TrySendError::Full(rejected)) => value = Some(rejected),
+            }
+        }).await
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(value)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+
+    /// Create a sender for interrupt handlers.
+    pub fn irq_sender(&self) -> IrqSender<T> {
+        IrqSender {
+            chan: self.chan.add_sender(),
+        }
+    }
+
+    fn poll_flush_pending(&mut self, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        while let Some(value) = self.pending.take() {
+            match self.chan.poll_send_ready(cx) {
+                Poll::Pending => {
+                    self.pending = Some(value);
+                    return Poll::Pending;
+                }
+                Poll::Ready(Err(Closed)) => return Poll::Ready(Err(Closed)),
+                Poll::Ready(Ok(())) => {}
+            }
+            match self.chan.try_send(value) {
+                Ok(()) => {}
+                Err(TrySendError::Full(value)) => self.pending = Some(value),
+                Err(TrySendError::Closed(_)) => return Poll::Ready(Err(Closed)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Sink<T> for Sender<T> {
+    type Error = Closed;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        self.get_mut().poll_flush_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Closed> {
+        let this = self.get_mut();
+        if this.chan.is_closed() {
+            return Err(Closed);
+        }
+        if let Err(TrySendError::Full(item)) = this.chan.try_send(item) {
+            this.pending = Some(item);
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        self.get_mut().poll_flush_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Closed>> {
+        self.get_mut().poll_flush_pending(cx)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        Sender {
+            chan: self.chan.add_sender(),
+            pending: None,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.pending.take() {
+            let _ = self.chan.try_send(value);
+        }
+        self.chan.drop_sender();
+    }
+}
+
+/// Sender of a bounded channel for interrupt handlers.
+///
+/// Sending never blocks or allocates. Dropping the last handle to the
+/// channel frees it, so at least one handle must be dropped outside of
+/// interrupt handlers.
+pub struct IrqSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> IrqSender<T> {
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(value)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Clone for IrqSender<T> {
+    fn clone(&self) -> IrqSender<T> {
+        IrqSender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for IrqSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// Sending end of an unbounded channel.
+pub struct UnboundedSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.chan.try_send(value).map_err(|error| match error {
+            TrySendError::Full(value) | TrySendError::Closed(value) => SendError(value),
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Sink<T> for UnboundedSender<T> {
+    type Error = Closed;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Closed>> {
+        if self.is_closed() {
+            Poll::Ready(Err(Closed))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Closed> {
+        self.send(item).map_err(|_| Closed)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Closed>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Closed>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> UnboundedSender<T> {
+        UnboundedSender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// Receiving end of a channel.
+///
+/// Yields `None` once all senders are gone and the channel is empty.
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        if let Some(value) = self.chan.pop() {
+            return Poll::Ready(Some(value));
+        }
+
+        self.chan.recv_waker.register(cx.waker());
+        if let Some(value) = self.chan.pop() {
+            return Poll::Ready(Some(value));
+        }
+        if self.chan.senders.load(Ordering::SeqCst) == 0 {
+            // A sender may have sent right before leaving.
+            return Poll::Ready(self.chan.pop());
+        }
+        Poll::Pending
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.chan.pop() {
+            return Ok(value);
+        }
+        if self.chan.senders.load(Ordering::SeqCst) == 0 {
+            return self.chan.pop().ok_or(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    /// Reject further values. Values already queued can still be received.
+    pub fn close(&mut self) {
+        self.chan.closed.store(true, Ordering::SeqCst);
+        self.chan.wake_senders();
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -1,7 +1,10 @@
-use alloc::{collections::{BTreeMap, VecDeque}, sync::Arc};
+use alloc::{collections::{BTreeMap, VecDeque}, sync::Arc, vec::Vec};
 use alloc::task::Wake;
 
 use core::{
+    future::Future,
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     task::{
         RawWakerVTable,
         RawWaker,
@@ -11,132 +14,552 @@ use core::{
     },
 };
 
+use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
 
-use crate::cooperative::task::{Task, TaskId};
-use crate::println;
+use crate::cooperative::join_handle::JoinHandle;
+use crate::cooperative::task::{
+    Priority, Task, TaskHeader, TaskId, TaskOptions, TaskStats, PRIORITY_CLASSES,
+};
+use crate::cooperative::wake_queue::WakeQueue;
+use crate::thread::{self, ThreadError};
+use crate::arch::interrupt_stats::timestamp;
+use crate::{serial_println, warn};
+
+/// Default number of polls per scheduling iteration.
+pub const DEFAULT_POLL_BUDGET: usize = 32;
+
+/// Default poll duration above which the watchdog warns, in TSC cycles.
+pub const DEFAULT_WATCHDOG_CYCLES: u64 = 100_000_000;
+
+/// Capacity of the queue used by `spawn_from_interrupt`.
+pub const INTERRUPT_SPAWN_QUEUE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutorError {
+    /// The interrupt spawn queue is full.
+    QueueFull,
+    /// The executor was shut down or dropped.
+    ShutDown,
+    /// No executor is running.
+    NoExecutor,
+}
+
+/// Builds a task in executor context, see `spawn_from_interrupt`.
+pub type TaskFactory = fn() -> Task;
 
-const WAKE_Q_SIZE: usize = 100;
+/// Tasks queued by interrupt handlers, built and scheduled by the executor
+/// owning the queue, see `INTERRUPT_SPAWN_OWNER`.
+static INTERRUPT_SPAWN_QUEUE: OnceCell<ArrayQueue<TaskFactory>> = OnceCell::uninit();
 
-#[cfg(feature = "spawner")]
+/// Whether an executor owns `INTERRUPT_SPAWN_QUEUE`. The first executor
+/// created takes it, the next one created after it was dropped takes over.
+static INTERRUPT_SPAWN_OWNER: AtomicBool = AtomicBool::new(false);
+
+struct SpawnerShared {
+    spawn_queue: spin::Mutex<VecDeque<Task>>,
+    shut_down: AtomicBool,
+}
+
+/// Handle to spawn tasks onto an `Executor`, also from the tasks it runs.
+///
+/// Interrupt handlers must use `spawn_from_interrupt` instead.
 #[derive(Clone)]
 pub struct Spawner {
-    spawn_queue: Arc<spin::Mutex<VecDeque<Task>>>,
+    shared: Arc<SpawnerShared>,
 }
 
-#[cfg(feature = "spawner")]
 impl Spawner {
     fn new() -> Spawner {
-        let spawn_queue = Arc::new(spin::Mutex::new(VecDeque::new()));
+        let shared = Arc::new(SpawnerShared {
+            spawn_queue: spin::Mutex::new(VecDeque::new()),
+            shut_down: AtomicBool::new(false),
+        });
         Spawner {
-            spawn_queue,
+            shared,
+        }
+    }
+
+    /// Spawn `future` as a new task, returning a handle to await its output.
+    pub fn spawn<F>(&self, future: F) -> Result<JoinHandle<F::Output>, ExecutorError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with(future, TaskOptions::default())
+    }
+
+    /// Spawn `future` as a new task configured by `options`.
+    pub fn spawn_with<F>(
+        &self,
+        future: F,
+        options: TaskOptions,
+    ) -> Result<JoinHandle<F::Output>, ExecutorError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        if self.is_shut_down() {
+            return Err(ExecutorError::ShutDown);
         }
+        let (mut task, handle) = Task::with_join_handle(future);
+        task.set_options(options);
+        self.spawn_task(task)?;
+        Ok(handle)
     }
 
-    pub fn spawn(&mut self, task: Task) -> Result<(), ExecutorError> {
-        let task_id = task.id();
-        self.spawn_queue.lock().push_back(task);
+    pub fn spawn_task(&self, task: Task) -> Result<(), ExecutorError> {
+        if self.is_shut_down() {
+            return Err(ExecutorError::ShutDown);
+        }
+        self.shared.spawn_queue.lock().push_back(task);
         Ok(())
     }
 
-    fn tasks(&mut self) -> spin::MutexGuard<VecDeque<Task>> {
-        self.spawn_queue.lock()
+    /// Stop the executor: `Executor::run` returns after the current
+    /// iteration and spawning fails with `ExecutorError::ShutDown`.
+    pub fn shutdown(&self) {
+        self.shared.shut_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.shared.shut_down.load(Ordering::SeqCst)
+    }
+
+    fn tasks(&self) -> spin::MutexGuard<VecDeque<Task>> {
+        self.shared.spawn_queue.lock()
     }
 
-    fn pop(&mut self) -> Option<Task> {
-        self.spawn_queue.lock().pop_front()
+    fn pop(&self) -> Option<Task> {
+        self.shared.spawn_queue.lock().pop_front()
     }
 
     fn len(&self) -> usize {
-        self.spawn_queue.lock().len()
+        self.shared.spawn_queue.lock().len()
     }
 }
 
-pub enum ExecutorError {
+/// Current spawner of the boot context before `thread::init`, when there is
+/// no thread table to keep it in and no other thread to see it.
+static BOOT_SPAWNER: AtomicUsize = AtomicUsize::new(0);
+
+/// Make `spawner` the one of the executor running on the current thread,
+/// returning the previous one.
+///
+/// It is kept in the thread's `thread::local` word as a pointer from
+/// `Arc::into_raw`. Spawners queue tasks that are not `Send`, so they must
+/// never be reachable from another thread, which the word guarantees.
+fn set_current_spawner(spawner: Option<Spawner>) -> Option<Spawner> {
+    let raw = spawner.map_or(0, |spawner| Arc::into_raw(spawner.shared) as usize);
+    let previous = thread::set_local(raw)
+        .unwrap_or_else(|_| BOOT_SPAWNER.swap(raw, Ordering::SeqCst));
+    unsafe { spawner_from_raw(previous) }
+}
+
+/// Take back a spawner stored by `set_current_spawner`.
+unsafe fn spawner_from_raw(raw: usize) -> Option<Spawner> {
+    if raw == 0 {
+        return None;
+    }
+    Some(Spawner { shared: Arc::from_raw(raw as *const SpawnerShared) })
+}
+
+/// Restores the spawner of the outer executor when dropped.
+struct EnterGuard {
+    previous: Option<Spawner>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        set_current_spawner(self.previous.take());
+    }
+}
+
+/// Spawner of the executor running the current task.
+pub fn current_spawner() -> Option<Spawner> {
+    // The thread keeps its reference, only a clone is handed out.
+    let raw = thread::local().unwrap_or_else(|_| BOOT_SPAWNER.load(Ordering::SeqCst));
+    let spawner = ManuallyDrop::new(unsafe { spawner_from_raw(raw) }?);
+    Some(Spawner::clone(&spawner))
+}
+
+/// Spawn `future` onto the executor running the current task.
+///
+/// Before `thread::init` the current executor is the one running on the
+/// boot stack. Calling `thread::init` while it runs makes it stop being
+/// current.
+pub fn spawn<F>(future: F) -> Result<JoinHandle<F::Output>, ExecutorError>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    current_spawner().ok_or(ExecutorError::NoExecutor)?.spawn(future)
+}
+
+/// Spawn a task from an interrupt handler.
+///
+/// Never blocks or allocates: `factory` is pushed on a lock-free queue and
+/// called by the executor owning the queue to build the task, which runs as
+/// `Priority::BottomHalf`. With several executors only that one runs
+/// interrupt spawned tasks.
+pub fn spawn_from_interrupt(factory: TaskFactory) -> Result<(), ExecutorError> {
+    if !INTERRUPT_SPAWN_OWNER.load(Ordering::SeqCst) {
+        return Err(ExecutorError::NoExecutor);
+    }
+    let queue = INTERRUPT_SPAWN_QUEUE.try_get().map_err(|_| ExecutorError::NoExecutor)?;
+    queue.push(factory).map_err(|_| ExecutorError::QueueFull)
+}
+
+/// Where a task is in its life cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Spawned through a `Spawner`, not scheduled yet.
+    Spawned,
+    Ready,
+    /// Waiting to be woken.
+    Pending,
+}
+
+/// Snapshot of a task, see `Executor::tasks`.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<&'static str>,
+    pub priority: Priority,
+    pub state: TaskState,
+    pub stats: TaskStats,
+}
+
+impl TaskInfo {
+    fn new(task: &Task, state: TaskState) -> TaskInfo {
+        TaskInfo {
+            id: task.id(),
+            name: task.name(),
+            priority: task.priority(),
+            state,
+            stats: task.stats(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutorStats {
+    pub spawned: usize,
+    pub ready: usize,
+    pub pending: usize,
+    /// Tasks that completed or were aborted.
+    pub finished: u64,
+    pub polls: u64,
 }
 
 pub struct Executor {
-    #[cfg(feature = "spawner")]
     spawner: Spawner,
 
-    task_queue: VecDeque<Task>,
+    /// Ready tasks, one queue per `Priority`.
+    task_queues: [VecDeque<Task>; PRIORITY_CLASSES],
+    poll_budget: usize,
     waiting_tasks: BTreeMap<TaskId, Task>,
-    wake_queue: Arc<ArrayQueue<TaskId>>,
+    wake_queue: Arc<WakeQueue>,
     waker_cache: BTreeMap<TaskId, Waker>,
+
+    watchdog_cycles: Option<u64>,
+    finished: u64,
+    polls: u64,
+
+    /// Whether this executor owns `INTERRUPT_SPAWN_QUEUE`.
+    interrupt_spawns: bool,
 }
 
 impl Executor {
     pub fn new() -> Self {
+        let interrupt_spawns = INTERRUPT_SPAWN_OWNER
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+        if interrupt_spawns {
+            let _ = INTERRUPT_SPAWN_QUEUE
+                .try_init_once(|| ArrayQueue::new(INTERRUPT_SPAWN_QUEUE_SIZE));
+        }
+
         Executor {
-            #[cfg(feature = "spawner")]
             spawner: Spawner::new(),
 
-            task_queue: VecDeque::new(),
+            task_queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            poll_budget: DEFAULT_POLL_BUDGET,
             waiting_tasks: BTreeMap::new(),
-            wake_queue: Arc::new(ArrayQueue::new(WAKE_Q_SIZE)),
+            wake_queue: Arc::new(WakeQueue::new()),
             waker_cache: BTreeMap::new(),
+
+            watchdog_cycles: Some(DEFAULT_WATCHDOG_CYCLES),
+            finished: 0,
+            polls: 0,
+
+            interrupt_spawns,
         }
     }
 
-    #[cfg(feature = "spawner")]
-    pub fn spawn(&mut self, task: Task) -> Result<(), ExecutorError> {
-        self.spawner.spawn(task)
+    /// Spawn `future` as a new task, returning a handle to await its output.
+    pub fn spawn<F>(&mut self, future: F) -> Result<JoinHandle<F::Output>, ExecutorError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with(future, TaskOptions::default())
+    }
+
+    /// Spawn `future` as a new task configured by `options`.
+    pub fn spawn_with<F>(
+        &mut self,
+        future: F,
+        options: TaskOptions,
+    ) -> Result<JoinHandle<F::Output>, ExecutorError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (mut task, handle) = Task::with_join_handle(future);
+        task.set_options(options);
+        self.spawn_task(task)?;
+        Ok(handle)
     }
 
-    #[cfg(not(feature = "spawner"))]
-    pub fn spawn(&mut self, task: Task) -> Result<(), ExecutorError> {
-        self.task_queue.push_back(task);
+    pub fn spawn_task(&mut self, task: Task) -> Result<(), ExecutorError> {
+        if self.spawner.is_shut_down() {
+            return Err(ExecutorError::ShutDown);
+        }
+        self.enqueue(task);
         Ok(())
     }
 
-    #[cfg(feature = "spawner")]
+    /// Set the maximum number of tasks polled before checking for new
+    /// wakeups again.
+    ///
+    /// Every class with ready tasks is polled at least once per iteration,
+    /// so low priority tasks make progress even if the budget is exhausted.
+    pub fn set_poll_budget(&mut self, budget: usize) {
+        self.poll_budget = core::cmp::max(budget, 1);
+    }
+
+    /// Warn about polls taking longer than `cycles` TSC cycles, `None`
+    /// disables the watchdog.
+    pub fn set_watchdog(&mut self, cycles: Option<u64>) {
+        self.watchdog_cycles = cycles;
+    }
+
+    fn enqueue(&mut self, task: Task) {
+        self.task_queues[task.priority() as usize].push_back(task);
+    }
+
+    fn has_ready_tasks(&self) -> bool {
+        self.task_queues.iter().any(|queue| !queue.is_empty())
+    }
+
     pub fn get_spawner(&self) -> Spawner {
         self.spawner.clone()
     }
 
+    /// Stop the executor, see `Spawner::shutdown`.
+    pub fn shutdown(&self) {
+        self.spawner.shutdown();
+    }
+
+    /// The interrupt spawn queue, if this executor owns it.
+    fn interrupt_spawn_queue(&self) -> Option<&'static ArrayQueue<TaskFactory>> {
+        if !self.interrupt_spawns {
+            return None;
+        }
+        INTERRUPT_SPAWN_QUEUE.try_get().ok()
+    }
+
+    /// Whether tasks were spawned but not scheduled yet.
+    fn has_spawned_tasks(&self) -> bool {
+        let from_interrupts = self
+            .interrupt_spawn_queue()
+            .map_or(false, |queue| !queue.is_empty());
+        self.spawner.len() != 0 || from_interrupts
+    }
+
     fn create_waker(&self, task: &Task) -> Waker {
-        let task_waker = TaskWaker::new(task.id(), self.wake_queue.clone());
+        let task_waker = TaskWaker::new(task.header().clone(), self.wake_queue.clone());
         Waker::from(Arc::new(task_waker))
     }
 
+    /// Abort the task with id `task_id`.
+    ///
+    /// The task is dropped at the next scheduling point, which cancels its
+    /// `JoinHandle`. Returns `false` if the executor has no such task.
+    pub fn abort(&mut self, task_id: TaskId) -> bool {
+        if let Some(task) = self.waiting_tasks.remove(&task_id) {
+            task.abort();
+            self.enqueue(task);
+            return true;
+        }
+
+        let mut ready = self.task_queues.iter().flatten();
+        if let Some(task) = ready.find(|task| task.id() == task_id) {
+            task.abort();
+            return true;
+        }
+
+        if let Some(task) = self.spawner.tasks().iter().find(|task| task.id() == task_id) {
+            task.abort();
+            return true;
+        }
+
+        false
+    }
+
+    /// Poll ready tasks, highest priority first, within the poll budget.
     fn run_ready(&mut self) {
-        while let Some(mut task) = self.task_queue.pop_front() {
-            if !self.waker_cache.contains_key(&task.id()) {
-                    self.waker_cache.insert(task.id(), self.create_waker(&task));
+        let mut budget = self.poll_budget;
+        for class in 0..PRIORITY_CLASSES {
+            // Keep one poll for each lower class that has work.
+            let reserved = self.task_queues[class + 1..]
+                .iter()
+                .filter(|queue| !queue.is_empty())
+                .count();
+            // Only tasks that were ready before this iteration are polled,
+            // so a task that keeps waking itself cannot monopolize its class.
+            let ready = self.task_queues[class].len();
+            let polls = core::cmp::min(ready, core::cmp::max(budget.saturating_sub(reserved), 1));
+
+            for _ in 0..polls {
+                let task = self.task_queues[class].pop_front().expect("should exist");
+                self.poll_task(task);
             }
-            let waker = self.waker_cache.get(&task.id()).expect("should exist");
-            let mut context = Context::from_waker(&waker);
+            budget = budget.saturating_sub(polls);
+        }
+    }
 
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    self.waker_cache.remove(&task.id());
-                }
-                Poll::Pending => {
-                    if self.waiting_tasks.insert(task.id(), task).is_some() {
-                        panic!("Attempted to insert task with non-unique ID");
-                    }
-                },
+    /// Number of tasks, spawned but not yet scheduled ones included.
+    pub fn task_count(&self) -> usize {
+        let stats = self.stats();
+        stats.spawned + stats.ready + stats.pending
+    }
+
+    pub fn stats(&self) -> ExecutorStats {
+        ExecutorStats {
+            spawned: self.spawner.len(),
+            ready: self.task_queues.iter().map(VecDeque::len).sum(),
+            pending: self.waiting_tasks.len(),
+            finished: self.finished,
+            polls: self.polls,
+        }
+    }
+
+    /// Snapshot of all tasks, ready ones first in scheduling order.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let ready = self.task_queues.iter().flatten()
+            .map(|task| TaskInfo::new(task, TaskState::Ready));
+        let pending = self.waiting_tasks.values()
+            .map(|task| TaskInfo::new(task, TaskState::Pending));
+        let mut tasks: Vec<TaskInfo> = ready.chain(pending).collect();
+        tasks.extend(self.spawner.tasks().iter().map(|task| TaskInfo::new(task, TaskState::Spawned)));
+        tasks
+    }
+
+    /// Print the executor statistics and a table of all tasks.
+    pub fn dump(&self) {
+        let stats = self.stats();
+        let now = timestamp();
+        serial_println!(
+            "executor: {} spawned, {} ready, {} pending, {} finished, {} polls",
+            stats.spawned, stats.ready, stats.pending, stats.finished, stats.polls
+        );
+        serial_println!(
+            "{:>6} {:<20} {:<10} {:<8} {:>8} {:>12} {:>12} {:>14}",
+            "ID", "NAME", "PRIORITY", "STATE", "POLLS", "AVG CYCLES", "MAX CYCLES", "WOKEN AGO"
+        );
+        for task in self.tasks() {
+            let priority = alloc::format!("{:?}", task.priority);
+            let state = alloc::format!("{:?}", task.state);
+            let woken_ago = task.stats.last_wake.map(|wake| now.saturating_sub(wake));
+            serial_println!(
+                "{:>6} {:<20} {:<10} {:<8} {:>8} {:>12} {:>12} {:>14}",
+                task.id.as_u64(),
+                task.name.unwrap_or("-"),
+                priority,
+                state,
+                task.stats.polls,
+                task.stats.average_poll_cycles(),
+                task.stats.max_poll_cycles,
+                woken_ago.map_or(alloc::string::String::from("-"), |ago| alloc::format!("{}", ago)),
+            );
+        }
+    }
+
+    fn poll_task(&mut self, mut task: Task) {
+        if task.is_aborted() {
+            self.waker_cache.remove(&task.id());
+            self.finished += 1;
+            return;
+        }
+
+        if !self.waker_cache.contains_key(&task.id()) {
+                self.waker_cache.insert(task.id(), self.create_waker(&task));
+        }
+        let waker = self.waker_cache.get(&task.id()).expect("should exist");
+        let mut context = Context::from_waker(&waker);
+
+        let start = timestamp();
+        let result = task.poll(&mut context);
+        let cycles = timestamp().saturating_sub(start);
+        task.record_poll(cycles);
+        self.polls += 1;
+        if self.watchdog_cycles.map_or(false, |limit| cycles > limit) {
+            warn!(
+                "task {} ({}) blocked the executor for {} cycles",
+                task.id().as_u64(), task.name().unwrap_or("unnamed"), cycles
+            );
+        }
+
+        match result {
+            Poll::Ready(()) => {
+                self.waker_cache.remove(&task.id());
+                self.finished += 1;
             }
+            Poll::Pending => {
+                if self.waiting_tasks.insert(task.id(), task).is_some() {
+                    panic!("Attempted to insert task with non-unique ID");
+                }
+            },
         }
     }
 
     fn wake_tasks(&mut self) {
-        while let Ok(task_id) = self.wake_queue.pop() {
-            let task = self.waiting_tasks.remove(&task_id).expect("task with unknown ID woke up");
-            self.task_queue.push_back(task);
+        for task_id in self.wake_queue.drain() {
+            // Tasks that completed or were aborted may still be woken by
+            // stale wakers, and tasks woken while being polled are not
+            // waiting yet.
+            if let Some(task) = self.waiting_tasks.remove(&task_id) {
+                self.enqueue(task);
+            }
         }
     }
 
-    #[cfg(feature = "spawner")]
     fn spawn_tasks(&mut self) {
         while let Some(task) = self.spawner.pop() {
-            self.task_queue.push_back(task);
+            self.enqueue(task);
         }
+
+        if let Some(queue) = self.interrupt_spawn_queue() {
+            while let Ok(factory) = queue.pop() {
+                let mut task = factory();
+                task.set_priority(Priority::BottomHalf);
+                self.enqueue(task);
+            }
+        }
+    }
+
+    /// Make this executor's spawner the current one until the guard drops.
+    fn enter(&self) -> EnterGuard {
+        let previous = set_current_spawner(Some(self.spawner.clone()));
+        EnterGuard { previous }
     }
 
+    /// Run tasks until the executor is shut down.
     pub fn run(&mut self) {
-        loop {
-            #[cfg(feature = "spawner")]
+        let _guard = self.enter();
+        while !self.spawner.is_shut_down() {
             self.spawn_tasks();
 
             self.wake_tasks();
@@ -146,35 +569,86 @@ impl Executor {
         }
     }
 
+    /// Run tasks until none of them is ready, then return.
+    pub fn run_until_stalled(&mut self) {
+        let _guard = self.enter();
+        loop {
+            self.spawn_tasks();
+
+            self.wake_tasks();
+            if !self.has_ready_tasks() {
+                return;
+            }
+            self.run_ready();
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.wake_queue.is_empty()
+            && !self.has_ready_tasks()
+            && !self.has_spawned_tasks()
+            && !self.spawner.is_shut_down()
+    }
+
     fn sleep_if_idle(&self) {
-        if !self.wake_queue.is_empty() {
+        if !self.is_idle() {
             return;
         }
 
         crate::arch::interrupts::disable();
-        if self.wake_queue.is_empty() {
-            crate::arch::interrupts::enable_interrupt_halt();
+        if self.is_idle() {
+            thread::idle();
         } else {
             crate::arch::interrupts::enable();
         }
     }
 }
 
+/// Run a new executor in a kernel thread until it is shut down.
+///
+/// `setup` runs in the new thread and spawns the initial tasks.
+pub fn spawn_executor_thread<F>(
+    name: &'static str,
+    setup: F,
+) -> Result<thread::JoinHandle<()>, ThreadError>
+where
+    F: FnOnce(&mut Executor) + Send + 'static,
+{
+    thread::spawn(name, move || {
+        let mut executor = Executor::new();
+        setup(&mut executor);
+        executor.run();
+    })
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        // Spawners may outlive the executor, their tasks would never run.
+        self.spawner.shutdown();
+        if self.interrupt_spawns {
+            INTERRUPT_SPAWN_OWNER.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
 struct TaskWaker {
-    task_id: TaskId,
-    wake_queue: Arc<ArrayQueue<TaskId>>,
+    header: Arc<TaskHeader>,
+    wake_queue: Arc<WakeQueue>,
 }
 
 impl TaskWaker {
-    pub fn new(task_id: TaskId, wake_queue: Arc<ArrayQueue<TaskId>>) -> TaskWaker {
+    pub fn new(header: Arc<TaskHeader>, wake_queue: Arc<WakeQueue>) -> TaskWaker {
         TaskWaker {
-            task_id,
+            header,
             wake_queue,
         }
     }
 
+    /// Queue the task for polling. Never blocks, allocates or fails, so it
+    /// is safe to call from interrupt context.
     fn wake_task(&self) {
-        self.wake_queue.push(self.task_id).expect("wake_queue full");
+        self.header.record_wake();
+        self.wake_queue.push(&self.header);
     }
 }
 
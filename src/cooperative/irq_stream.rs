@@ -0,0 +1,162 @@
+//! Bridge from interrupt handlers to tasks.
+//!
+//! An `IrqSource` lives in a static next to the driver. Its interrupt
+//! handler pushes events into it, and every task subscribed through
+//! `IrqSource::subscribe` receives them as a `Stream`.
+
+use alloc::sync::Arc;
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crossbeam_queue::ArrayQueue;
+use futures_util::{stream::Stream, task::AtomicWaker};
+use spin::RwLock;
+
+/// Maximum number of streams subscribed to one source.
+pub const MAX_SUBSCRIBERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqStreamError {
+    TooManySubscribers,
+}
+
+struct Subscriber<T> {
+    /// Allocated on subscription, so pushing never allocates.
+    queue: ArrayQueue<T>,
+    waker: AtomicWaker,
+    /// Events dropped because `queue` was full.
+    overflows: AtomicU64,
+}
+
+/// Events raised by an interrupt handler.
+pub struct IrqSource<T> {
+    name: &'static str,
+    // Written with interrupts disabled only, so the handler never finds
+    // it locked.
+    subscribers: RwLock<[Option<Arc<Subscriber<T>>>; MAX_SUBSCRIBERS]>,
+    events: AtomicU64,
+    overflows: AtomicU64,
+}
+
+impl<T> IrqSource<T> {
+    pub const fn new(name: &'static str) -> IrqSource<T> {
+        IrqSource {
+            name,
+            subscribers: RwLock::new([None, None, None, None, None, None, None, None]),
+            events: AtomicU64::new(0),
+            overflows: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Number of events pushed, including those nobody received.
+    pub fn events(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Number of events lost by any subscriber because its buffer was full.
+    pub fn overflows(&self) -> u64 {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.read().iter().filter(|slot| slot.is_some()).count()
+    }
+}
+
+impl<T: Copy + Send> IrqSource<T> {
+    /// Hand `event` to every subscriber.
+    ///
+    /// Called by the interrupt handler, or with interrupts disabled. Must not
+    /// block or allocate.
+    pub fn push(&self, event: T) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        for subscriber in self.subscribers.read().iter().flatten() {
+            if subscriber.queue.push(event).is_err() {
+                subscriber.overflows.fetch_add(1, Ordering::Relaxed);
+                self.overflows.fetch_add(1, Ordering::Relaxed);
+            }
+            subscriber.waker.wake();
+        }
+    }
+
+    /// Receive events pushed from now on, buffering up to `capacity` of them.
+    pub fn subscribe(&'static self, capacity: usize) -> Result<IrqStream<T>, IrqStreamError> {
+        let subscriber = Arc::new(Subscriber {
+            queue: ArrayQueue::new(capacity),
+            waker: AtomicWaker::new(),
+            overflows: AtomicU64::new(0),
+        });
+
+        crate::arch::no_interrupts(|| {
+            let mut subscribers = self.subscribers.write();
+            let slot = subscribers
+                .iter()
+                .position(Option::is_none)
+                .ok_or(IrqStreamError::TooManySubscribers)?;
+            subscribers[slot] = Some(subscriber.clone());
+            Ok(IrqStream {
+                source: self,
+                subscriber,
+                slot,
+            })
+        })
+    }
+}
+
+/// Events of an `IrqSource`, unsubscribed when dropped.
+pub struct IrqStream<T> {
+    source: &'static IrqSource<T>,
+    subscriber: Arc<Subscriber<T>>,
+    slot: usize,
+}
+
+impl<T> IrqStream<T> {
+    pub fn source(&self) -> &'static IrqSource<T> {
+        self.source
+    }
+
+    /// Take the next buffered event without waiting.
+    pub fn try_next(&mut self) -> Option<T> {
+        self.subscriber.queue.pop().ok()
+    }
+
+    /// Number of events this stream lost because its buffer was full.
+    pub fn overflows(&self) -> u64 {
+        self.subscriber.overflows.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Stream for IrqStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        if let Some(event) = self.try_next() {
+            return Poll::Ready(Some(event));
+        }
+
+        self.subscriber.waker.register(cx.waker());
+        match self.try_next() {
+            Some(event) => {
+                self.subscriber.waker.take();
+                Poll::Ready(Some(event))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for IrqStream<T> {
+    fn drop(&mut self) {
+        // The handler may be walking the subscribers.
+        crate::arch::no_interrupts(|| {
+            self.source.subscribers.write()[self.slot] = None;
+        });
+    }
+}
@@ -0,0 +1,137 @@
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::cooperative::task::{TaskHeader, TaskId};
+
+/// Reason a task did not produce an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// The task was dropped before it completed.
+    Cancelled,
+}
+
+enum State<T> {
+    Running,
+    Finished(T),
+    Cancelled,
+    Joined,
+}
+
+struct Shared<T> {
+    state: spin::Mutex<(State<T>, Option<Waker>)>,
+}
+
+impl<T> Shared<T> {
+    /// Store the final state of the task and wake the joiner, unless the task
+    /// already stopped.
+    fn finish(&self, state: State<T>) {
+        let waker = {
+            let mut guard = self.state.lock();
+            if let State::Running = guard.0 {
+                guard.0 = state;
+                guard.1.take()
+            } else {
+                None
+            }
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Create the two ends connecting a task to its `JoinHandle`.
+pub(crate) fn pair<T>(id: TaskId, header: Arc<TaskHeader>) -> (Completion<T>, JoinHandle<T>) {
+    let shared = Arc::new(Shared {
+        state: spin::Mutex::new((State::Running, None)),
+    });
+    let completion = Completion {
+        shared: shared.clone(),
+    };
+    (completion, JoinHandle { id, header, shared })
+}
+
+/// Task side of a `JoinHandle`.
+///
+/// Dropping it without calling `complete` marks the task as cancelled.
+pub(crate) struct Completion<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Completion<T> {
+    pub(crate) fn complete(self, output: T) {
+        self.shared.finish(State::Finished(output));
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        self.shared.finish(State::Cancelled);
+    }
+}
+
+/// Handle to await the output of a spawned task.
+///
+/// Dropping the handle detaches the task: it keeps running and its output
+/// is discarded.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    header: Arc<TaskHeader>,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn task_id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Whether the task completed or was cancelled.
+    pub fn is_finished(&self) -> bool {
+        match self.shared.state.lock().0 {
+            State::Running => false,
+            _ => true,
+        }
+    }
+
+    /// Let the task run to completion without awaiting it.
+    pub fn detach(self) {}
+
+    /// Drop the task the next time the executor schedules it.
+    ///
+    /// Awaiting the handle afterwards yields `JoinError::Cancelled`, unless the
+    /// task completed first.
+    pub fn abort(&self) {
+        self.header.abort();
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut guard = self.shared.state.lock();
+        match core::mem::replace(&mut guard.0, State::Joined) {
+            State::Running => {
+                guard.0 = State::Running;
+                guard.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            State::Finished(output) => Poll::Ready(Ok(output)),
+            State::Cancelled => Poll::Ready(Err(JoinError::Cancelled)),
+            State::Joined => panic!("JoinHandle polled after completion"),
+        }
+    }
+}
+
+impl<T> core::fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
@@ -1,62 +1,49 @@
-use conquer_once::spin::OnceCell;
-use crossbeam_queue::ArrayQueue;
-
 use core::{pin::Pin, task::{Poll, Context}};
-use futures_util::{
-    stream::Stream,
-    task::AtomicWaker,
-};
+use futures_util::stream::Stream;
+
+use crate::cooperative::irq_stream::{IrqSource, IrqStream};
 
-use crate::println;
+/// Number of scancodes buffered per `ScancodeStream`.
+pub const SCANCODE_BUFFER_SIZE: usize = 100;
 
-static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
-static WAKER: AtomicWaker = AtomicWaker::new();
+pub static SCANCODES: IrqSource<u8> = IrqSource::new("keyboard");
 
 /// Called by the keyboard interrupt handler
 ///
 /// Must not block or allocate.
 pub(crate) fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        } else {
-            WAKER.wake();
-        }
-    } else {
-        println!("WARNING: scancode queue uninitialized");
-    }
+    SCANCODES.push(scancode);
 }
 
+/// Scancodes received from now on.
+///
+/// Every stream sees all scancodes, and can be created again once dropped.
 pub struct ScancodeStream {
-    _private: (),
+    events: IrqStream<u8>,
 }
 
 impl ScancodeStream {
+    /// Panics if too many streams exist, see `try_new`.
     pub fn new() -> Self {
-        SCANCODE_QUEUE.try_init_once(|| ArrayQueue::new(100))
-            .expect("ScancodeStream::new should only be called once");
-        ScancodeStream { _private: () }
+        Self::try_new().expect("too many ScancodeStreams")
+    }
+
+    pub fn try_new() -> Option<Self> {
+        SCANCODES.subscribe(SCANCODE_BUFFER_SIZE)
+            .ok()
+            .map(|events| ScancodeStream { events })
+    }
+
+    /// Number of scancodes this stream lost because it was not read in time.
+    pub fn overflows(&self) -> u64 {
+        self.events.overflows()
     }
 }
 
 impl Stream for ScancodeStream {
     type Item = u8;
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        let que = SCANCODE_QUEUE.try_get().expect("not initialized");
-
-        if let Ok(scancode) = que.pop() {
-            return Poll::Ready(Some(scancode));
-        }
-
-        WAKER.register(&cx.waker());
-
-        match que.pop() {
-            Ok(scancode) => {
-                WAKER.take();
-                Poll::Ready(Some(scancode))
-            },
-            Err(_) => Poll::Pending
-        }
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
     }
 }
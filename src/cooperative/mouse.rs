@@ -0,0 +1,77 @@
+use core::{pin::Pin, task::{Poll, Context}};
+use futures_util::stream::Stream;
+
+use crate::cooperative::irq_stream::{IrqSource, IrqStream};
+use crate::mouse::{MouseEvent, PacketDecoder};
+
+/// Number of bytes buffered per `MouseStream`, enough for about a second of
+/// packets at the default sample rate.
+pub const MOUSE_BUFFER_SIZE: usize = 512;
+
+pub static MOUSE_BYTES: IrqSource<u8> = IrqSource::new("mouse");
+
+/// Called by the mouse interrupt handler
+///
+/// Must not block or allocate.
+pub(crate) fn add_byte(byte: u8) {
+    MOUSE_BYTES.push(byte);
+}
+
+/// Mouse events from now on.
+///
+/// Every stream sees all events, and can be created again once dropped.
+pub struct MouseStream {
+    bytes: IrqStream<u8>,
+    decoder: PacketDecoder,
+}
+
+impl MouseStream {
+    /// Panics if too many streams exist, see `try_new`.
+    pub fn new() -> Self {
+        Self::try_new().expect("too many MouseStreams")
+    }
+
+    pub fn try_new() -> Option<Self> {
+        let format = crate::arch::ps2::mouse::packet_format();
+        MOUSE_BYTES.subscribe(MOUSE_BUFFER_SIZE)
+            .ok()
+            .map(|bytes| MouseStream {
+                bytes,
+                decoder: PacketDecoder::new(format),
+            })
+    }
+
+    /// The next event if its packet is complete, without waiting.
+    pub fn try_next(&mut self) -> Option<MouseEvent> {
+        while let Some(byte) = self.bytes.try_next() {
+            if let Some(event) = self.decoder.add_byte(byte) {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Number of bytes this stream lost because it was not read in time.
+    pub fn overflows(&self) -> u64 {
+        self.bytes.overflows()
+    }
+}
+
+impl Stream for MouseStream {
+    type Item = MouseEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match Pin::new(&mut this.bytes).poll_next(cx) {
+                Poll::Ready(Some(byte)) => {
+                    if let Some(event) = this.decoder.add_byte(byte) {
+                        return Poll::Ready(Some(event));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
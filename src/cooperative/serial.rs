@@ -0,0 +1,146 @@
+use alloc::string::String;
+use core::{pin::Pin, task::{Poll, Context}};
+use futures_util::stream::{Stream, StreamExt};
+
+use crate::cooperative::irq_stream::{IrqSource, IrqStream};
+use crate::serial::{self, ComPort};
+
+/// Number of bytes buffered per `SerialStream`, enough for a pasted line.
+pub const SERIAL_BUFFER_SIZE: usize = 256;
+
+/// Longest line returned by `LineReader`, further bytes are dropped.
+pub const MAX_LINE_LEN: usize = 1024;
+
+/// Bytes received on each port, indexed by `ComPort::index`.
+pub static SERIAL_INPUTS: [IrqSource<u8>; 4] = [
+    IrqSource::new("com1"),
+    IrqSource::new("com2"),
+    IrqSource::new("com3"),
+    IrqSource::new("com4"),
+];
+
+/// Called by the serial interrupt handler
+///
+/// Must not block or allocate.
+pub(crate) fn add_byte(com: ComPort, byte: u8) {
+    SERIAL_INPUTS[com.index()].push(byte);
+}
+
+/// Bytes received on a port from now on.
+///
+/// Every stream sees all bytes, and can be created again once dropped.
+pub struct SerialStream {
+    com: ComPort,
+    bytes: IrqStream<u8>,
+}
+
+impl SerialStream {
+    /// Bytes received on COM1.
+    ///
+    /// Panics if too many streams exist, see `try_new`.
+    pub fn new() -> Self {
+        Self::try_new(ComPort::Com1).expect("too many SerialStreams")
+    }
+
+    pub fn try_new(com: ComPort) -> Option<Self> {
+        SERIAL_INPUTS[com.index()].subscribe(SERIAL_BUFFER_SIZE)
+            .ok()
+            .map(|bytes| SerialStream { com, bytes })
+    }
+
+    pub fn port(&self) -> ComPort {
+        self.com
+    }
+
+    pub fn try_next(&mut self) -> Option<u8> {
+        self.bytes.try_next()
+    }
+
+    /// Number of bytes this stream lost because it was not read in time.
+    pub fn overflows(&self) -> u64 {
+        self.bytes.overflows()
+    }
+}
+
+impl Stream for SerialStream {
+    type Item = u8;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.bytes).poll_next(cx)
+    }
+}
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// Reads lines typed in a terminal connected to a serial port.
+///
+/// Handles backspace and `\r`, `\n` or `\r\n` line endings, and can echo
+/// what is typed, since terminals in raw mode do not.
+pub struct LineReader {
+    stream: SerialStream,
+    echo: bool,
+    line: String,
+    /// The last line ended with `\r`, so a following `\n` is skipped.
+    after_cr: bool,
+}
+
+impl LineReader {
+    pub fn new(stream: SerialStream) -> LineReader {
+        LineReader {
+            stream,
+            echo: false,
+            line: String::new(),
+            after_cr: false,
+        }
+    }
+
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// The next line, without its line ending.
+    ///
+    /// Only returns `None` if the stream ends, never in practice. Bytes that
+    /// are not ASCII are dropped.
+    pub async fn read_line(&mut self) -> Option<String> {
+        loop {
+            let byte = self.stream.next().await?;
+            if let Some(line) = self.add_byte(byte) {
+                return Some(line);
+            }
+        }
+    }
+
+    fn echo(&self, bytes: &[u8]) {
+        if self.echo {
+            serial::write(self.stream.com, bytes);
+        }
+    }
+
+    fn add_byte(&mut self, byte: u8) -> Option<String> {
+        let after_cr = core::mem::replace(&mut self.after_cr, false);
+        match byte {
+            b'\n' if after_cr => None,
+            b'\r' | b'\n' => {
+                self.after_cr = byte == b'\r';
+                self.echo(b"\r\n");
+                Some(core::mem::replace(&mut self.line, String::new()))
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() {
+                    self.echo(b"\x08 \x08");
+                }
+                None
+            }
+            byte if byte.is_ascii() && !byte.is_ascii_control() => {
+                if self.line.len() < MAX_LINE_LEN {
+                    self.line.push(byte as char);
+                    self.echo(&[byte]);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
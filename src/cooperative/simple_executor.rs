@@ -37,6 +37,9 @@ impl SimpleExecutor {
 
     pub fn run(&mut self) {
         while let Some(mut task) = self.tasks.pop_front() {
+            if task.is_aborted() {
+                continue;
+            }
             let waker = dummy_waker();
             let mut context = Context::from_waker(&waker);
             match task.poll(&mut context) {
@@ -0,0 +1,50 @@
+//! Synchronization primitives for tasks.
+//!
+//! Unlike `spin::Mutex`, waiting for these yields to the executor, so they
+//! can be held across `.await` points. Waiters are served in FIFO order.
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use futures_util::task::AtomicWaker;
+
+pub mod semaphore;
+pub mod mutex;
+pub mod rwlock;
+pub mod notify;
+pub mod barrier;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::Notify;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+
+/// A task waiting in the queue of a primitive.
+struct Waiter {
+    /// Permits requested from a `Semaphore`.
+    permits: usize,
+    /// Zero while waiting, set to a primitive specific value under the
+    /// primitive's lock once the waiter may proceed.
+    grant: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+impl Waiter {
+    fn new(permits: usize) -> Arc<Waiter> {
+        Arc::new(Waiter {
+            permits,
+            grant: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+        })
+    }
+
+    fn grant(&self, grant: usize) {
+        self.grant.store(grant, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    fn granted(&self) -> usize {
+        self.grant.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,103 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::Waiter;
+
+struct State {
+    arrived: usize,
+    waiters: Vec<Arc<Waiter>>,
+}
+
+/// Lets a fixed number of tasks wait until all of them reached a point.
+///
+/// The barrier can be reused once all tasks passed it.
+pub struct Barrier {
+    parties: usize,
+    state: spin::Mutex<State>,
+}
+
+/// Whether the task was the one releasing the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    pub fn new(parties: usize) -> Barrier {
+        Barrier {
+            parties: core::cmp::max(parties, 1),
+            state: spin::Mutex::new(State {
+                arrived: 0,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// Wait until `parties` tasks are waiting.
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            waiter: None,
+        }
+    }
+}
+
+/// Future returned by `Barrier::wait`.
+///
+/// Dropping it before the barrier opens withdraws the task.
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<BarrierWaitResult> {
+        if let Some(waiter) = &self.waiter {
+            waiter.waker.register(cx.waker());
+            if waiter.granted() == 0 {
+                return Poll::Pending;
+            }
+            self.waiter = None;
+            return Poll::Ready(BarrierWaitResult(false));
+        }
+
+        let mut state = self.barrier.state.lock();
+        state.arrived += 1;
+        if state.arrived == self.barrier.parties {
+            state.arrived = 0;
+            for waiter in state.waiters.drain(..) {
+                waiter.grant(1);
+            }
+            return Poll::Ready(BarrierWaitResult(true));
+        }
+
+        let waiter = Waiter::new(0);
+        waiter.waker.register(cx.waker());
+        state.waiters.push(waiter.clone());
+        drop(state);
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            let mut state = self.barrier.state.lock();
+            if waiter.granted() == 0 {
+                state.arrived -= 1;
+                state.waiters.retain(|queued| !Arc::ptr_eq(queued, &waiter));
+            }
+        }
+    }
+}
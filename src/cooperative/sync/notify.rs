@@ -0,0 +1,122 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::Waiter;
+
+/// Grant of a waiter woken by `notify_one`.
+const NOTIFIED_ONE: usize = 1;
+/// Grant of a waiter woken by `notify_all`.
+const NOTIFIED_ALL: usize = 2;
+
+struct State {
+    /// A `notify_one` call found nobody waiting.
+    pending: bool,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+/// Event that wakes tasks waiting on it.
+///
+/// A `notify_one` without waiters is stored, and completes the next
+/// `notified` call right away.
+pub struct Notify {
+    state: spin::Mutex<State>,
+}
+
+impl Notify {
+    pub fn new() -> Notify {
+        Notify {
+            state: spin::Mutex::new(State {
+                pending: false,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Wait for a notification.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            waiter: None,
+        }
+    }
+
+    /// Wake the longest waiting task, or store the notification.
+    pub fn notify_one(&self) {
+        let mut state = self.state.lock();
+        match state.waiters.pop_front() {
+            Some(waiter) => waiter.grant(NOTIFIED_ONE),
+            None => state.pending = true,
+        }
+    }
+
+    /// Wake all tasks waiting right now.
+    pub fn notify_all(&self) {
+        let mut state = self.state.lock();
+        for waiter in state.waiters.drain(..) {
+            waiter.grant(NOTIFIED_ALL);
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Notify {
+        Notify::new()
+    }
+}
+
+/// Future returned by `Notify::notified`.
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        match &self.waiter {
+            Some(waiter) => {
+                waiter.waker.register(cx.waker());
+                if waiter.granted() == 0 {
+                    return Poll::Pending;
+                }
+            }
+            None => {
+                let mut state = self.notify.state.lock();
+                if state.pending {
+                    state.pending = false;
+                } else {
+                    let waiter = Waiter::new(0);
+                    waiter.waker.register(cx.waker());
+                    state.waiters.push_back(waiter.clone());
+                    drop(state);
+                    self.waiter = Some(waiter);
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        self.waiter = None;
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            let granted = {
+                let mut state = self.notify.state.lock();
+                state.waiters.retain(|queued| !Arc::ptr_eq(queued, &waiter));
+                waiter.granted()
+            };
+            // Pass on a notification meant for a single task.
+            if granted == NOTIFIED_ONE {
+                self.notify.notify_one();
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::Waiter;
+
+struct State {
+    permits: usize,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+impl State {
+    /// Hand permits to waiters at the front of the queue.
+    fn grant_waiters(&mut self) {
+        while let Some(waiter) = self.waiters.front() {
+            if waiter.permits > self.permits {
+                break;
+            }
+            self.permits -= waiter.permits;
+            self.waiters.pop_front().expect("should exist").grant(1);
+        }
+    }
+
+    fn remove(&mut self, waiter: &Arc<Waiter>) {
+        self.waiters.retain(|queued| !Arc::ptr_eq(queued, waiter));
+    }
+}
+
+/// Counting semaphore.
+///
+/// Permits are handed out in request order: a large request at the front
+/// of the queue holds back smaller ones behind it.
+pub struct Semaphore {
+    state: spin::Mutex<State>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            state: spin::Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Wait until `permits` permits are available and take them.
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            permits,
+            waiter: None,
+        }
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Take `permits` permits if they are available and nobody is waiting.
+    pub fn try_acquire_many(&self, permits: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock();
+        if state.waiters.is_empty() && state.permits >= permits {
+            state.permits -= permits;
+            Some(SemaphorePermit::new(self, permits))
+        } else {
+            None
+        }
+    }
+
+    /// Return `permits` permits to the semaphore.
+    pub fn add_permits(&self, permits: usize) {
+        let mut state = self.state.lock();
+        state.permits += permits;
+        state.grant_waiters();
+    }
+}
+
+/// Future returned by `Semaphore::acquire`.
+///
+/// Dropping it gives up its place in the queue.
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+        let permits = self.permits;
+
+        match &self.waiter {
+            Some(waiter) => {
+                waiter.waker.register(cx.waker());
+                if waiter.granted() == 0 {
+                    return Poll::Pending;
+                }
+            }
+            None => {
+                let mut state = semaphore.state.lock();
+                if state.waiters.is_empty() && state.permits >= permits {
+                    state.permits -= permits;
+                } else {
+                    let waiter = Waiter::new(permits);
+                    waiter.waker.register(cx.waker());
+                    state.waiters.push_back(waiter.clone());
+                    drop(state);
+                    self.waiter = Some(waiter);
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        self.waiter = None;
+        Poll::Ready(SemaphorePermit::new(semaphore, permits))
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            let mut state = self.semaphore.state.lock();
+            if waiter.granted() != 0 {
+                // Granted, but never observed.
+                state.permits += self.permits;
+            } else {
+                state.remove(&waiter);
+            }
+            // Waiters queued behind a large request may proceed now.
+            state.grant_waiters();
+        }
+    }
+}
+
+/// Permits taken from a `Semaphore`, returned when dropped.
+#[must_use]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl<'a> SemaphorePermit<'a> {
+    fn new(semaphore: &'a Semaphore, permits: usize) -> SemaphorePermit<'a> {
+        SemaphorePermit {
+            semaphore,
+            permits,
+        }
+    }
+
+    /// Keep the permits taken for good.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits != 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
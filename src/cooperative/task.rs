@@ -1,5 +1,6 @@
 use core::{
-    sync::atomic::{AtomicU64, Ordering},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
     task::{
         Context,
         Poll,
@@ -7,7 +8,11 @@ use core::{
     future::Future,
     pin::Pin
 };
-use alloc::boxed::Box;
+use alloc::{boxed::Box, sync::Arc};
+
+use futures_util::task::AtomicWaker;
+
+use crate::cooperative::join_handle::{self, JoinHandle};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TaskId(u64);
@@ -25,28 +30,221 @@ impl TaskId {
     pub unsafe fn from_u64(id: u64) -> TaskId {
         TaskId(id)
     }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Number of `Priority` classes.
+pub const PRIORITY_CLASSES: usize = 3;
+
+/// Scheduling class of a task.
+///
+/// Ready tasks of a higher class are polled first. Tasks of the same class
+/// are polled in the order they became ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Deferred work of interrupt handlers, e.g. draining input queues.
+    BottomHalf = 0,
+    Normal = 1,
+    /// Work that should only run when nothing else is ready.
+    Background = 2,
+}
+
+impl Priority {
+    /// All classes, highest first.
+    pub const ALL: [Priority; PRIORITY_CLASSES] = [Priority::BottomHalf, Priority::Normal, Priority::Background];
+}
+
+impl Default for Priority {
+    fn default() -> Priority {
+        Priority::Normal
+    }
+}
+
+/// Settings applied to a task when it is spawned.
+#[derive(Debug, Clone, Default)]
+pub struct TaskOptions {
+    pub priority: Priority,
+    /// Shown in executor dumps and watchdog warnings.
+    pub name: Option<&'static str>,
+}
+
+/// Scheduling statistics of a task, times in TSC cycles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskStats {
+    pub polls: u64,
+    pub total_poll_cycles: u64,
+    pub max_poll_cycles: u64,
+    /// Timestamp of the last wakeup.
+    pub last_wake: Option<u64>,
+}
+
+impl TaskStats {
+    pub fn average_poll_cycles(&self) -> u64 {
+        if self.polls == 0 {
+            0
+        } else {
+            self.total_poll_cycles / self.polls
+        }
+    }
+}
+
+/// State shared between a task and the handles pointing to it.
+pub(crate) struct TaskHeader {
+    id: TaskId,
+    aborted: AtomicBool,
+    /// Waker the executor last polled the task with.
+    waker: AtomicWaker,
+    /// Whether the task is linked in a `WakeQueue`.
+    pub(crate) queued: AtomicBool,
+    /// Next task in the `WakeQueue`.
+    pub(crate) next: AtomicPtr<TaskHeader>,
+    /// Timestamp of the last wakeup, zero if never woken.
+    last_wake: AtomicU64,
+}
+
+impl TaskHeader {
+    fn new(id: TaskId) -> TaskHeader {
+        TaskHeader {
+            id,
+            aborted: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+            queued: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+            last_wake: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Request the task to be dropped, and wake it so the executor notices.
+    pub(crate) fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Called by wakers, safe in interrupt context.
+    pub(crate) fn record_wake(&self) {
+        let now = crate::arch::interrupt_stats::timestamp();
+        self.last_wake.store(now, Ordering::Relaxed);
+    }
+
+    fn last_wake(&self) -> Option<u64> {
+        match self.last_wake.load(Ordering::Relaxed) {
+            0 => None,
+            timestamp => Some(timestamp),
+        }
+    }
 }
 
 pub struct Task {
     id: TaskId,
+    name: Option<&'static str>,
+    priority: Priority,
+    stats: TaskStats,
+    header: Arc<TaskHeader>,
     future: Pin<Box<dyn Future<Output = ()>>>,
 }
 
 impl Task {
-    pub fn new<T: 'static + Future<Output=()>>(raw_future: T) -> Task {
-        let future = Box::pin(raw_future);
+    /// Create a task whose output is discarded.
+    pub fn new<T: 'static + Future>(raw_future: T) -> Task {
+        let future = Box::pin(async move {
+            raw_future.await;
+        });
         let id = TaskId::new();
         Task {
             id,
+            name: None,
+            priority: Priority::default(),
+            stats: TaskStats::default(),
+            header: Arc::new(TaskHeader::new(id)),
             future,
         }
     }
 
+    /// Create a task together with a handle to await its output.
+    pub fn with_join_handle<T>(raw_future: T) -> (Task, JoinHandle<T::Output>)
+    where
+        T: 'static + Future,
+        T::Output: 'static,
+    {
+        let id = TaskId::new();
+        let header = Arc::new(TaskHeader::new(id));
+        let (completion, handle) = join_handle::pair(id, header.clone());
+        let future = Box::pin(async move {
+            let output = raw_future.await;
+            completion.complete(output);
+        });
+        let task = Task {
+            id,
+            name: None,
+            priority: Priority::default(),
+            stats: TaskStats::default(),
+            header,
+            future,
+        };
+        (task, handle)
+    }
+
     pub fn id(&self) -> TaskId {
         self.id
     }
 
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.priority = priority;
+    }
+
+    pub fn set_options(&mut self, options: TaskOptions) {
+        self.priority = options.priority;
+        self.name = options.name;
+    }
+
+    pub fn stats(&self) -> TaskStats {
+        TaskStats {
+            last_wake: self.header.last_wake(),
+            ..self.stats
+        }
+    }
+
+    /// Account a poll that took `cycles` TSC cycles.
+    pub(crate) fn record_poll(&mut self, cycles: u64) {
+        self.stats.polls += 1;
+        self.stats.total_poll_cycles += cycles;
+        self.stats.max_poll_cycles = core::cmp::max(self.stats.max_poll_cycles, cycles);
+    }
+
+    pub(crate) fn header(&self) -> &Arc<TaskHeader> {
+        &self.header
+    }
+
+    /// Mark the task to be dropped the next time it is scheduled.
+    pub fn abort(&self) {
+        self.header.abort();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.header.is_aborted()
+    }
+
     pub fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.header.waker.register(context.waker());
         let future_ref = self.future.as_mut();
         future_ref.poll(context)
     }
@@ -56,6 +254,9 @@ impl core::fmt::Debug for Task {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
         f.debug_struct("Task")
             .field("id", &self.id)
+            .field("name", &self.name)
+            .field("priority", &self.priority)
+            .field("aborted", &self.is_aborted())
             .finish()
     }
 }
@@ -0,0 +1,83 @@
+use core::{pin::Pin, task::{Poll, Context}};
+use futures_util::stream::Stream;
+
+use crate::cooperative::irq_stream::{IrqSource, IrqStream};
+use crate::keyboard::{DecodedKey, KeyCode, Modifiers};
+use crate::vga_buffer::{self, TERMINAL_COUNT};
+
+/// Number of keys buffered per `TerminalInput`.
+pub const TERMINAL_INPUT_BUFFER_SIZE: usize = 64;
+
+/// Keys typed while each terminal was shown, indexed like `TERMINALS`.
+pub static TERMINAL_INPUTS: [IrqSource<DecodedKey>; TERMINAL_COUNT] = [
+    IrqSource::new("tty1"),
+    IrqSource::new("tty2"),
+    IrqSource::new("tty3"),
+    IrqSource::new("tty4"),
+    IrqSource::new("tty5"),
+    IrqSource::new("tty6"),
+];
+
+/// Alt + these keys switch to the terminal of the same index.
+const SWITCH_KEYS: [KeyCode; TERMINAL_COUNT] = [
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+];
+
+/// Handle a key from the keyboard task: Alt+F1 to Alt+F6 switch
+/// terminals, any other key goes to the input of the terminal shown.
+pub fn dispatch_key(key: DecodedKey, modifiers: &Modifiers) {
+    if let DecodedKey::RawKey(code) = key {
+        if modifiers.is_alt() {
+            if let Some(index) = SWITCH_KEYS.iter().position(|&switch| switch == code) {
+                vga_buffer::switch_terminal(index);
+                return;
+            }
+        }
+    }
+    // Sources are also pushed from interrupt handlers, which must not find
+    // one locked by the code they interrupted.
+    crate::arch::no_interrupts(|| TERMINAL_INPUTS[vga_buffer::active_terminal()].push(key));
+}
+
+/// Keys typed in a terminal from now on.
+///
+/// Every stream sees all keys, and can be created again once dropped.
+pub struct TerminalInput {
+    index: usize,
+    keys: IrqStream<DecodedKey>,
+}
+
+impl TerminalInput {
+    /// Panics if `index` is not below `TERMINAL_COUNT`.
+    pub fn try_new(index: usize) -> Option<Self> {
+        TERMINAL_INPUTS[index].subscribe(TERMINAL_INPUT_BUFFER_SIZE)
+            .ok()
+            .map(|keys| TerminalInput { index, keys })
+    }
+
+    pub fn terminal(&self) -> usize {
+        self.index
+    }
+
+    pub fn try_next(&mut self) -> Option<DecodedKey> {
+        self.keys.try_next()
+    }
+
+    /// Number of keys this stream lost because it was not read in time.
+    pub fn overflows(&self) -> u64 {
+        self.keys.overflows()
+    }
+}
+
+impl Stream for TerminalInput {
+    type Item = DecodedKey;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.keys).poll_next(cx)
+    }
+}
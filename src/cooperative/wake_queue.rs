@@ -0,0 +1,95 @@
+use alloc::sync::Arc;
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::cooperative::task::{TaskHeader, TaskId};
+
+/// Queue of woken tasks.
+///
+/// The queue is an intrusive lock-free stack threaded through the task
+/// headers, so pushing never allocates or fails and can be done from
+/// interrupt context. A task is linked at most once until the executor
+/// drains it, so repeated wakeups of the same task take no extra space and
+/// the queue grows with the number of tasks only.
+pub(crate) struct WakeQueue {
+    head: AtomicPtr<TaskHeader>,
+}
+
+impl WakeQueue {
+    pub(crate) fn new() -> WakeQueue {
+        WakeQueue {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub(crate) fn push(&self, header: &Arc<TaskHeader>) {
+        if header.queued.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // The queue owns one reference to every linked header.
+        let node = Arc::into_raw(header.clone()) as *mut TaskHeader;
+        let mut head = self.head.load(Ordering::SeqCst);
+        loop {
+            header.next.store(head, Ordering::SeqCst);
+            match self.head.compare_exchange_weak(head, node, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::SeqCst).is_null()
+    }
+
+    /// Unlink every queued task, yielding them in the order they were woken.
+    pub(crate) fn drain(&self) -> Drain {
+        // The stack is in LIFO order, reverse it.
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::SeqCst);
+        let mut reversed = ptr::null_mut();
+        while !node.is_null() {
+            let header = unsafe { &*node };
+            let next = header.next.load(Ordering::SeqCst);
+            header.next.store(reversed, Ordering::SeqCst);
+            reversed = node;
+            node = next;
+        }
+        Drain { node: reversed }
+    }
+}
+
+impl Drop for WakeQueue {
+    fn drop(&mut self) {
+        for _ in self.drain() {}
+    }
+}
+
+pub(crate) struct Drain {
+    node: *mut TaskHeader,
+}
+
+impl Iterator for Drain {
+    type Item = TaskId;
+
+    fn next(&mut self) -> Option<TaskId> {
+        if self.node.is_null() {
+            return None;
+        }
+
+        let header = unsafe { Arc::from_raw(self.node) };
+        self.node = header.next.load(Ordering::SeqCst);
+        // Only clear the flag once `next` was read: the task may be pushed
+        // again right away, overwriting it.
+        header.queued.store(false, Ordering::SeqCst);
+        Some(header.id())
+    }
+}
+
+impl Drop for Drain {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
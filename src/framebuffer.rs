@@ -0,0 +1,289 @@
+//! Graphics on a linear framebuffer with 32 bits per pixel.
+
+pub mod console;
+pub mod font;
+
+use alloc::alloc::{alloc_zeroed, Layout};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use spin::Mutex;
+
+use crate::arch::{bga, no_interrupts};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferError {
+    /// No supported display adapter.
+    NoDevice,
+    MappingFailed,
+    /// The adapter or its memory cannot do this resolution.
+    UnsupportedMode,
+    /// No mode was set yet.
+    NotInitialized,
+    /// The font could not be read before leaving text mode.
+    NoFont,
+    /// Not enough heap for the back buffer.
+    OutOfMemory,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(0xff, 0xff, 0xff);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    fn to_pixel(self) -> u32 {
+        u32::from(self.r) << 16 | u32::from(self.g) << 8 | u32::from(self.b)
+    }
+
+    fn from_pixel(pixel: u32) -> Rgb {
+        Rgb::new((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
+    }
+}
+
+/// A mode set by `set_mode`.
+///
+/// Drawing goes to a back buffer when double buffering is enabled, and
+/// shows up on `present`. Coordinates outside the screen are clipped.
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    /// Pixels from one line to the next.
+    stride: usize,
+    front: *mut u32,
+    back: Option<Vec<u32>>,
+    /// Lines of the back buffer changed since the last `present`.
+    dirty: Option<Range<usize>>,
+}
+
+// The framebuffer memory is only accessed through the `FRAMEBUFFER` lock.
+unsafe impl Send for Framebuffer {}
+
+impl Framebuffer {
+    fn new(mode: bga::Mode) -> Framebuffer {
+        Framebuffer {
+            width: mode.width,
+            height: mode.height,
+            stride: mode.stride / 4,
+            front: mode.address.as_mut_ptr(),
+            back: None,
+            dirty: None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn mark_dirty(&mut self, lines: Range<usize>) {
+        if self.back.is_some() {
+            self.dirty = Some(match self.dirty.take() {
+                Some(dirty) => dirty.start.min(lines.start)..dirty.end.max(lines.end),
+                None => lines,
+            });
+        }
+    }
+
+    fn write(&mut self, index: usize, pixel: u32) {
+        match self.back.as_mut() {
+            Some(back) => back[index] = pixel,
+            None => unsafe { self.front.add(index).write_volatile(pixel) },
+        }
+    }
+
+    fn read(&self, index: usize) -> u32 {
+        match self.back.as_ref() {
+            Some(back) => back[index],
+            None => unsafe { self.front.add(index).read_volatile() },
+        }
+    }
+
+    /// Clip a rectangle to the screen, returning its column and line ranges.
+    fn clip(&self, x: usize, y: usize, width: usize, height: usize) -> (Range<usize>, Range<usize>) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        (x.min(x_end)..x_end, y.min(y_end)..y_end)
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.write(y * self.stride + x, color.to_pixel());
+            self.mark_dirty(y..y + 1);
+        }
+    }
+
+    /// A pixel of what is being drawn, the back buffer if there is one.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x < self.width && y < self.height {
+            Some(Rgb::from_pixel(self.read(y * self.stride + x)))
+        } else {
+            None
+        }
+    }
+
+    /// A pixel as shown on the screen.
+    pub fn screen_pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x < self.width && y < self.height {
+            let pixel = unsafe { self.front.add(y * self.stride + x).read_volatile() };
+            Some(Rgb::from_pixel(pixel))
+        } else {
+            None
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        let (columns, lines) = self.clip(x, y, width, height);
+        let pixel = color.to_pixel();
+        for line in lines.clone() {
+            for column in columns.clone() {
+                self.write(line * self.stride + column, pixel);
+            }
+        }
+        self.mark_dirty(lines);
+    }
+
+    pub fn clear(&mut self, color: Rgb) {
+        self.fill_rect(0, 0, self.width, self.height, color);
+    }
+
+    /// Copy an image `width` pixels wide, given line by line, to `x`, `y`.
+    pub fn blit(&mut self, x: usize, y: usize, width: usize, pixels: &[Rgb]) {
+        if width == 0 {
+            return;
+        }
+        let (columns, lines) = self.clip(x, y, width, pixels.len() / width);
+        for line in lines.clone() {
+            let source = &pixels[(line - y) * width..];
+            for column in columns.clone() {
+                self.write(line * self.stride + column, source[column - x].to_pixel());
+            }
+        }
+        self.mark_dirty(lines);
+    }
+
+    /// Move everything up by `lines`, filling the bottom with `fill`.
+    pub fn scroll_up(&mut self, lines: usize, fill: Rgb) {
+        let lines = lines.min(self.height);
+        let moved = (self.height - lines) * self.stride;
+        let offset = lines * self.stride;
+        match self.back.as_mut() {
+            Some(back) => back.copy_within(offset..offset + moved, 0),
+            None => unsafe { core::ptr::copy(self.front.add(offset), self.front, moved) },
+        }
+        self.mark_dirty(0..self.height);
+        self.fill_rect(0, self.height - lines, self.width, lines, fill);
+    }
+
+    pub fn is_double_buffered(&self) -> bool {
+        self.back.is_some()
+    }
+
+    /// Start drawing into `back`, filled with what is on the screen. Leaves
+    /// `back` in place if there already is a back buffer or it does not fit.
+    fn install_back_buffer(&mut self, back: &mut Option<Vec<u32>>) {
+        let len = self.stride * self.height;
+        if self.back.is_some() || back.as_ref().map_or(true, |back| back.len() != len) {
+            return;
+        }
+        let mut buffer = back.take().expect("should exist");
+        unsafe { core::ptr::copy_nonoverlapping(self.front, buffer.as_mut_ptr(), len) };
+        self.back = Some(buffer);
+        self.dirty = None;
+    }
+
+    /// Copy the lines of the back buffer that changed to the screen.
+    pub fn present(&mut self) {
+        if let (Some(back), Some(lines)) = (self.back.as_ref(), self.dirty.take()) {
+            let start = lines.start * self.stride;
+            let len = (lines.end - lines.start) * self.stride;
+            unsafe {
+                core::ptr::copy_nonoverlapping(back[start..].as_ptr(), self.front.add(start), len);
+            }
+        }
+    }
+}
+
+// Only locked with interrupts disabled, as console output from interrupt
+// handlers draws on it. Nothing is allocated or freed while it is held, the
+// allocator may be locked by a preempted thread.
+pub(crate) static FRAMEBUFFER: Mutex<Option<Framebuffer>> = Mutex::new(None);
+
+/// Leave text mode for a `width` x `height` graphics mode.
+///
+/// The VGA text console stops showing anything from then on.
+pub fn set_mode(width: usize, height: usize) -> Result<(), FramebufferError> {
+    // The font is only readable in text mode.
+    font::vga_font().ok_or(FramebufferError::NoFont)?;
+    let mode = bga::set_mode(width, height)?;
+
+    let mut framebuffer = Framebuffer::new(mode);
+    framebuffer.clear(Rgb::BLACK);
+    let previous = no_interrupts(|| {
+        let previous = FRAMEBUFFER.lock().replace(framebuffer);
+        console::CONSOLE.lock().reset();
+        previous
+    });
+    // Frees the back buffer of the previous mode.
+    drop(previous);
+    Ok(())
+}
+
+/// Run `f` on the framebuffer, with interrupts disabled.
+///
+/// `f` must not allocate or free memory.
+pub fn with_framebuffer<F, R>(f: F) -> Result<R, FramebufferError>
+where
+    F: FnOnce(&mut Framebuffer) -> R,
+{
+    no_interrupts(|| {
+        FRAMEBUFFER
+            .lock()
+            .as_mut()
+            .map(f)
+            .ok_or(FramebufferError::NotInitialized)
+    })
+}
+
+/// Draw into a back buffer on the heap from now on, starting with what is
+/// on the screen.
+pub fn enable_double_buffering() -> Result<(), FramebufferError> {
+    let len = with_framebuffer(|framebuffer| framebuffer.stride * framebuffer.height)?;
+    let layout = Layout::array::<u32>(len).map_err(|_| FramebufferError::OutOfMemory)?;
+    // Allocate by hand, so running out of heap is an error and not an
+    // allocation failure panic.
+    let back = unsafe {
+        let buffer = alloc_zeroed(layout) as *mut u32;
+        if buffer.is_null() {
+            return Err(FramebufferError::OutOfMemory);
+        }
+        Vec::from_raw_parts(buffer, len, len)
+    };
+    let mut back = Some(back);
+    with_framebuffer(|framebuffer| framebuffer.install_back_buffer(&mut back))?;
+    // Not installed if another one was meanwhile.
+    drop(back);
+    Ok(())
+}
+
+/// Show the back buffer and draw to the screen directly again.
+pub fn disable_double_buffering() -> Result<(), FramebufferError> {
+    let back = with_framebuffer(|framebuffer| {
+        framebuffer.present();
+        framebuffer.back.take()
+    })?;
+    drop(back);
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Text console drawn with the VGA font on the framebuffer.
+
+use core::fmt;
+
+use spin::Mutex;
+
+use super::font::{self, Font, GLYPH_HEIGHT, GLYPH_WIDTH};
+use super::{Framebuffer, Rgb, FRAMEBUFFER};
+use crate::arch::no_interrupts;
+use crate::vga_buffer::ansi::{Parser, TextScreen, TextState};
+
+/// The 16 VGA text colors in ANSI order, the bright ones last.
+pub const PALETTE: [Rgb; 16] = [
+    Rgb::new(0x00, 0x00, 0x00),
+    Rgb::new(0xaa, 0x00, 0x00),
+    Rgb::new(0x00, 0xaa, 0x00),
+    Rgb::new(0xaa, 0x55, 0x00),
+    Rgb::new(0x00, 0x00, 0xaa),
+    Rgb::new(0xaa, 0x00, 0xaa),
+    Rgb::new(0x00, 0xaa, 0xaa),
+    Rgb::new(0xaa, 0xaa, 0xaa),
+    Rgb::new(0x55, 0x55, 0x55),
+    Rgb::new(0xff, 0x55, 0x55),
+    Rgb::new(0x55, 0xff, 0x55),
+    Rgb::new(0xff, 0xff, 0x55),
+    Rgb::new(0x55, 0x55, 0xff),
+    Rgb::new(0xff, 0x55, 0xff),
+    Rgb::new(0x55, 0xff, 0xff),
+    Rgb::new(0xff, 0xff, 0xff),
+];
+
+/// Yellow on black, like the VGA text console.
+const DEFAULT_FOREGROUND: usize = 11;
+const DEFAULT_BACKGROUND: usize = 0;
+
+/// Shown for characters missing from code page 437.
+const REPLACEMENT_CHARACTER: u8 = 0xfe;
+
+/// Text console on the framebuffer, with as many cells of
+/// `GLYPH_WIDTH` x `GLYPH_HEIGHT` pixels as fit.
+///
+/// Writes are dropped until `framebuffer::set_mode` was called.
+pub struct Console {
+    /// Colors are `PALETTE` indices.
+    text: TextState,
+    parser: Parser,
+}
+
+/// The framebuffer and font a write draws with.
+struct Screen<'a> {
+    framebuffer: &'a mut Framebuffer,
+    font: &'static Font,
+    columns: usize,
+    rows: usize,
+}
+
+impl Console {
+    const fn new() -> Console {
+        Console {
+            text: TextState::new(0, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+            parser: Parser::new(),
+        }
+    }
+
+    /// Start over at the top left with the default colors.
+    pub fn reset(&mut self) {
+        *self = Console::new();
+    }
+
+    /// Size in characters, `(columns, rows)`.
+    pub fn size(&self) -> Option<(usize, usize)> {
+        no_interrupts(|| {
+            FRAMEBUFFER.lock().as_ref().map(|framebuffer| {
+                (framebuffer.width() / GLYPH_WIDTH, framebuffer.height() / GLYPH_HEIGHT)
+            })
+        })
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        no_interrupts(|| self.draw(s));
+    }
+
+    fn draw(&mut self, s: &str) {
+        let mut framebuffer = FRAMEBUFFER.lock();
+        // The font was loaded by `set_mode`, so this does not touch the
+        // VGA registers.
+        let (framebuffer, font) = match framebuffer.as_mut() {
+            Some(framebuffer) => match font::vga_font() {
+                Some(font) => (framebuffer, font),
+                None => return,
+            },
+            None => return,
+        };
+        let mut screen = Screen {
+            columns: framebuffer.width() / GLYPH_WIDTH,
+            rows: framebuffer.height() / GLYPH_HEIGHT,
+            framebuffer,
+            font,
+        };
+        if screen.columns == 0 || screen.rows == 0 {
+            return;
+        }
+
+        for c in s.chars() {
+            let byte = if c.is_ascii() { c as u8 } else { REPLACEMENT_CHARACTER };
+            if let Some(action) = self.parser.advance(byte) {
+                self.text.perform(&mut screen, action);
+            }
+        }
+        screen.framebuffer.present();
+    }
+}
+
+/// `(foreground, background)` to draw with.
+fn colors(text: &TextState) -> (Rgb, Rgb) {
+    let (foreground, background) = text.colors();
+    (PALETTE[foreground], PALETTE[background])
+}
+
+impl TextScreen for Screen<'_> {
+    fn size(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    fn put(&mut self, text: &TextState, row: usize, column: usize, byte: u8) {
+        let (foreground, background) = colors(text);
+        let glyph = self.font.glyph(byte);
+        let (x, y) = (column * GLYPH_WIDTH, row * GLYPH_HEIGHT);
+        for (line, &bits) in glyph.iter().enumerate() {
+            for pixel in 0..GLYPH_WIDTH {
+                let color = if bits & (0x80 >> pixel) != 0 { foreground } else { background };
+                self.framebuffer.set_pixel(x + pixel, y + line, color);
+            }
+        }
+    }
+
+    fn clear(&mut self, text: &TextState, row: usize, columns: core::ops::Range<usize>) {
+        let (_, background) = colors(text);
+        self.framebuffer.fill_rect(
+            columns.start * GLYPH_WIDTH,
+            row * GLYPH_HEIGHT,
+            (columns.end - columns.start) * GLYPH_WIDTH,
+            GLYPH_HEIGHT,
+            background,
+        );
+    }
+
+    fn scroll_up(&mut self, text: &TextState) {
+        let (_, background) = colors(text);
+        self.framebuffer.scroll_up(GLYPH_HEIGHT, background);
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+// Only locked with interrupts disabled, like `FRAMEBUFFER` which is locked
+// while holding it.
+pub(crate) static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
+
+/// Run `f` on the console, with interrupts disabled.
+pub fn with_console<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Console) -> R,
+{
+    no_interrupts(|| f(&mut CONSOLE.lock()))
+}
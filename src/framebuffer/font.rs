@@ -0,0 +1,81 @@
+//! The 8x16 font of the VGA text mode, read back from the adapter.
+
+use spin::Once;
+use x86_64::PhysAddr;
+
+use crate::arch::{memory, no_interrupts, write_port};
+
+pub const GLYPH_WIDTH: usize = 8;
+pub const GLYPH_HEIGHT: usize = 16;
+
+/// The text mode font lives in plane 2, 32 bytes per glyph.
+const FONT_PLANE_ADDRESS: u64 = 0xa0000;
+const PLANE_GLYPH_STRIDE: usize = 32;
+
+const SEQUENCER_INDEX: u16 = 0x3c4;
+const GRAPHICS_INDEX: u16 = 0x3ce;
+
+/// Index in the low byte, value in the high byte, written to the index
+/// port as one word.
+const SELECT_PLANE_2: [(u16, u16); 5] = [
+    (SEQUENCER_INDEX, 0x0402), // write plane 2
+    (SEQUENCER_INDEX, 0x0704), // sequential addressing
+    (GRAPHICS_INDEX, 0x0204),  // read plane 2
+    (GRAPHICS_INDEX, 0x0005),  // no odd/even
+    (GRAPHICS_INDEX, 0x0406),  // memory at 0xa0000
+];
+/// Standard text mode values of the same registers.
+const RESTORE_TEXT_MODE: [(u16, u16); 5] = [
+    (SEQUENCER_INDEX, 0x0302),
+    (SEQUENCER_INDEX, 0x0304),
+    (GRAPHICS_INDEX, 0x0004),
+    (GRAPHICS_INDEX, 0x1005),
+    (GRAPHICS_INDEX, 0x0e06),
+];
+
+/// Glyphs of code page 437, one byte per line with the leftmost pixel in
+/// the high bit.
+pub struct Font {
+    glyphs: [[u8; GLYPH_HEIGHT]; 256],
+}
+
+impl Font {
+    pub fn glyph(&self, character: u8) -> &[u8; GLYPH_HEIGHT] {
+        &self.glyphs[usize::from(character)]
+    }
+}
+
+static FONT: Once<Font> = Once::new();
+
+fn read_vga_font() -> Font {
+    let plane = memory::phys_to_virt(PhysAddr::new(FONT_PLANE_ADDRESS))
+        .expect("physical memory is not mapped")
+        .as_ptr::<u8>();
+    let mut font = Font {
+        glyphs: [[0; GLYPH_HEIGHT]; 256],
+    };
+
+    no_interrupts(|| {
+        for &(port, value) in SELECT_PLANE_2.iter() {
+            write_port(port, value);
+        }
+        for (i, glyph) in font.glyphs.iter_mut().enumerate() {
+            for (line, byte) in glyph.iter_mut().enumerate() {
+                *byte = unsafe { plane.add(i * PLANE_GLYPH_STRIDE + line).read_volatile() };
+            }
+        }
+        for &(port, value) in RESTORE_TEXT_MODE.iter() {
+            write_port(port, value);
+        }
+    });
+    font
+}
+
+/// The VGA font, which is read on the first call and must be while the
+/// adapter is still in text mode.
+///
+/// Returns `None` before the physical memory mapping is set up.
+pub fn vga_font() -> Option<&'static Font> {
+    memory::phys_to_virt(PhysAddr::new(FONT_PLANE_ADDRESS))?;
+    Some(FONT.call_once(read_vga_font))
+}
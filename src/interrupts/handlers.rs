@@ -1,13 +1,161 @@
 use crate::{println, print};
 
+use spin::RwLock;
+
+use crate::arch::{REGISTER_COUNT, REGISTER_NAMES};
+
+/// CPU exceptions that can be routed to a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    DivideError,
+    Debug,
+    NonMaskableInterrupt,
+    Breakpoint,
+    Overflow,
+    BoundRangeExceeded,
+    InvalidOpcode,
+    DeviceNotAvailable,
+    DoubleFault,
+    CoprocessorSegmentOverrun,
+    InvalidTss,
+    SegmentNotPresent,
+    StackSegmentFault,
+    GeneralProtectionFault,
+    PageFault,
+    X87FloatingPoint,
+    AlignmentCheck,
+    MachineCheck,
+    SimdFloatingPoint,
+    Virtualization,
+    ControlProtection,
+    HypervisorInjection,
+    VmmCommunication,
+    SecurityException,
+    Reserved,
+}
+
+const EXCEPTION_COUNT: usize = Exception::Reserved as usize + 1;
+
+impl Exception {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exception::DivideError => "Divide Error",
+            Exception::Debug => "Debug",
+            Exception::NonMaskableInterrupt => "Non-maskable Interrupt",
+            Exception::Breakpoint => "Breakpoint",
+            Exception::Overflow => "Overflow",
+            Exception::BoundRangeExceeded => "Bound Range Exceeded",
+            Exception::InvalidOpcode => "Invalid Opcode",
+            Exception::DeviceNotAvailable => "Device Not Available",
+            Exception::DoubleFault => "Double Fault",
+            Exception::CoprocessorSegmentOverrun => "Coprocessor Segment Overrun",
+            Exception::InvalidTss => "Invalid TSS",
+            Exception::SegmentNotPresent => "Segment Not Present",
+            Exception::StackSegmentFault => "Stack-Segment Fault",
+            Exception::GeneralProtectionFault => "General Protection Fault",
+            Exception::PageFault => "Page Fault",
+            Exception::X87FloatingPoint => "x87 Floating-Point Exception",
+            Exception::AlignmentCheck => "Alignment Check",
+            Exception::MachineCheck => "Machine Check",
+            Exception::SimdFloatingPoint => "SIMD Floating-Point Exception",
+            Exception::Virtualization => "Virtualization Exception",
+            Exception::ControlProtection => "Control Protection Exception",
+            Exception::HypervisorInjection => "Hypervisor Injection Exception",
+            Exception::VmmCommunication => "VMM Communication Exception",
+            Exception::SecurityException => "Security Exception",
+            Exception::Reserved => "Reserved",
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Exception::DivideError => "#DE",
+            Exception::Debug => "#DB",
+            Exception::NonMaskableInterrupt => "NMI",
+            Exception::Breakpoint => "#BP",
+            Exception::Overflow => "#OF",
+            Exception::BoundRangeExceeded => "#BR",
+            Exception::InvalidOpcode => "#UD",
+            Exception::DeviceNotAvailable => "#NM",
+            Exception::DoubleFault => "#DF",
+            Exception::CoprocessorSegmentOverrun => "CSO",
+            Exception::InvalidTss => "#TS",
+            Exception::SegmentNotPresent => "#NP",
+            Exception::StackSegmentFault => "#SS",
+            Exception::GeneralProtectionFault => "#GP",
+            Exception::PageFault => "#PF",
+            Exception::X87FloatingPoint => "#MF",
+            Exception::AlignmentCheck => "#AC",
+            Exception::MachineCheck => "#MC",
+            Exception::SimdFloatingPoint => "#XM",
+            Exception::Virtualization => "#VE",
+            Exception::ControlProtection => "#CP",
+            Exception::HypervisorInjection => "#HV",
+            Exception::VmmCommunication => "#VC",
+            Exception::SecurityException => "#SX",
+            Exception::Reserved => "-",
+        }
+    }
+
+    /// Traps report the instruction after the one that raised them, so
+    /// execution can simply resume.
+    pub fn is_trap(&self) -> bool {
+        match self {
+            Exception::Debug | Exception::Breakpoint => true,
+            _ => false,
+        }
+    }
+}
+
+impl core::fmt::Display for Exception {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{} ({})", self.name(), self.mnemonic())
+    }
+}
+
+/// General purpose registers at the time of the exception.
+///
+/// Registers are stored in the order of `crate::arch::REGISTER_NAMES`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RustosRegisters(pub [usize; REGISTER_COUNT]);
+
+impl RustosRegisters {
+    /// Iterate over `(name, value)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+        REGISTER_NAMES.iter().copied().zip(self.0.iter().copied())
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.iter().find(|(n, _)| *n == name).map(|(_, value)| value)
+    }
+}
+
+impl core::fmt::Debug for RustosRegisters {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.iter() {
+            map.entry(&name, &format_args!("{:#x}", value));
+        }
+        map.finish()
+    }
+}
+
+/// Architecture independent view of the state saved on an exception.
+///
+/// Changes made by a handler are written back before returning from the
+/// exception.
 #[derive(Debug, PartialEq, Eq)]
 pub struct RustosExcStackframe {
+    pub vector: usize,
     pub instruction_pointer: usize,
     pub code_segment: usize,
     pub cpu_flags: usize,
     pub stack_pointer: usize,
     pub stack_segment: usize,
     pub errcode: Option<u64>,
+    /// Address that caused a page fault.
+    pub fault_address: Option<usize>,
+    pub registers: RustosRegisters,
 }
 
 impl RustosExcStackframe {
@@ -15,3 +163,84 @@ impl RustosExcStackframe {
         self.errcode = Some(error_code);
     }
 }
+
+/// Function called when an exception is raised.
+pub type ExceptionHandler = fn(Exception, &mut RustosExcStackframe);
+
+static HANDLERS: RwLock<[Option<ExceptionHandler>; EXCEPTION_COUNT]> =
+    RwLock::new([None; EXCEPTION_COUNT]);
+
+/// Route `exception` to `handler` instead of the default handler.
+pub fn set_exception_handler(exception: Exception, handler: ExceptionHandler) {
+    crate::arch::no_interrupts(|| {
+        HANDLERS.write()[exception as usize] = Some(handler);
+    })
+}
+
+/// Route `exception` back to the default handler.
+pub fn reset_exception_handler(exception: Exception) {
+    crate::arch::no_interrupts(|| {
+        HANDLERS.write()[exception as usize] = None;
+    })
+}
+
+/// Called by the architecture code for every exception.
+pub fn handle_exception(exception: Exception, frame: &mut RustosExcStackframe) {
+    let handler = HANDLERS.read()[exception as usize];
+    match handler {
+        Some(handler) => handler(exception, frame),
+        None => default_handler(exception, frame),
+    }
+}
+
+/// Print a decoded report of the exception and panic, unless it is a trap.
+fn default_handler(exception: Exception, frame: &mut RustosExcStackframe) {
+    print_report(exception, frame);
+    if exception.is_trap() {
+        return;
+    }
+
+    panic!("unhandled {} at {:#x}", exception, frame.instruction_pointer);
+}
+
+/// Print the exception, decoded error code, faulting instruction and registers.
+pub fn print_report(exception: Exception, frame: &RustosExcStackframe) {
+    use crate::arch::exceptions::{decode_error_code, instruction_readable, MAX_INSTRUCTION_LEN};
+
+    println!("EXCEPTION: {} [vector {}]", exception, frame.vector);
+    if let Some(errcode) = frame.errcode {
+        println!("Error code: {:#x} ({})", errcode, decode_error_code(exception, errcode));
+    }
+    if let Some(address) = frame.fault_address {
+        println!("Accessed Address: {:#x}", address);
+    }
+
+    print!("Instruction: {:#x}", frame.instruction_pointer);
+    if instruction_readable(exception, frame) {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                frame.instruction_pointer as *const u8,
+                MAX_INSTRUCTION_LEN,
+            )
+        };
+        print!(" [");
+        for byte in bytes {
+            print!(" {:02x}", byte);
+        }
+        print!(" ]");
+    }
+    println!();
+
+    println!(
+        "Stack: {:#x}:{:#x} Code segment: {:#x} Flags: {:#x}",
+        frame.stack_segment, frame.stack_pointer, frame.code_segment, frame.cpu_flags
+    );
+    for (i, (name, value)) in frame.registers.iter().enumerate() {
+        print!("{:>3}={:016x} ", name, value);
+        if i % 3 == 2 {
+            println!();
+        }
+    }
+    println!();
+    println!("{}", crate::backtrace::Backtrace::from_exception(frame));
+}
@@ -0,0 +1,535 @@
+//! Decoding of PS/2 keyboard scancodes into key events and characters.
+
+pub mod layouts;
+mod scancodes;
+
+pub use layouts::Layout;
+
+/// Physical key, named after its label on a US keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyCode {
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Backquote,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    Key0,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    LeftBracket,
+    RightBracket,
+    /// Right of `RightBracket` on US keyboards, `#` on UK and German ones.
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    Semicolon,
+    Quote,
+    Enter,
+    LeftShift,
+    /// Left of `Z` on ISO keyboards.
+    NonUsBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    Slash,
+    RightShift,
+    LeftCtrl,
+    LeftGui,
+    LeftAlt,
+    Space,
+    /// AltGr on international layouts.
+    RightAlt,
+    RightGui,
+    Menu,
+    RightCtrl,
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    ArrowUp,
+    ArrowLeft,
+    ArrowDown,
+    ArrowRight,
+    NumLock,
+    NumpadDivide,
+    NumpadMultiply,
+    NumpadSubtract,
+    NumpadAdd,
+    NumpadEnter,
+    NumpadDecimal,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    /// XT scancodes, what the 8042 controller translates to by default.
+    Set1,
+    /// AT scancodes, sent by keyboards unless told otherwise.
+    Set2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The byte completed a scancode that maps to no key.
+    UnknownScancode(u8),
+}
+
+const EXTENDED: u8 = 0xe0;
+const PAUSE: u8 = 0xe1;
+const SET1_RELEASE: u8 = 0x80;
+const SET2_RELEASE: u8 = 0xf0;
+
+/// Length of the Pause key sequence, which has no release code.
+const SET1_PAUSE_LEN: u8 = 6;
+const SET2_PAUSE_LEN: u8 = 8;
+
+/// Shift presses and releases the keyboard wraps around some extended keys
+/// for compatibility, they carry no information.
+const SET1_FAKE_SHIFTS: [u8; 2] = [0x2a, 0x36];
+const SET2_FAKE_SHIFTS: [u8; 2] = [0x12, 0x59];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeState {
+    Start,
+    Extended,
+    /// Set 2 release prefix received.
+    Release,
+    ExtendedRelease,
+    /// Skipping the rest of a Pause sequence.
+    Pause(u8),
+}
+
+/// Turns scancode bytes into key events.
+pub struct Decoder {
+    set: ScancodeSet,
+    state: DecodeState,
+}
+
+impl Decoder {
+    pub const fn new(set: ScancodeSet) -> Decoder {
+        Decoder {
+            set,
+            state: DecodeState::Start,
+        }
+    }
+
+    pub fn scancode_set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Switch sets, dropping any partial scancode.
+    pub fn set_scancode_set(&mut self, set: ScancodeSet) {
+        self.set = set;
+        self.state = DecodeState::Start;
+    }
+
+    /// Feed one byte, returning an event once a scancode is complete.
+    ///
+    /// Pause only reports presses.
+    pub fn add_byte(&mut self, byte: u8) -> Result<Option<KeyEvent>, DecodeError> {
+        if let DecodeState::Pause(remaining) = self.state {
+            if remaining > 1 {
+                self.state = DecodeState::Pause(remaining - 1);
+                return Ok(None);
+            }
+            self.state = DecodeState::Start;
+            return Ok(Some(KeyEvent {
+                code: KeyCode::Pause,
+                state: KeyState::Down,
+            }));
+        }
+
+        match self.set {
+            ScancodeSet::Set1 => self.add_set1(byte),
+            ScancodeSet::Set2 => self.add_set2(byte),
+        }
+    }
+
+    fn add_set1(&mut self, byte: u8) -> Result<Option<KeyEvent>, DecodeError> {
+        let state = if byte & SET1_RELEASE != 0 { KeyState::Up } else { KeyState::Down };
+        let code = byte & !SET1_RELEASE;
+
+        match (self.state, byte) {
+            (DecodeState::Start, EXTENDED) => {
+                self.state = DecodeState::Extended;
+                Ok(None)
+            }
+            (DecodeState::Start, PAUSE) => {
+                self.state = DecodeState::Pause(SET1_PAUSE_LEN - 1);
+                Ok(None)
+            }
+            (DecodeState::Extended, _) => {
+                self.state = DecodeState::Start;
+                if SET1_FAKE_SHIFTS.contains(&code) {
+                    return Ok(None);
+                }
+                event(scancodes::set1_extended(code), state, byte)
+            }
+            _ => {
+                self.state = DecodeState::Start;
+                event(scancodes::set1(code), state, byte)
+            }
+        }
+    }
+
+    fn add_set2(&mut self, byte: u8) -> Result<Option<KeyEvent>, DecodeError> {
+        match (self.state, byte) {
+            (DecodeState::Start, EXTENDED) => {
+                self.state = DecodeState::Extended;
+                Ok(None)
+            }
+            (DecodeState::Start, PAUSE) => {
+                self.state = DecodeState::Pause(SET2_PAUSE_LEN - 1);
+                Ok(None)
+            }
+            (DecodeState::Start, SET2_RELEASE) => {
+                self.state = DecodeState::Release;
+                Ok(None)
+            }
+            (DecodeState::Extended, SET2_RELEASE) => {
+                self.state = DecodeState::ExtendedRelease;
+                Ok(None)
+            }
+            (DecodeState::Extended, _) | (DecodeState::ExtendedRelease, _) => {
+                let state = if self.state == DecodeState::Extended {
+                    KeyState::Down
+                } else {
+                    KeyState::Up
+                };
+                self.state = DecodeState::Start;
+                if SET2_FAKE_SHIFTS.contains(&byte) {
+                    return Ok(None);
+                }
+                event(scancodes::set2_extended(byte), state, byte)
+            }
+            (DecodeState::Release, _) => {
+                self.state = DecodeState::Start;
+                event(scancodes::set2(byte), KeyState::Up, byte)
+            }
+            _ => {
+                self.state = DecodeState::Start;
+                event(scancodes::set2(byte), KeyState::Down, byte)
+            }
+        }
+    }
+}
+
+fn event(code: Option<KeyCode>, state: KeyState, byte: u8) -> Result<Option<KeyEvent>, DecodeError> {
+    code.map(|code| Some(KeyEvent { code, state }))
+        .ok_or(DecodeError::UnknownScancode(byte))
+}
+
+/// Modifier keys held and lock keys active.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub left_alt: bool,
+    pub right_alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl Modifiers {
+    pub fn is_shifted(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    pub fn is_ctrl(&self) -> bool {
+        self.left_ctrl || self.right_ctrl
+    }
+
+    pub fn is_alt(&self) -> bool {
+        self.left_alt
+    }
+
+    pub fn is_alt_gr(&self) -> bool {
+        self.right_alt
+    }
+}
+
+/// A key press translated by the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedKey {
+    Unicode(char),
+    /// Keys that produce no character, like arrows or function keys.
+    RawKey(KeyCode),
+}
+
+/// Number of `KeyCode`s.
+const KEY_COUNT: usize = KeyCode::Numpad9 as usize + 1;
+
+/// Decoder that tracks modifiers and translates key presses to characters.
+pub struct Keyboard {
+    decoder: Decoder,
+    layout: &'static dyn Layout,
+    modifiers: Modifiers,
+    /// Keys held down, to tell presses from typematic repeats.
+    pressed: [bool; KEY_COUNT],
+}
+
+impl Keyboard {
+    pub fn new(set: ScancodeSet, layout: &'static dyn Layout) -> Keyboard {
+        Keyboard {
+            decoder: Decoder::new(set),
+            layout,
+            modifiers: Modifiers::default(),
+            pressed: [false; KEY_COUNT],
+        }
+    }
+
+    pub fn layout(&self) -> &'static dyn Layout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: &'static dyn Layout) {
+        self.layout = layout;
+    }
+
+    pub fn set_scancode_set(&mut self, set: ScancodeSet) {
+        self.decoder.set_scancode_set(set);
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn is_pressed(&self, code: KeyCode) -> bool {
+        self.pressed[code as usize]
+    }
+
+    /// Feed one scancode byte and update the modifiers.
+    pub fn add_byte(&mut self, byte: u8) -> Result<Option<KeyEvent>, DecodeError> {
+        let event = self.decoder.add_byte(byte)?;
+        if let Some(event) = event {
+            self.update_modifiers(event);
+        }
+        Ok(event)
+    }
+
+    /// Feed one scancode byte and translate key presses, ignoring releases
+    /// and unknown scancodes.
+    pub fn process_byte(&mut self, byte: u8) -> Option<DecodedKey> {
+        match self.add_byte(byte) {
+            Ok(Some(event)) => self.translate(event),
+            _ => None,
+        }
+    }
+
+    fn update_modifiers(&mut self, event: KeyEvent) {
+        let down = event.state == KeyState::Down;
+        let repeated = down && self.pressed[event.code as usize];
+        self.pressed[event.code as usize] = down;
+
+        let modifiers = &mut self.modifiers;
+        match event.code {
+            KeyCode::LeftShift => modifiers.left_shift = down,
+            KeyCode::RightShift => modifiers.right_shift = down,
+            KeyCode::LeftCtrl => modifiers.left_ctrl = down,
+            KeyCode::RightCtrl => modifiers.right_ctrl = down,
+            KeyCode::LeftAlt => modifiers.left_alt = down,
+            KeyCode::RightAlt => modifiers.right_alt = down,
+            KeyCode::CapsLock if down && !repeated => modifiers.caps_lock = !modifiers.caps_lock,
+            KeyCode::NumLock if down && !repeated => modifiers.num_lock = !modifiers.num_lock,
+            KeyCode::ScrollLock if down && !repeated => {
+                modifiers.scroll_lock = !modifiers.scroll_lock
+            }
+            _ => {}
+        }
+    }
+
+    /// Translate a key press with the current modifiers and layout.
+    pub fn translate(&self, event: KeyEvent) -> Option<DecodedKey> {
+        use KeyCode::*;
+
+        if event.state == KeyState::Up {
+            return None;
+        }
+
+        let num_lock = self.modifiers.num_lock;
+        let c = match event.code {
+            Enter | NumpadEnter => '\n',
+            Tab => '\t',
+            Backspace => '\x08',
+            Escape => '\x1b',
+            Delete => '\x7f',
+            Space => ' ',
+            NumpadDivide => '/',
+            NumpadMultiply => '*',
+            NumpadSubtract => '-',
+            NumpadAdd => '+',
+            NumpadDecimal if num_lock => '.',
+            Numpad0 if num_lock => '0',
+            Numpad1 if num_lock => '1',
+            Numpad2 if num_lock => '2',
+            Numpad3 if num_lock => '3',
+            Numpad4 if num_lock => '4',
+            Numpad5 if num_lock => '5',
+            Numpad6 if num_lock => '6',
+            Numpad7 if num_lock => '7',
+            Numpad8 if num_lock => '8',
+            Numpad9 if num_lock => '9',
+            code => match self.layout.chars(code) {
+                Some(chars) => chars.select(&self.modifiers),
+                None => return Some(DecodedKey::RawKey(numpad_navigation(code))),
+            },
+        };
+        Some(DecodedKey::Unicode(c))
+    }
+}
+
+/// Navigation key of a keypad key with Num Lock off.
+fn numpad_navigation(code: KeyCode) -> KeyCode {
+    use KeyCode::*;
+    match code {
+        Numpad0 => Insert,
+        Numpad1 => End,
+        Numpad2 => ArrowDown,
+        Numpad3 => PageDown,
+        Numpad4 => ArrowLeft,
+        Numpad6 => ArrowRight,
+        Numpad7 => Home,
+        Numpad8 => ArrowUp,
+        Numpad9 => PageUp,
+        NumpadDecimal => Delete,
+        code => code,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    fn feed(keyboard: &mut Keyboard, bytes: &[u8]) -> Option<DecodedKey> {
+        bytes.iter().fold(None, |last, &byte| keyboard.process_byte(byte).or(last))
+    }
+
+    #[test_case]
+    fn test_set1_shifted_letter() {
+        serial_print!("test_set1_shifted_letter...");
+        let mut keyboard = Keyboard::new(ScancodeSet::Set1, &layouts::US);
+        assert_eq!(feed(&mut keyboard, &[0x1e, 0x9e]), Some(DecodedKey::Unicode('a')));
+        assert_eq!(feed(&mut keyboard, &[0x2a, 0x1e]), Some(DecodedKey::Unicode('A')));
+        assert_eq!(feed(&mut keyboard, &[0xaa, 0x02]), Some(DecodedKey::Unicode('1')));
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_set2_extended_and_pause() {
+        serial_print!("test_set2_extended_and_pause...");
+        let mut decoder = Decoder::new(ScancodeSet::Set2);
+        let bytes = [
+            0xe0, 0x75, 0xe0, 0xf0, 0x75, // arrow up
+            0xe1, 0x14, 0x77, 0xe1, 0xf0, 0x14, 0xf0, 0x77, // pause
+            0xe0, 0x12, 0xe0, 0x7c, // print screen
+        ];
+        let events: alloc::vec::Vec<KeyEvent> = bytes
+            .iter()
+            .filter_map(|&byte| decoder.add_byte(byte).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                KeyEvent { code: KeyCode::ArrowUp, state: KeyState::Down },
+                KeyEvent { code: KeyCode::ArrowUp, state: KeyState::Up },
+                KeyEvent { code: KeyCode::Pause, state: KeyState::Down },
+                KeyEvent { code: KeyCode::PrintScreen, state: KeyState::Down },
+            ]
+        );
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_caps_lock_ignores_repeats() {
+        serial_print!("test_caps_lock_ignores_repeats...");
+        let mut keyboard = Keyboard::new(ScancodeSet::Set2, &layouts::US);
+        feed(&mut keyboard, &[0x58, 0x58, 0x58, 0xf0, 0x58]);
+        assert!(keyboard.modifiers().caps_lock);
+        assert_eq!(feed(&mut keyboard, &[0x1c]), Some(DecodedKey::Unicode('A')));
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_german_layout() {
+        serial_print!("test_german_layout...");
+        let mut keyboard = Keyboard::new(ScancodeSet::Set1, &layouts::GERMAN);
+        assert_eq!(feed(&mut keyboard, &[0x15]), Some(DecodedKey::Unicode('z')));
+        assert_eq!(feed(&mut keyboard, &[0x0c]), Some(DecodedKey::Unicode('ß')));
+        assert_eq!(feed(&mut keyboard, &[0xe0, 0x38, 0x10]), Some(DecodedKey::Unicode('@')));
+        serial_println!("[ok]");
+    }
+}
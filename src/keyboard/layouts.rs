@@ -0,0 +1,220 @@
+//! Keyboard layouts, mapping keys to the characters printed on them.
+
+use super::{KeyCode, Modifiers};
+
+/// Characters produced by a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChars {
+    pub normal: char,
+    pub shifted: char,
+    /// Produced with the right Alt key held.
+    pub alt_gr: Option<char>,
+    /// Caps Lock inverts Shift on letters only.
+    pub letter: bool,
+}
+
+impl KeyChars {
+    pub const fn new(normal: char, shifted: char) -> KeyChars {
+        KeyChars {
+            normal,
+            shifted,
+            alt_gr: None,
+            letter: false,
+        }
+    }
+
+    pub const fn letter(normal: char, shifted: char) -> KeyChars {
+        KeyChars {
+            normal,
+            shifted,
+            alt_gr: None,
+            letter: true,
+        }
+    }
+
+    pub const fn with_alt_gr(self, alt_gr: char) -> KeyChars {
+        KeyChars {
+            alt_gr: Some(alt_gr),
+            ..self
+        }
+    }
+
+    /// The character produced with `modifiers` held.
+    pub fn select(&self, modifiers: &Modifiers) -> char {
+        if modifiers.is_alt_gr() {
+            if let Some(c) = self.alt_gr {
+                return c;
+            }
+        }
+        if modifiers.is_ctrl() && self.letter && self.normal.is_ascii_alphabetic() {
+            // Ctrl+A is U+0001 and so on.
+            return ((self.normal as u8) & 0x1f) as char;
+        }
+        if modifiers.is_shifted() ^ (self.letter && modifiers.caps_lock) {
+            self.shifted
+        } else {
+            self.normal
+        }
+    }
+}
+
+pub trait Layout: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Characters of a key of the main block, `None` for keys that do not
+    /// print anything.
+    fn chars(&self, code: KeyCode) -> Option<KeyChars>;
+}
+
+/// Latin letter printed on a key of a QWERTY keyboard.
+fn qwerty_letter(code: KeyCode) -> Option<KeyChars> {
+    use KeyCode::*;
+    let c = match code {
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        _ => return None,
+    };
+    Some(KeyChars::letter(c, c.to_ascii_uppercase()))
+}
+
+/// US English.
+pub struct Us;
+
+impl Layout for Us {
+    fn name(&self) -> &'static str {
+        "us"
+    }
+
+    fn chars(&self, code: KeyCode) -> Option<KeyChars> {
+        use KeyCode::*;
+        let chars = match code {
+            Backquote => KeyChars::new('`', '~'),
+            Key1 => KeyChars::new('1', '!'),
+            Key2 => KeyChars::new('2', '@'),
+            Key3 => KeyChars::new('3', '#'),
+            Key4 => KeyChars::new('4', '$'),
+            Key5 => KeyChars::new('5', '%'),
+            Key6 => KeyChars::new('6', '^'),
+            Key7 => KeyChars::new('7', '&'),
+            Key8 => KeyChars::new('8', '*'),
+            Key9 => KeyChars::new('9', '('),
+            Key0 => KeyChars::new('0', ')'),
+            Minus => KeyChars::new('-', '_'),
+            Equals => KeyChars::new('=', '+'),
+            LeftBracket => KeyChars::new('[', '{'),
+            RightBracket => KeyChars::new(']', '}'),
+            Backslash => KeyChars::new('\\', '|'),
+            Semicolon => KeyChars::new(';', ':'),
+            Quote => KeyChars::new('\'', '"'),
+            Comma => KeyChars::new(',', '<'),
+            Period => KeyChars::new('.', '>'),
+            Slash => KeyChars::new('/', '?'),
+            NonUsBackslash => KeyChars::new('\\', '|'),
+            _ => return qwerty_letter(code),
+        };
+        Some(chars)
+    }
+}
+
+/// UK English.
+pub struct Uk;
+
+impl Layout for Uk {
+    fn name(&self) -> &'static str {
+        "uk"
+    }
+
+    fn chars(&self, code: KeyCode) -> Option<KeyChars> {
+        use KeyCode::*;
+        let chars = match code {
+            Backquote => KeyChars::new('`', '¬').with_alt_gr('¦'),
+            Key2 => KeyChars::new('2', '"'),
+            Key3 => KeyChars::new('3', '£'),
+            Key4 => KeyChars::new('4', '$').with_alt_gr('€'),
+            Quote => KeyChars::new('\'', '@'),
+            Backslash => KeyChars::new('#', '~'),
+            _ => return Us.chars(code),
+        };
+        Some(chars)
+    }
+}
+
+/// German, QWERTZ.
+pub struct German;
+
+impl Layout for German {
+    fn name(&self) -> &'static str {
+        "de"
+    }
+
+    fn chars(&self, code: KeyCode) -> Option<KeyChars> {
+        use KeyCode::*;
+        let chars = match code {
+            Backquote => KeyChars::new('^', '°'),
+            Key1 => KeyChars::new('1', '!'),
+            Key2 => KeyChars::new('2', '"').with_alt_gr('²'),
+            Key3 => KeyChars::new('3', '§').with_alt_gr('³'),
+            Key4 => KeyChars::new('4', '$'),
+            Key5 => KeyChars::new('5', '%'),
+            Key6 => KeyChars::new('6', '&'),
+            Key7 => KeyChars::new('7', '/').with_alt_gr('{'),
+            Key8 => KeyChars::new('8', '(').with_alt_gr('['),
+            Key9 => KeyChars::new('9', ')').with_alt_gr(']'),
+            Key0 => KeyChars::new('0', '=').with_alt_gr('}'),
+            Minus => KeyChars::new('ß', '?').with_alt_gr('\\'),
+            Equals => KeyChars::new('´', '`'),
+            LeftBracket => KeyChars::letter('ü', 'Ü'),
+            RightBracket => KeyChars::new('+', '*').with_alt_gr('~'),
+            Backslash => KeyChars::new('#', '\''),
+            Semicolon => KeyChars::letter('ö', 'Ö'),
+            Quote => KeyChars::letter('ä', 'Ä'),
+            Comma => KeyChars::new(',', ';'),
+            Period => KeyChars::new('.', ':'),
+            Slash => KeyChars::new('-', '_'),
+            NonUsBackslash => KeyChars::new('<', '>').with_alt_gr('|'),
+            Y => KeyChars::letter('z', 'Z'),
+            Z => KeyChars::letter('y', 'Y'),
+            Q => KeyChars::letter('q', 'Q').with_alt_gr('@'),
+            E => KeyChars::letter('e', 'E').with_alt_gr('€'),
+            M => KeyChars::letter('m', 'M').with_alt_gr('µ'),
+            _ => return qwerty_letter(code),
+        };
+        Some(chars)
+    }
+}
+
+pub static US: Us = Us;
+pub static UK: Uk = Uk;
+pub static GERMAN: German = German;
+
+/// All built-in layouts.
+pub static LAYOUTS: [&dyn Layout; 3] = [&US, &UK, &GERMAN];
+
+/// Look up a built-in layout by its name, e.g. `"de"`.
+pub fn by_name(name: &str) -> Option<&'static dyn Layout> {
+    LAYOUTS.iter().copied().find(|layout| layout.name() == name)
+}
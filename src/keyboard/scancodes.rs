@@ -0,0 +1,245 @@
+use super::KeyCode;
+
+/// Key of a scancode set 1 make code, without the `0xe0` prefix.
+pub(super) fn set1(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+    let key = match code {
+        0x01 => Escape,
+        0x02 => Key1,
+        0x03 => Key2,
+        0x04 => Key3,
+        0x05 => Key4,
+        0x06 => Key5,
+        0x07 => Key6,
+        0x08 => Key7,
+        0x09 => Key8,
+        0x0a => Key9,
+        0x0b => Key0,
+        0x0c => Minus,
+        0x0d => Equals,
+        0x0e => Backspace,
+        0x0f => Tab,
+        0x10 => Q,
+        0x11 => W,
+        0x12 => E,
+        0x13 => R,
+        0x14 => T,
+        0x15 => Y,
+        0x16 => U,
+        0x17 => I,
+        0x18 => O,
+        0x19 => P,
+        0x1a => LeftBracket,
+        0x1b => RightBracket,
+        0x1c => Enter,
+        0x1d => LeftCtrl,
+        0x1e => A,
+        0x1f => S,
+        0x20 => D,
+        0x21 => F,
+        0x22 => G,
+        0x23 => H,
+        0x24 => J,
+        0x25 => K,
+        0x26 => L,
+        0x27 => Semicolon,
+        0x28 => Quote,
+        0x29 => Backquote,
+        0x2a => LeftShift,
+        0x2b => Backslash,
+        0x2c => Z,
+        0x2d => X,
+        0x2e => C,
+        0x2f => V,
+        0x30 => B,
+        0x31 => N,
+        0x32 => M,
+        0x33 => Comma,
+        0x34 => Period,
+        0x35 => Slash,
+        0x36 => RightShift,
+        0x37 => NumpadMultiply,
+        0x38 => LeftAlt,
+        0x39 => Space,
+        0x3a => CapsLock,
+        0x3b => F1,
+        0x3c => F2,
+        0x3d => F3,
+        0x3e => F4,
+        0x3f => F5,
+        0x40 => F6,
+        0x41 => F7,
+        0x42 => F8,
+        0x43 => F9,
+        0x44 => F10,
+        0x45 => NumLock,
+        0x46 => ScrollLock,
+        0x47 => Numpad7,
+        0x48 => Numpad8,
+        0x49 => Numpad9,
+        0x4a => NumpadSubtract,
+        0x4b => Numpad4,
+        0x4c => Numpad5,
+        0x4d => Numpad6,
+        0x4e => NumpadAdd,
+        0x4f => Numpad1,
+        0x50 => Numpad2,
+        0x51 => Numpad3,
+        0x52 => Numpad0,
+        0x53 => NumpadDecimal,
+        0x56 => NonUsBackslash,
+        0x57 => F11,
+        0x58 => F12,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Key of a scancode set 1 make code following `0xe0`.
+pub(super) fn set1_extended(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+    let key = match code {
+        0x1c => NumpadEnter,
+        0x1d => RightCtrl,
+        0x35 => NumpadDivide,
+        0x37 => PrintScreen,
+        0x38 => RightAlt,
+        0x47 => Home,
+        0x48 => ArrowUp,
+        0x49 => PageUp,
+        0x4b => ArrowLeft,
+        0x4d => ArrowRight,
+        0x4f => End,
+        0x50 => ArrowDown,
+        0x51 => PageDown,
+        0x52 => Insert,
+        0x53 => Delete,
+        0x5b => LeftGui,
+        0x5c => RightGui,
+        0x5d => Menu,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Key of a scancode set 2 make code, without the `0xe0` prefix.
+pub(super) fn set2(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+    let key = match code {
+        0x01 => F9,
+        0x03 => F5,
+        0x04 => F3,
+        0x05 => F1,
+        0x06 => F2,
+        0x07 => F12,
+        0x09 => F10,
+        0x0a => F8,
+        0x0b => F6,
+        0x0c => F4,
+        0x0d => Tab,
+        0x0e => Backquote,
+        0x11 => LeftAlt,
+        0x12 => LeftShift,
+        0x14 => LeftCtrl,
+        0x15 => Q,
+        0x16 => Key1,
+        0x1a => Z,
+        0x1b => S,
+        0x1c => A,
+        0x1d => W,
+        0x1e => Key2,
+        0x21 => C,
+        0x22 => X,
+        0x23 => D,
+        0x24 => E,
+        0x25 => Key4,
+        0x26 => Key3,
+        0x29 => Space,
+        0x2a => V,
+        0x2b => F,
+        0x2c => T,
+        0x2d => R,
+        0x2e => Key5,
+        0x31 => N,
+        0x32 => B,
+        0x33 => H,
+        0x34 => G,
+        0x35 => Y,
+        0x36 => Key6,
+        0x3a => M,
+        0x3b => J,
+        0x3c => U,
+        0x3d => Key7,
+        0x3e => Key8,
+        0x41 => Comma,
+        0x42 => K,
+        0x43 => I,
+        0x44 => O,
+        0x45 => Key0,
+        0x46 => Key9,
+        0x49 => Period,
+        0x4a => Slash,
+        0x4b => L,
+        0x4c => Semicolon,
+        0x4d => P,
+        0x4e => Minus,
+        0x52 => Quote,
+        0x54 => LeftBracket,
+        0x55 => Equals,
+        0x58 => CapsLock,
+        0x59 => RightShift,
+        0x5a => Enter,
+        0x5b => RightBracket,
+        0x5d => Backslash,
+        0x61 => NonUsBackslash,
+        0x66 => Backspace,
+        0x69 => Numpad1,
+        0x6b => Numpad4,
+        0x6c => Numpad7,
+        0x70 => Numpad0,
+        0x71 => NumpadDecimal,
+        0x72 => Numpad2,
+        0x73 => Numpad5,
+        0x74 => Numpad6,
+        0x75 => Numpad8,
+        0x76 => Escape,
+        0x77 => NumLock,
+        0x78 => F11,
+        0x79 => NumpadAdd,
+        0x7a => Numpad3,
+        0x7b => NumpadSubtract,
+        0x7c => NumpadMultiply,
+        0x7d => Numpad9,
+        0x7e => ScrollLock,
+        0x83 => F7,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Key of a scancode set 2 make code following `0xe0`.
+pub(super) fn set2_extended(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+    let key = match code {
+        0x11 => RightAlt,
+        0x14 => RightCtrl,
+        0x1f => LeftGui,
+        0x27 => RightGui,
+        0x2f => Menu,
+        0x4a => NumpadDivide,
+        0x5a => NumpadEnter,
+        0x69 => End,
+        0x6b => ArrowLeft,
+        0x6c => Home,
+        0x70 => Insert,
+        0x71 => Delete,
+        0x72 => ArrowDown,
+        0x74 => ArrowRight,
+        0x75 => ArrowUp,
+        0x7a => PageDown,
+        0x7c => PrintScreen,
+        0x7d => PageUp,
+        _ => return None,
+    };
+    Some(key)
+}
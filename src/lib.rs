@@ -3,6 +3,8 @@
 #![no_std]
 #![cfg_attr(test, no_main)]
 #![cfg_attr(feature = "x86", feature(abi_x86_interrupt))]
+#![cfg_attr(feature = "x86", feature(global_asm))]
+#![cfg_attr(feature = "x86", feature(llvm_asm))]
 #![cfg_attr(test, feature(custom_test_frameworks))]
 #![cfg_attr(test, test_runner(crate::test::test_runner))]
 #![cfg_attr(test, reexport_test_harness_main = "test_main")]
@@ -15,13 +17,28 @@ pub mod heap;
 extern crate alloc;
 
 #[macro_use]
+pub mod console;
+#[macro_use]
+pub mod log;
+pub mod build_cmdline;
 pub mod vga_buffer;
+pub mod framebuffer;
 
 #[macro_use]
 pub mod serial;
 
 pub mod arch;
 
+pub mod interrupts;
+
+pub mod backtrace;
+pub mod symbols;
+
+pub mod thread;
+
+pub mod keyboard;
+pub mod mouse;
+
 pub mod cooperative;
 
 #[macro_use]
@@ -66,11 +83,18 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
 
 use bootloader::bootinfo::BootInfo;
 pub fn init(boot_info: &'static BootInfo) {
+    crate::log::init();
     crate::arch::initialize();
     let mut mapper = unsafe { crate::arch::memory::init(boot_info.physical_memory_offset) };
     let mut frame_allocator = crate::arch::memory::init_frame_allocator(&boot_info.memory_map);
     crate::arch::heap::init(&mut mapper, &mut frame_allocator)
         .expect("failed to init the heap");
+    // Without a graphics adapter only the text console is available.
+    match crate::arch::bga::init(&mut mapper, &mut frame_allocator) {
+        Ok(()) | Err(crate::framebuffer::FramebufferError::NoDevice) => {}
+        Err(err) => crate::error!("failed to init the graphics adapter: {:?}", err),
+    }
+    crate::thread::init();
 }
 
 #[cfg(test)]
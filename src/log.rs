@@ -0,0 +1,274 @@
+//! Kernel log with levels and targets.
+//!
+//! Records that pass the level filter are printed on the console and kept
+//! in a ring, see `dmesg`. The filter starts out from the `loglevel`
+//! option of the build-time command line, like
+//! `loglevel=warn,rustos::arch=debug`, see `build_cmdline`.
+
+mod ring;
+
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use spin::RwLock;
+
+use crate::arch::no_interrupts;
+
+pub use ring::{for_each_record, next_sequence, Record, MAX_MESSAGE_LEN, RECORD_CAPACITY};
+
+/// Maximum number of per-target levels.
+pub const MAX_TARGET_FILTERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Level> {
+        match value {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Most verbose level let through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    pub fn allows(self, level: Level) -> bool {
+        level as u8 <= self as u8
+    }
+
+    /// Parse a level name like `warn`, ignoring case.
+    pub fn parse(name: &str) -> Option<LevelFilter> {
+        let filters = [
+            ("off", LevelFilter::Off),
+            ("error", LevelFilter::Error),
+            ("warn", LevelFilter::Warn),
+            ("info", LevelFilter::Info),
+            ("debug", LevelFilter::Debug),
+            ("trace", LevelFilter::Trace),
+        ];
+        filters
+            .iter()
+            .find(|(filter_name, _)| filter_name.eq_ignore_ascii_case(name))
+            .map(|&(_, filter)| filter)
+    }
+
+    fn from_u8(value: u8) -> LevelFilter {
+        match Level::from_u8(value) {
+            Some(Level::Error) => LevelFilter::Error,
+            Some(Level::Warn) => LevelFilter::Warn,
+            Some(Level::Info) => LevelFilter::Info,
+            Some(Level::Debug) => LevelFilter::Debug,
+            Some(Level::Trace) => LevelFilter::Trace,
+            None => LevelFilter::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogError {
+    TooManyFilters,
+    /// A `loglevel` option that does not parse.
+    InvalidFilter,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TargetFilter {
+    target: &'static str,
+    level: LevelFilter,
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Info as u8);
+
+// Only locked with interrupts disabled, so logging from a handler never
+// finds it locked by the code it interrupted.
+static TARGET_FILTERS: RwLock<[Option<TargetFilter>; MAX_TARGET_FILTERS]> =
+    RwLock::new([None; MAX_TARGET_FILTERS]);
+
+/// Level for targets without one of their own.
+pub fn max_level() -> LevelFilter {
+    LevelFilter::from_u8(MAX_LEVEL.load(Ordering::Relaxed))
+}
+
+pub fn set_max_level(level: LevelFilter) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Set the level of `target` and the modules below it, like
+/// `rustos::arch` for `rustos::arch::x86::ps2`.
+pub fn set_target_level(target: &'static str, level: LevelFilter) -> Result<(), LogError> {
+    no_interrupts(|| {
+        let mut filters = TARGET_FILTERS.write();
+        let slot = match filters.iter().position(|slot| slot.map_or(false, |filter| filter.target == target)) {
+            Some(index) => index,
+            None => filters
+                .iter()
+                .position(Option::is_none)
+                .ok_or(LogError::TooManyFilters)?,
+        };
+        filters[slot] = Some(TargetFilter { target, level });
+        Ok(())
+    })
+}
+
+pub fn clear_target_levels() {
+    no_interrupts(|| *TARGET_FILTERS.write() = [None; MAX_TARGET_FILTERS]);
+}
+
+fn matches(filter: &str, target: &str) -> bool {
+    target.starts_with(filter)
+        && (target.len() == filter.len() || target[filter.len()..].starts_with("::"))
+}
+
+/// Level let through for `target`, from the most specific filter.
+pub fn level_for(target: &str) -> LevelFilter {
+    no_interrupts(|| {
+        TARGET_FILTERS
+            .read()
+            .iter()
+            .flatten()
+            .filter(|filter| matches(filter.target, target))
+            .max_by_key(|filter| filter.target.len())
+            .map_or_else(max_level, |filter| filter.level)
+    })
+}
+
+pub fn enabled(level: Level, target: &str) -> bool {
+    level_for(target).allows(level)
+}
+
+/// Apply a filter like `info,rustos::arch=debug`: a bare level sets the
+/// maximum, `target=level` the level of a target.
+pub fn parse_filters(spec: &'static str) -> Result<(), LogError> {
+    for directive in spec.split(',').filter(|directive| !directive.is_empty()) {
+        let mut parts = directive.rsplitn(2, '=');
+        let level = parts.next().and_then(LevelFilter::parse).ok_or(LogError::InvalidFilter)?;
+        match parts.next() {
+            Some(target) => set_target_level(target, level)?,
+            None => set_max_level(level),
+        }
+    }
+    Ok(())
+}
+
+/// Apply the `loglevel` option of the build-time command line.
+pub fn init() {
+    if let Some(spec) = crate::build_cmdline::value("loglevel") {
+        if let Err(err) = parse_filters(spec) {
+            crate::warn!("bad loglevel option {:?}: {:?}", spec, err);
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, target: &'static str, args: fmt::Arguments) {
+    if !enabled(level, target) {
+        return;
+    }
+    let timestamp = crate::thread::ticks();
+    ring::push(timestamp, level, target, args);
+    crate::println!("[{:>8}] {:<5} {}: {}", timestamp, level, target, args);
+}
+
+/// Print the records still in the ring, oldest first.
+pub fn dmesg() {
+    for_each_record(|record| crate::println!("{}", record));
+}
+
+/// Log at a `Level`, with the module path as target unless one is given.
+#[macro_export]
+macro_rules! log {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => (
+        $crate::log::_log($level, $target, format_args!($($arg)+))
+    );
+    ($level:expr, $($arg:tt)+) => ($crate::log!(target: module_path!(), $level, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! error {
+    (target: $target:expr, $($arg:tt)+) => ($crate::log!(target: $target, $crate::log::Level::Error, $($arg)+));
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Error, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! warn {
+    (target: $target:expr, $($arg:tt)+) => ($crate::log!(target: $target, $crate::log::Level::Warn, $($arg)+));
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Warn, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! info {
+    (target: $target:expr, $($arg:tt)+) => ($crate::log!(target: $target, $crate::log::Level::Info, $($arg)+));
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Info, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! debug {
+    (target: $target:expr, $($arg:tt)+) => ($crate::log!(target: $target, $crate::log::Level::Debug, $($arg)+));
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Debug, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! trace {
+    (target: $target:expr, $($arg:tt)+) => ($crate::log!(target: $target, $crate::log::Level::Trace, $($arg)+));
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Trace, $($arg)+));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    #[test_case]
+    fn test_level_filters() {
+        serial_print!("test_level_filters...");
+        assert_eq!(LevelFilter::parse("WARN"), Some(LevelFilter::Warn));
+        assert_eq!(LevelFilter::parse("verbose"), None);
+        assert!(LevelFilter::Warn.allows(Level::Error));
+        assert!(!LevelFilter::Warn.allows(Level::Info));
+        assert!(!LevelFilter::Off.allows(Level::Error));
+
+        assert!(matches("rustos::arch", "rustos::arch::x86"));
+        assert!(matches("rustos::arch", "rustos::arch"));
+        assert!(!matches("rustos::arch", "rustos::architecture"));
+        serial_println!("[ok]");
+    }
+}
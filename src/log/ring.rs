@@ -0,0 +1,190 @@
+//! Lock-free ring of the most recent log records.
+//!
+//! Every slot is a seqlock: writers claim a sequence number and mark the
+//! slot as being written while filling it, readers skip records that
+//! changed while they were copied. Writing never waits, so it is safe from
+//! interrupt handlers, even ones that interrupted another writer.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+
+use super::Level;
+
+/// Records kept, older ones are overwritten.
+pub const RECORD_CAPACITY: usize = 256;
+/// Longer messages are truncated.
+pub const MAX_MESSAGE_LEN: usize = 120;
+
+/// A log record as kept in the ring.
+#[derive(Clone, Copy)]
+pub struct Record {
+    sequence: u64,
+    timestamp: u64,
+    level: Level,
+    target: &'static str,
+    message: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+}
+
+impl Record {
+    /// Number of records logged before this one.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Timer ticks since boot.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn target(&self) -> &'static str {
+        self.target
+    }
+
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Record")
+            .field("sequence", &self.sequence)
+            .field("timestamp", &self.timestamp)
+            .field("level", &self.level)
+            .field("target", &self.target)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>8}] {:<5} {}: {}", self.timestamp, self.level, self.target, self.message())
+    }
+}
+
+/// A record as stored, only turned into a `Record` once known not to be
+/// torn.
+#[derive(Clone, Copy)]
+struct RawRecord {
+    timestamp: u64,
+    level: u8,
+    target: (*const u8, usize),
+    message: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+}
+
+const EMPTY_RECORD: RawRecord = RawRecord {
+    timestamp: 0,
+    level: 0,
+    target: (core::ptr::null(), 0),
+    message: [0; MAX_MESSAGE_LEN],
+    len: 0,
+};
+
+struct Slot {
+    /// `2 * sequence + 1` while being written, `2 * sequence + 2` once
+    /// written, 0 if never used.
+    state: AtomicU64,
+    record: UnsafeCell<RawRecord>,
+}
+
+// Accesses to `record` are guarded by `state`.
+unsafe impl Sync for Slot {}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: Slot = Slot {
+    state: AtomicU64::new(0),
+    record: UnsafeCell::new(EMPTY_RECORD),
+};
+
+static SLOTS: [Slot; RECORD_CAPACITY] = [EMPTY_SLOT; RECORD_CAPACITY];
+/// Sequence number of the next record.
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// Fills a message buffer, truncating at a character boundary.
+struct MessageWriter<'a> {
+    message: &'a mut [u8; MAX_MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut count = s.len().min(MAX_MESSAGE_LEN - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.message[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Store a record, returning its sequence number.
+pub(super) fn push(timestamp: u64, level: Level, target: &'static str, args: fmt::Arguments) -> u64 {
+    let sequence = NEXT.fetch_add(1, Ordering::Relaxed);
+    let slot = &SLOTS[(sequence % RECORD_CAPACITY as u64) as usize];
+
+    slot.state.store(sequence * 2 + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    let record = unsafe { &mut *slot.record.get() };
+    record.timestamp = timestamp;
+    record.level = level as u8;
+    record.target = (target.as_ptr(), target.len());
+    let mut writer = MessageWriter {
+        message: &mut record.message,
+        len: 0,
+    };
+    let _ = writer.write_fmt(args);
+    record.len = writer.len;
+    slot.state.store(sequence * 2 + 2, Ordering::Release);
+    sequence
+}
+
+/// Record `sequence`, if it is still in the ring and not being rewritten.
+fn get(sequence: u64) -> Option<Record> {
+    let slot = &SLOTS[(sequence % RECORD_CAPACITY as u64) as usize];
+    let written = sequence * 2 + 2;
+    if slot.state.load(Ordering::Acquire) != written {
+        return None;
+    }
+    let raw = unsafe { core::ptr::read_volatile(slot.record.get()) };
+    fence(Ordering::Acquire);
+    if slot.state.load(Ordering::Relaxed) != written {
+        return None;
+    }
+
+    let target = unsafe {
+        core::str::from_utf8_unchecked(core::slice::from_raw_parts(raw.target.0, raw.target.1))
+    };
+    Some(Record {
+        sequence,
+        timestamp: raw.timestamp,
+        level: Level::from_u8(raw.level)?,
+        target,
+        message: raw.message,
+        len: raw.len,
+    })
+}
+
+/// Sequence number the next record will get, also the number of records
+/// logged so far.
+pub fn next_sequence() -> u64 {
+    NEXT.load(Ordering::Acquire)
+}
+
+/// Call `f` on the records still in the ring, oldest first.
+pub fn for_each_record(mut f: impl FnMut(&Record)) {
+    let next = next_sequence();
+    for sequence in next.saturating_sub(RECORD_CAPACITY as u64)..next {
+        if let Some(record) = get(sequence) {
+            f(&record);
+        }
+    }
+}
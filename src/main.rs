@@ -15,7 +15,7 @@ use alloc::alloc::Layout;
 
 use bootloader::{bootinfo::BootInfo, entry_point};
 
-use rustos::println;
+use rustos::{print, println};
 
 entry_point!(kmain);
 
@@ -23,6 +23,7 @@ entry_point!(kmain);
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
+    println!("{}", rustos::backtrace::Backtrace::capture());
     rustos::arch::halt_loop()
 }
 
@@ -33,30 +34,62 @@ pub fn kmain(boot_info: &'static BootInfo) -> ! {
 
 
     rustos::init(boot_info);
+    rustos::arch::ps2::keyboard::spawn_led_thread().expect("failed to start the LED thread");
     
     let mut executor = rustos::cooperative::executor::Executor::new();
     let spawner = executor.get_spawner();
     println!("got spawner");
-    let task1 = rustos::cooperative::task::Task::new(buggy_example_task(spawner.clone()));
-    println!("got task1");
-    let task2 = rustos::cooperative::task::Task::new(keyboard_printer(spawner.clone()));
-    println!("got task2");
-    executor.spawn(task1);
+    executor.spawn(buggy_example_task(spawner.clone())).expect("failed to spawn task1");
     println!("spawned task1");
-    executor.spawn(task2);
+    executor.spawn(keyboard_printer(spawner.clone())).expect("failed to spawn task2");
     println!("spawned task2");
+    for terminal in 0..rustos::vga_buffer::TERMINAL_COUNT {
+        executor.spawn(terminal_echo(terminal)).expect("failed to spawn a terminal echo task");
+    }
     executor.run();
 
     println!("It did not crash!");
     rustos::arch::halt_loop();
 }
 
-async fn keyboard_printer(mut spawner: rustos::cooperative::executor::Spawner) {
+async fn keyboard_printer(spawner: rustos::cooperative::executor::Spawner) {
     use futures_util::stream::StreamExt;
+    use rustos::arch::ps2::keyboard::{self as ps2_keyboard, Leds};
+    use rustos::cooperative::terminal::dispatch_key;
+    use rustos::keyboard::{layouts, DecodedKey, KeyCode, Keyboard};
     let mut codes = rustos::cooperative::keyboard::ScancodeStream::new();
+    let mut keyboard = Keyboard::new(ps2_keyboard::received_scancode_set(), &layouts::US);
+    let mut leds = Leds::default();
 
     while let Some(scancode) = codes.next().await {
-        println!("{}", scancode);
+        match keyboard.process_byte(scancode) {
+            Some(DecodedKey::RawKey(KeyCode::PageUp)) => rustos::vga_buffer::page_up(),
+            Some(DecodedKey::RawKey(KeyCode::PageDown)) => rustos::vga_buffer::page_down(),
+            Some(key) => dispatch_key(key, &keyboard.modifiers()),
+            None => {}
+        }
+
+        let lock_state = Leds::from_modifiers(&keyboard.modifiers());
+        if lock_state != leds {
+            leds = lock_state;
+            ps2_keyboard::request_leds(leds);
+        }
+    }
+}
+
+async fn terminal_echo(terminal: usize) {
+    use futures_util::stream::StreamExt;
+    use rustos::keyboard::DecodedKey;
+    let mut keys = match rustos::cooperative::terminal::TerminalInput::try_new(terminal) {
+        Some(keys) => keys,
+        None => return,
+    };
+
+    while let Some(key) = keys.next().await {
+        match key {
+            DecodedKey::Unicode(c) => rustos::vga_buffer::print_to(terminal, format_args!("{}", c)),
+            DecodedKey::RawKey(key) => rustos::vga_buffer::print_to(terminal, format_args!("{:?}", key)),
+        }
     }
 }
 
@@ -64,12 +97,11 @@ async fn async_number() -> u32 {
     42
 }
 
-async fn buggy_example_task(mut spawner: rustos::cooperative::executor::Spawner) {
+async fn buggy_example_task(spawner: rustos::cooperative::executor::Spawner) {
     let number = async_number().await;
     let mut i = 0;
     println!("async number: {}", number);
-    let new_task = rustos::cooperative::task::Task::new(buggy_example_task(spawner.clone()));
-    spawner.spawn(new_task);
+    spawner.spawn(buggy_example_task(spawner.clone())).expect("failed to respawn the example task");
 }
 
 // TESTS
@@ -0,0 +1,164 @@
+//! Decoding of PS/2 mouse packets.
+
+/// Layout of the packets sent by the mouse, selected while enabling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketFormat {
+    /// Three bytes, three buttons.
+    Standard,
+    /// IntelliMouse: a fourth byte with the wheel movement.
+    Wheel,
+    /// IntelliMouse Explorer: the fourth byte also carries buttons 4 and 5.
+    FiveButton,
+}
+
+impl PacketFormat {
+    pub fn len(self) -> usize {
+        match self {
+            PacketFormat::Standard => 3,
+            PacketFormat::Wheel | PacketFormat::FiveButton => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+    pub fourth: bool,
+    pub fifth: bool,
+}
+
+/// State reported by one packet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Movement to the right since the last packet.
+    pub dx: i16,
+    /// Movement up since the last packet.
+    pub dy: i16,
+    /// Wheel clicks, positive when scrolling down.
+    pub wheel: i8,
+    /// Buttons held.
+    pub buttons: MouseButtons,
+}
+
+const LEFT_BUTTON: u8 = 1 << 0;
+const RIGHT_BUTTON: u8 = 1 << 1;
+const MIDDLE_BUTTON: u8 = 1 << 2;
+/// Always set in the first byte, used to find packet boundaries.
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+const FOURTH_BUTTON: u8 = 1 << 4;
+const FIFTH_BUTTON: u8 = 1 << 5;
+
+/// Assembles packets from the bytes sent by the mouse.
+pub struct PacketDecoder {
+    format: PacketFormat,
+    packet: [u8; 4],
+    len: usize,
+}
+
+impl PacketDecoder {
+    pub const fn new(format: PacketFormat) -> PacketDecoder {
+        PacketDecoder {
+            format,
+            packet: [0; 4],
+            len: 0,
+        }
+    }
+
+    pub fn format(&self) -> PacketFormat {
+        self.format
+    }
+
+    /// Feed one byte, returning an event once a packet is complete.
+    ///
+    /// Bytes that cannot start a packet are dropped, which resynchronizes
+    /// the decoder after a lost byte.
+    pub fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        if self.len == 0 && byte & ALWAYS_ONE == 0 {
+            return None;
+        }
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < self.format.len() {
+            return None;
+        }
+        self.len = 0;
+        Some(self.decode())
+    }
+
+    fn decode(&self) -> MouseEvent {
+        let [flags, x, y, extra] = self.packet;
+        let mut event = MouseEvent {
+            dx: movement(x, flags & X_SIGN != 0, flags & X_OVERFLOW != 0),
+            dy: movement(y, flags & Y_SIGN != 0, flags & Y_OVERFLOW != 0),
+            wheel: 0,
+            buttons: MouseButtons {
+                left: flags & LEFT_BUTTON != 0,
+                right: flags & RIGHT_BUTTON != 0,
+                middle: flags & MIDDLE_BUTTON != 0,
+                fourth: false,
+                fifth: false,
+            },
+        };
+
+        match self.format {
+            PacketFormat::Standard => {}
+            PacketFormat::Wheel => event.wheel = extra as i8,
+            PacketFormat::FiveButton => {
+                // Sign extend the low nibble.
+                event.wheel = ((extra << 4) as i8) >> 4;
+                event.buttons.fourth = extra & FOURTH_BUTTON != 0;
+                event.buttons.fifth = extra & FIFTH_BUTTON != 0;
+            }
+        }
+        event
+    }
+}
+
+/// 9-bit two's complement movement, dropped on overflow as it is garbage.
+fn movement(low: u8, negative: bool, overflow: bool) -> i16 {
+    if overflow {
+        0
+    } else if negative {
+        i16::from(low) - 0x100
+    } else {
+        i16::from(low)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    #[test_case]
+    fn test_standard_packet() {
+        serial_print!("test_standard_packet...");
+        let mut decoder = PacketDecoder::new(PacketFormat::Standard);
+        assert_eq!(decoder.add_byte(0x29), None);
+        assert_eq!(decoder.add_byte(0x05), None);
+        let event = decoder.add_byte(0xfe).unwrap();
+        assert_eq!((event.dx, event.dy), (5, -2));
+        assert!(event.buttons.left && !event.buttons.right);
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_wheel_packet_and_resync() {
+        serial_print!("test_wheel_packet_and_resync...");
+        let mut decoder = PacketDecoder::new(PacketFormat::Wheel);
+        // A stray byte without bit 3 set is skipped.
+        let bytes = [0x00, 0x18, 0xff, 0x00, 0xff];
+        let events: alloc::vec::Vec<_> =
+            bytes.iter().filter_map(|&byte| decoder.add_byte(byte)).collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].dx, events[0].dy, events[0].wheel), (-1, 0, -1));
+        serial_println!("[ok]");
+    }
+}
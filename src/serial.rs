@@ -1,26 +1,432 @@
-use uart_16550::SerialPort;
-use spin::Mutex;
-use lazy_static::lazy_static;
+//! Driver for the COM1 to COM4 serial ports.
+//!
+//! Output goes through a per-port ring buffer drained by the transmit
+//! interrupt, so writers only wait for the UART when the buffer is full or
+//! interrupts are disabled.
 
-const  UART_PORT_1 : u16 = 0x3F8;
+mod uart;
 
-lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(UART_PORT_1) };
-        serial_port.init();
-        Mutex::new(serial_port)
-    };
+use core::fmt;
+
+use spin::{Mutex, Once};
+
+use crate::arch::interrupts::{SERIAL_IRQ_1, SERIAL_IRQ_2};
+use crate::arch::{self, no_interrupts};
+use uart::{InterruptCause, Uart, ENABLE_RECEIVED, ENABLE_TRANSMIT_EMPTY};
+
+/// Size of the transmit buffer of each port.
+pub const TX_BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl ComPort {
+    pub const ALL: [ComPort; 4] = [ComPort::Com1, ComPort::Com2, ComPort::Com3, ComPort::Com4];
+
+    pub fn base(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3f8,
+            ComPort::Com2 => 0x2f8,
+            ComPort::Com3 => 0x3e8,
+            ComPort::Com4 => 0x2e8,
+        }
+    }
+
+    /// PIC line the port raises interrupts on, shared by two ports.
+    pub fn irq_line(self) -> u8 {
+        match self {
+            ComPort::Com1 | ComPort::Com3 => SERIAL_IRQ_1,
+            ComPort::Com2 | ComPort::Com4 => SERIAL_IRQ_2,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        match self {
+            ComPort::Com1 => 0,
+            ComPort::Com2 => 1,
+            ComPort::Com3 => 2,
+            ComPort::Com4 => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    /// Parity bit always set.
+    Mark,
+    /// Parity bit always clear.
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    /// One and a half with five data bits.
+    Two,
+}
+
+/// Number of received bytes that raise an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FifoTrigger {
+    Bytes1,
+    Bytes4,
+    Bytes8,
+    Bytes14,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    /// Must divide 115200.
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// `None` disables the FIFOs.
+    pub fifo: Option<FifoTrigger>,
+}
+
+impl Default for SerialConfig {
+    /// 115200 baud, 8N1, with FIFOs.
+    fn default() -> SerialConfig {
+        SerialConfig {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            fifo: Some(FifoTrigger::Bytes14),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// Nothing answers at the port address.
+    NotPresent,
+    /// A UART is there, but does not receive what it sends in loopback mode.
+    LoopbackFailed,
+    InvalidBaudRate,
+}
+
+struct TxRing {
+    buffer: [u8; TX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl TxRing {
+    const fn new() -> TxRing {
+        TxRing {
+            buffer: [0; TX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == TX_BUFFER_SIZE {
+            return false;
+        }
+        self.buffer[(self.head + self.len) % TX_BUFFER_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % TX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+struct Port {
+    uart: Uart,
+    config: Option<SerialConfig>,
+    fifo_size: usize,
+    /// The interrupt handler is installed, so the ring is drained by it.
+    interrupt_driven: bool,
+    /// The transmit interrupt is enabled because the ring is not empty.
+    transmitting: bool,
+    tx: TxRing,
+}
+
+impl Port {
+    const fn new(base: u16) -> Port {
+        Port {
+            uart: Uart::new(base),
+            config: None,
+            fifo_size: 1,
+            interrupt_driven: false,
+            transmitting: false,
+            tx: TxRing::new(),
+        }
+    }
+
+    /// Move bytes from the ring to the UART as long as it takes them
+    /// without waiting.
+    fn fill_fifo(&mut self) {
+        if !self.uart.can_transmit() {
+            return;
+        }
+        for _ in 0..self.fifo_size {
+            match self.tx.pop() {
+                Some(byte) => self.uart.transmit(byte),
+                None => break,
+            }
+        }
+    }
+
+    /// Keep the transmit interrupt enabled exactly while there is something
+    /// to send.
+    fn update_transmit_interrupt(&mut self) {
+        let transmitting = self.interrupt_driven && !self.tx.is_empty();
+        if transmitting != self.transmitting {
+            if transmitting {
+                self.uart.enable_interrupts(ENABLE_TRANSMIT_EMPTY);
+            } else {
+                self.uart.disable_interrupts(ENABLE_TRANSMIT_EMPTY);
+            }
+            self.transmitting = transmitting;
+        }
+    }
+
+    /// Send the whole ring by polling the UART.
+    fn drain(&mut self) {
+        while !self.tx.is_empty() {
+            while !self.uart.can_transmit() {
+                core::sync::atomic::spin_loop_hint();
+            }
+            self.fill_fifo();
+        }
+        self.update_transmit_interrupt();
+    }
+
+    /// Queue as much of `bytes` as fits, returning how much did.
+    fn queue(&mut self, bytes: &[u8]) -> usize {
+        let mut queued = 0;
+        for &byte in bytes {
+            if !self.tx.push(byte) {
+                break;
+            }
+            queued += 1;
+        }
+        if !self.transmitting {
+            self.fill_fifo();
+        }
+        self.update_transmit_interrupt();
+        queued
+    }
+}
+
+// Taken by the interrupt handler, and with interrupts disabled everywhere
+// else.
+static PORTS: [Mutex<Port>; 4] = [
+    Mutex::new(Port::new(0x3f8)),
+    Mutex::new(Port::new(0x2f8)),
+    Mutex::new(Port::new(0x3e8)),
+    Mutex::new(Port::new(0x2e8)),
+];
+
+static DETECTED: Once<()> = Once::new();
+
+fn port(com: ComPort) -> &'static Mutex<Port> {
+    &PORTS[com.index()]
+}
+
+/// Probe every port and configure the ones found with the default settings.
+///
+/// Runs on first use of the driver, later calls do nothing.
+pub fn init() {
+    DETECTED.call_once(|| {
+        for &com in ComPort::ALL.iter() {
+            no_interrupts(|| {
+                let mut port = port(com).lock();
+                if port.uart.loopback_test().is_ok() {
+                    let config = SerialConfig::default();
+                    if let Ok(fifo_size) = port.uart.configure(&config) {
+                        port.config = Some(config);
+                        port.fifo_size = fifo_size;
+                    }
+                }
+            });
+        }
+    });
+}
+
+pub fn is_present(com: ComPort) -> bool {
+    init();
+    no_interrupts(|| port(com).lock().config.is_some())
+}
+
+/// Ports that passed detection.
+pub fn present_ports() -> impl Iterator<Item = ComPort> {
+    ComPort::ALL.iter().cloned().filter(|&com| is_present(com))
+}
+
+pub fn config(com: ComPort) -> Option<SerialConfig> {
+    init();
+    no_interrupts(|| port(com).lock().config)
+}
+
+/// Change the line settings of a port, after sending what is buffered.
+pub fn configure(com: ComPort, config: SerialConfig) -> Result<(), SerialError> {
+    init();
+    flush(com);
+    no_interrupts(|| {
+        let mut port = port(com).lock();
+        if port.config.is_none() {
+            return Err(SerialError::NotPresent);
+        }
+        port.fifo_size = port.uart.configure(&config)?;
+        port.config = Some(config);
+        Ok(())
+    })
+}
+
+/// Switch present ports to interrupt driven input and output.
+///
+/// Called once the handlers for their lines are installed.
+pub(crate) fn enable_interrupts() {
+    for com in present_ports() {
+        no_interrupts(|| {
+            let mut port = port(com).lock();
+            port.interrupt_driven = true;
+            port.uart.enable_interrupts(ENABLE_RECEIVED);
+            port.update_transmit_interrupt();
+        });
+    }
 }
 
+/// Service the ports on PIC line `line`.
+///
+/// Returns whether any of them raised the interrupt.
+pub(crate) fn handle_interrupt(line: u8) -> bool {
+    let mut handled = false;
+    for &com in ComPort::ALL.iter().filter(|com| com.irq_line() == line) {
+        let mut port = port(com).lock();
+        if port.config.is_none() {
+            continue;
+        }
+        while let Some(cause) = port.uart.pending_interrupt() {
+            handled = true;
+            match cause {
+                InterruptCause::Received | InterruptCause::ReceiveTimeout => {
+                    while let Some(byte) = port.uart.try_receive() {
+                        crate::cooperative::serial::add_byte(com, byte);
+                    }
+                }
+                InterruptCause::TransmitEmpty => {
+                    port.fill_fifo();
+                    port.update_transmit_interrupt();
+                }
+                InterruptCause::LineStatus | InterruptCause::ModemStatus => {}
+            }
+        }
+    }
+    handled
+}
+
+/// Send `bytes` on `com`, dropping them if the port is missing.
+///
+/// Only waits when the transmit buffer is full. With interrupts disabled,
+/// or before they are set up, the buffer is drained by polling instead.
+pub fn write(com: ComPort, mut bytes: &[u8]) {
+    init();
+    while !bytes.is_empty() {
+        let interrupts_enabled = arch::interrupts_enabled();
+        let queued = no_interrupts(|| {
+            let mut port = port(com).lock();
+            if port.config.is_none() {
+                return bytes.len();
+            }
+            let queued = port.queue(bytes);
+            if !interrupts_enabled || !port.interrupt_driven {
+                port.drain();
+            }
+            queued
+        });
+        bytes = &bytes[queued..];
+        if !bytes.is_empty() {
+            // Let the transmit interrupt make room.
+            arch::halt();
+        }
+    }
+}
+
+/// Number of bytes written to `com` and waiting in its transmit buffer.
+pub fn queued(com: ComPort) -> usize {
+    init();
+    no_interrupts(|| port(com).lock().tx.len)
+}
+
+/// Wait until everything written to `com` has been sent.
+pub fn flush(com: ComPort) {
+    init();
+    no_interrupts(|| {
+        let mut port = port(com).lock();
+        if port.config.is_some() {
+            port.drain();
+            while !port.uart.is_idle() {
+                core::sync::atomic::spin_loop_hint();
+            }
+        }
+    })
+}
+
+pub fn flush_all() {
+    for com in present_ports() {
+        flush(com);
+    }
+}
+
+/// `fmt::Write` adapter writing to a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialWriter(pub ComPort);
+
+impl fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(self.0, s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Keeps concurrent prints from interleaving.
+static PRINT_LOCK: Mutex<()> = Mutex::new(());
+
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
 
-    use crate::arch::no_interrupts;
-
-    no_interrupts(|| {
-        SERIAL1.lock().write_fmt(args).expect("Printing to serial failed");
-    });
+    // The holder may have been interrupted, do not wait for it then.
+    let _guard = if arch::interrupts_enabled() {
+        Some(PRINT_LOCK.lock())
+    } else {
+        PRINT_LOCK.try_lock()
+    };
+    SerialWriter(ComPort::Com1).write_fmt(args).expect("Printing to serial failed");
 }
 
 /// Prints to the host through the serial interface.
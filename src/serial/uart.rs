@@ -0,0 +1,235 @@
+//! Registers of the 8250/16550 UART.
+
+use crate::arch::{read_port_u8, write_port_u8};
+
+use super::{DataBits, FifoTrigger, Parity, SerialConfig, SerialError, StopBits};
+
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+/// Interrupt identification on reads, FIFO control on writes.
+const INTERRUPT_ID: u16 = 2;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const MODEM_STATUS: u16 = 6;
+const SCRATCH: u16 = 7;
+
+/// Divisor latch registers, in place of `DATA` and `INTERRUPT_ENABLE`
+/// while `LINE_CONTROL_DLAB` is set.
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+
+pub(super) const ENABLE_RECEIVED: u8 = 1 << 0;
+pub(super) const ENABLE_TRANSMIT_EMPTY: u8 = 1 << 1;
+const ENABLE_LINE_STATUS: u8 = 1 << 2;
+
+const INTERRUPT_NONE_PENDING: u8 = 1 << 0;
+const INTERRUPT_CAUSE_MASK: u8 = 0x0e;
+const INTERRUPT_FIFO_ENABLED: u8 = 0xc0;
+
+const FIFO_ENABLE: u8 = 1 << 0;
+const FIFO_CLEAR_RECEIVE: u8 = 1 << 1;
+const FIFO_CLEAR_TRANSMIT: u8 = 1 << 2;
+
+const LINE_CONTROL_TWO_STOP_BITS: u8 = 1 << 2;
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+
+const MODEM_DTR: u8 = 1 << 0;
+const MODEM_RTS: u8 = 1 << 1;
+const MODEM_OUT1: u8 = 1 << 2;
+/// Gates the interrupt output of the UART on PCs.
+const MODEM_OUT2: u8 = 1 << 3;
+const MODEM_LOOPBACK: u8 = 1 << 4;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+/// Both the holding and shift registers are empty.
+const LINE_STATUS_IDLE: u8 = 1 << 6;
+
+/// Clock of the baud rate generator divided by 16.
+pub(super) const MAX_BAUD_RATE: u32 = 115_200;
+
+/// Transmit FIFO size of the 16550A.
+const FIFO_SIZE: usize = 16;
+
+const LOOPBACK_TEST_BYTE: u8 = 0xae;
+
+/// Why the UART raised an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum InterruptCause {
+    ModemStatus,
+    TransmitEmpty,
+    Received,
+    LineStatus,
+    /// Bytes wait in the receive FIFO below its trigger level.
+    ReceiveTimeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Uart {
+    base: u16,
+}
+
+impl Uart {
+    pub const fn new(base: u16) -> Uart {
+        Uart { base }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        read_port_u8(self.base + register)
+    }
+
+    fn write(&self, register: u16, value: u8) {
+        write_port_u8(self.base + register, value)
+    }
+
+    /// Check a UART answers at this address by sending a byte to itself.
+    pub fn loopback_test(&self) -> Result<(), SerialError> {
+        self.write(SCRATCH, LOOPBACK_TEST_BYTE);
+        if self.read(SCRATCH) != LOOPBACK_TEST_BYTE {
+            return Err(SerialError::NotPresent);
+        }
+
+        self.write(INTERRUPT_ENABLE, 0);
+        self.write(MODEM_CONTROL, MODEM_LOOPBACK | MODEM_RTS | MODEM_OUT1 | MODEM_OUT2);
+        // Drop whatever was received before.
+        while self.read(LINE_STATUS) & LINE_STATUS_DATA_READY != 0 {
+            self.read(DATA);
+        }
+        self.write(DATA, LOOPBACK_TEST_BYTE);
+        let mut received = None;
+        for _ in 0..1000 {
+            if self.read(LINE_STATUS) & LINE_STATUS_DATA_READY != 0 {
+                received = Some(self.read(DATA));
+                break;
+            }
+        }
+        self.write(MODEM_CONTROL, MODEM_DTR | MODEM_RTS | MODEM_OUT2);
+
+        match received {
+            Some(LOOPBACK_TEST_BYTE) => Ok(()),
+            _ => Err(SerialError::LoopbackFailed),
+        }
+    }
+
+    /// Program line settings and FIFOs, leaving interrupts as they were.
+    ///
+    /// Returns how many bytes can be written at once.
+    pub fn configure(&self, config: &SerialConfig) -> Result<usize, SerialError> {
+        if config.baud_rate == 0
+            || config.baud_rate > MAX_BAUD_RATE
+            || MAX_BAUD_RATE % config.baud_rate != 0
+        {
+            return Err(SerialError::InvalidBaudRate);
+        }
+        let divisor = (MAX_BAUD_RATE / config.baud_rate) as u16;
+
+        let mut line_control = match config.data_bits {
+            DataBits::Five => 0,
+            DataBits::Six => 1,
+            DataBits::Seven => 2,
+            DataBits::Eight => 3,
+        };
+        if config.stop_bits == StopBits::Two {
+            line_control |= LINE_CONTROL_TWO_STOP_BITS;
+        }
+        line_control |= match config.parity {
+            Parity::None => 0b000,
+            Parity::Odd => 0b001,
+            Parity::Even => 0b011,
+            Parity::Mark => 0b101,
+            Parity::Space => 0b111,
+        } << 3;
+
+        let interrupts = self.read(INTERRUPT_ENABLE);
+        self.write(INTERRUPT_ENABLE, 0);
+        self.write(LINE_CONTROL, LINE_CONTROL_DLAB);
+        self.write(DIVISOR_LOW, divisor as u8);
+        self.write(DIVISOR_HIGH, (divisor >> 8) as u8);
+        self.write(LINE_CONTROL, line_control);
+
+        let fifo_size = match config.fifo {
+            Some(trigger) => {
+                let trigger = match trigger {
+                    FifoTrigger::Bytes1 => 0x00,
+                    FifoTrigger::Bytes4 => 0x40,
+                    FifoTrigger::Bytes8 => 0x80,
+                    FifoTrigger::Bytes14 => 0xc0,
+                };
+                self.write(
+                    FIFO_CONTROL,
+                    FIFO_ENABLE | FIFO_CLEAR_RECEIVE | FIFO_CLEAR_TRANSMIT | trigger,
+                );
+                // Older UARTs have no FIFO, or a broken one.
+                if self.read(INTERRUPT_ID) & INTERRUPT_FIFO_ENABLED == INTERRUPT_FIFO_ENABLED {
+                    FIFO_SIZE
+                } else {
+                    self.write(FIFO_CONTROL, 0);
+                    1
+                }
+            }
+            None => {
+                self.write(FIFO_CONTROL, 0);
+                1
+            }
+        };
+
+        self.write(MODEM_CONTROL, MODEM_DTR | MODEM_RTS | MODEM_OUT2);
+        self.write(INTERRUPT_ENABLE, interrupts);
+        Ok(fifo_size)
+    }
+
+    pub fn enable_interrupts(&self, interrupts: u8) {
+        let enabled = self.read(INTERRUPT_ENABLE);
+        self.write(INTERRUPT_ENABLE, enabled | interrupts | ENABLE_LINE_STATUS);
+    }
+
+    pub fn disable_interrupts(&self, interrupts: u8) {
+        let enabled = self.read(INTERRUPT_ENABLE);
+        self.write(INTERRUPT_ENABLE, enabled & !interrupts);
+    }
+
+    pub fn pending_interrupt(&self) -> Option<InterruptCause> {
+        let id = self.read(INTERRUPT_ID);
+        if id & INTERRUPT_NONE_PENDING != 0 {
+            return None;
+        }
+        match id & INTERRUPT_CAUSE_MASK {
+            0x00 => {
+                self.read(MODEM_STATUS);
+                Some(InterruptCause::ModemStatus)
+            }
+            0x02 => Some(InterruptCause::TransmitEmpty),
+            0x04 => Some(InterruptCause::Received),
+            0x06 => {
+                self.read(LINE_STATUS);
+                Some(InterruptCause::LineStatus)
+            }
+            _ => Some(InterruptCause::ReceiveTimeout),
+        }
+    }
+
+    pub fn try_receive(&self) -> Option<u8> {
+        if self.read(LINE_STATUS) & LINE_STATUS_DATA_READY != 0 {
+            Some(self.read(DATA))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the transmit FIFO, or holding register, is empty.
+    pub fn can_transmit(&self) -> bool {
+        self.read(LINE_STATUS) & LINE_STATUS_TRANSMIT_EMPTY != 0
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.read(LINE_STATUS) & LINE_STATUS_IDLE != 0
+    }
+
+    /// Write a byte, only valid after `can_transmit` returned true, and for
+    /// as many bytes as the FIFO holds.
+    pub fn transmit(&self, byte: u8) {
+        self.write(DATA, byte)
+    }
+}
@@ -0,0 +1,72 @@
+use core::convert::TryInto;
+
+/// Kernel symbol table generated by `build.rs`.
+///
+/// The table is empty unless the kernel was built with `RUSTOS_SYMBOL_MAP`
+/// pointing at the symbol map of a previous build; see the README.
+static KSYMS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ksyms.bin"));
+
+const MAGIC: &[u8] = b"KSYM";
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 16;
+
+/// Symbols further away than this from an address are not reported.
+const MAX_SYMBOL_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub address: usize,
+    pub name: &'static str,
+}
+
+fn read_u32(offset: usize) -> u32 {
+    u32::from_le_bytes(KSYMS[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(offset: usize) -> u64 {
+    u64::from_le_bytes(KSYMS[offset..offset + 8].try_into().unwrap())
+}
+
+/// Number of symbols in the table.
+pub fn len() -> usize {
+    if KSYMS.len() < HEADER_SIZE || &KSYMS[..4] != MAGIC {
+        return 0;
+    }
+    read_u32(4) as usize
+}
+
+fn entry(index: usize) -> Symbol {
+    let offset = HEADER_SIZE + index * ENTRY_SIZE;
+    let address = read_u64(offset) as usize;
+    let name_offset = read_u32(offset + 8) as usize;
+    let name_len = read_u32(offset + 12) as usize;
+    let name = core::str::from_utf8(&KSYMS[name_offset..name_offset + name_len])
+        .unwrap_or("<invalid symbol>");
+    Symbol { address, name }
+}
+
+/// Find the symbol containing `address`, and the offset of `address` into it.
+pub fn lookup(address: usize) -> Option<(Symbol, usize)> {
+    // Entries are sorted by address: find the last one starting at or before
+    // `address`.
+    let (mut low, mut high) = (0, len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if entry(mid).address <= address {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    if low == 0 {
+        return None;
+    }
+    let symbol = entry(low - 1);
+    let offset = address - symbol.address;
+    if offset < MAX_SYMBOL_SIZE {
+        Some((symbol, offset))
+    } else {
+        None
+    }
+}
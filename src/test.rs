@@ -17,6 +17,9 @@ pub enum QemuExitCode {
 pub fn exit_qemu(exit_code: QemuExitCode) {
     use x86_64::instructions::port::Port;
 
+    // QEMU exits before sending what is still buffered.
+    crate::serial::flush_all();
+
     unsafe {
         let mut port = Port::new(0xf4);
         port.write(exit_code as u32);
@@ -33,6 +36,7 @@ macro_rules! test_panic {
                 QemuExitCode::Failed => {
                     serial_println!("[failed]\n");
                     serial_println!("Error: {}\n", info);
+                    serial_println!("{}", $crate::backtrace::Backtrace::capture());
                 },
                 QemuExitCode::Success => {
                     serial_println!("[ok]");
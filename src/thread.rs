@@ -0,0 +1,476 @@
+//! Preemptive kernel threads.
+//!
+//! Every thread runs on its own stack. The scheduler switches threads
+//! round-robin on every timer tick, or earlier when a thread yields, sleeps
+//! or waits for another one. Switching happens on interrupt return: the
+//! registers of the running thread are saved on its stack by the interrupt
+//! entry code, and the frame of the next thread is restored instead.
+//!
+//! The scheduler never allocates and is only touched with interrupts
+//! disabled. Stacks are allocated and freed by the threads spawning and
+//! joining, with interrupts enabled, as the allocator may be locked by a
+//! preempted thread. Below every stack lies an unmapped guard page, so an
+//! overflow faults instead of overwriting the heap.
+
+use alloc::{
+    alloc::{alloc, dealloc, handle_alloc_error, Layout},
+    boxed::Box,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use x86_64::VirtAddr;
+
+use crate::arch::{context, memory};
+
+/// Stack size of spawned threads, the guard page not included.
+pub const STACK_SIZE: usize = 64 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Maximum number of threads, the boot and idle threads included.
+pub const MAX_THREADS: usize = 64;
+
+const BOOT_SLOT: usize = 0;
+const IDLE_SLOT: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    /// The thread the kernel booted on.
+    pub const BOOT: ThreadId = ThreadId(0);
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadError {
+    /// `init` was not called.
+    NotInitialized,
+    TooManyThreads,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Ready,
+    Running,
+    /// Sleeping until the given tick.
+    Sleeping(u64),
+    /// Waiting for another thread to finish.
+    Joining(ThreadId),
+    Finished,
+}
+
+struct Thread {
+    id: ThreadId,
+    name: &'static str,
+    state: ThreadState,
+    /// Saved stack pointer, pointing to the frame to resume.
+    context: usize,
+    /// `None` for the boot thread, which runs on the bootloader's stack.
+    stack: Option<Stack>,
+    /// Nobody will join the thread, so it is freed once it finished.
+    detached: bool,
+    /// See `set_local`.
+    local: usize,
+}
+
+/// A thread stack of `STACK_SIZE` bytes on the heap, above a guard page.
+struct Stack {
+    /// Start of the allocation, the guard page.
+    base: *mut u8,
+    /// Whether the guard page could be unmapped.
+    guarded: bool,
+}
+
+// The stack is owned by its thread's entry in the scheduler.
+unsafe impl Send for Stack {}
+
+impl Stack {
+    fn layout() -> Layout {
+        Layout::from_size_align(PAGE_SIZE + STACK_SIZE, PAGE_SIZE).expect("invalid stack layout")
+    }
+
+    fn new() -> Stack {
+        let layout = Stack::layout();
+        let base = unsafe { alloc(layout) };
+        if base.is_null() {
+            handle_alloc_error(layout);
+        }
+        let guarded = unsafe { memory::set_page_present(VirtAddr::from_ptr(base), false) };
+        Stack { base, guarded }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.base.add(PAGE_SIZE), STACK_SIZE) }
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        // The allocator writes to freed memory.
+        if self.guarded {
+            unsafe { memory::set_page_present(VirtAddr::from_ptr(self.base), true) };
+        }
+        unsafe { dealloc(self.base, Stack::layout()) };
+    }
+}
+
+struct Scheduler {
+    threads: Vec<Option<Thread>>,
+    current: usize,
+    next_id: u64,
+}
+
+impl Scheduler {
+    fn current(&mut self) -> &mut Thread {
+        self.threads[self.current].as_mut().expect("current thread missing")
+    }
+
+    fn slot(&self, id: ThreadId) -> Option<usize> {
+        self.threads.iter().position(|thread| thread.as_ref().map(|t| t.id) == Some(id))
+    }
+
+    fn is_finished(&self, id: ThreadId) -> bool {
+        match self.slot(id) {
+            Some(slot) => self.threads[slot].as_ref().map(|t| t.state) == Some(ThreadState::Finished),
+            // Already joined.
+            None => true,
+        }
+    }
+
+    /// Make sleeping and joining threads ready once they can continue.
+    fn wake_threads(&mut self, now: u64) {
+        for slot in 0..MAX_THREADS {
+            let state = match &self.threads[slot] {
+                Some(thread) => thread.state,
+                None => continue,
+            };
+            let ready = match state {
+                ThreadState::Sleeping(until) => until <= now,
+                ThreadState::Joining(id) => self.is_finished(id),
+                _ => false,
+            };
+            if ready {
+                self.threads[slot].as_mut().expect("should exist").state = ThreadState::Ready;
+            }
+        }
+    }
+
+    /// Next ready thread after the current one, or the idle thread.
+    fn pick_next(&self) -> usize {
+        (1..=MAX_THREADS)
+            .map(|offset| (self.current + offset) % MAX_THREADS)
+            .filter(|&slot| slot != IDLE_SLOT)
+            .find(|&slot| {
+                self.threads[slot].as_ref().map(|t| t.state) == Some(ThreadState::Ready)
+            })
+            .unwrap_or(IDLE_SLOT)
+    }
+
+    fn switch(&mut self, context: usize) -> usize {
+        let current = self.current();
+        current.context = context;
+        if current.state == ThreadState::Running {
+            current.state = ThreadState::Ready;
+        }
+
+        self.wake_threads(TICKS.load(Ordering::SeqCst));
+        self.current = self.pick_next();
+        let next = self.current();
+        next.state = ThreadState::Running;
+        next.context
+    }
+
+    fn has_other_ready(&self) -> bool {
+        self.threads.iter().enumerate().any(|(slot, thread)| {
+            slot != self.current
+                && slot != IDLE_SLOT
+                && thread.as_ref().map(|t| t.state) == Some(ThreadState::Ready)
+        })
+    }
+
+    /// Remove a finished thread that nobody will join.
+    fn take_detached(&mut self) -> Option<Thread> {
+        let slot = self.threads.iter().position(|thread| match thread {
+            Some(thread) => thread.detached && thread.state == ThreadState::Finished,
+            None => false,
+        })?;
+        self.threads[slot].take()
+    }
+}
+
+static SCHEDULER: spin::Mutex<Option<Scheduler>> = spin::Mutex::new(None);
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+fn with_scheduler<R>(f: impl FnOnce(&mut Scheduler) -> R) -> Result<R, ThreadError> {
+    crate::arch::no_interrupts(|| {
+        SCHEDULER.lock().as_mut().map(f).ok_or(ThreadError::NotInitialized)
+    })
+}
+
+/// Turn the running code into the boot thread and start scheduling.
+///
+/// Needs the heap.
+pub fn init() {
+    let mut threads = Vec::with_capacity(MAX_THREADS);
+    threads.resize_with(MAX_THREADS, || None);
+    threads[BOOT_SLOT] = Some(Thread {
+        id: ThreadId::BOOT,
+        name: "boot",
+        state: ThreadState::Running,
+        context: 0,
+        stack: None,
+        detached: true,
+        local: 0,
+    });
+
+    let mut stack = Stack::new();
+    let context = context::init_stack(stack.as_mut_slice(), idle_entry, 0);
+    threads[IDLE_SLOT] = Some(Thread {
+        id: ThreadId(1),
+        name: "idle",
+        state: ThreadState::Ready,
+        context,
+        stack: Some(stack),
+        detached: false,
+        local: 0,
+    });
+
+    let scheduler = Scheduler {
+        threads,
+        current: BOOT_SLOT,
+        next_id: 2,
+    };
+    crate::arch::no_interrupts(|| *SCHEDULER.lock() = Some(scheduler));
+}
+
+/// Called by the timer interrupt handler.
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::SeqCst);
+    context::request_reschedule();
+}
+
+/// Called on interrupt return with the saved context of the interrupted
+/// thread, returns the context to resume.
+pub(crate) fn schedule(context: usize) -> usize {
+    match SCHEDULER.lock().as_mut() {
+        Some(scheduler) => scheduler.switch(context),
+        None => context,
+    }
+}
+
+/// Number of timer ticks since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::SeqCst)
+}
+
+/// Id of the running thread.
+pub fn current() -> ThreadId {
+    with_scheduler(|scheduler| scheduler.current().id).unwrap_or(ThreadId::BOOT)
+}
+
+/// Word set by `set_local` for the running thread, 0 until then.
+pub(crate) fn local() -> Result<usize, ThreadError> {
+    with_scheduler(|scheduler| scheduler.current().local)
+}
+
+/// Set the word returned by `local` on the running thread, returning the
+/// previous one. No other thread can read it.
+pub(crate) fn set_local(value: usize) -> Result<usize, ThreadError> {
+    with_scheduler(|scheduler| core::mem::replace(&mut scheduler.current().local, value))
+}
+
+/// Let other ready threads run.
+pub fn yield_now() {
+    context::reschedule();
+}
+
+/// Suspend the running thread for at least `ticks` timer ticks.
+pub fn sleep(ticks: u64) {
+    let until = self::ticks() + ticks;
+    while self::ticks() < until {
+        if with_scheduler(|scheduler| scheduler.current().state = ThreadState::Sleeping(until)).is_err() {
+            crate::arch::halt();
+            continue;
+        }
+        yield_now();
+    }
+}
+
+/// Wait for the next interrupt, letting other threads run meanwhile.
+///
+/// Must be called with interrupts disabled, returns with them enabled.
+/// Checking for work and waiting cannot race with interrupt handlers.
+pub fn idle() {
+    let others_ready = SCHEDULER.lock().as_ref().map_or(false, Scheduler::has_other_ready);
+    if others_ready {
+        yield_now();
+        crate::arch::interrupts::enable();
+    } else {
+        crate::arch::interrupts::enable_interrupt_halt();
+    }
+}
+
+extern "C" fn idle_entry(_arg: usize) -> ! {
+    crate::arch::halt_loop();
+}
+
+extern "C" fn thread_entry(arg: usize) -> ! {
+    let main = unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce()>) };
+    main();
+    exit();
+}
+
+/// Finish the running thread.
+fn exit() -> ! {
+    let _ = with_scheduler(|scheduler| scheduler.current().state = ThreadState::Finished);
+    loop {
+        yield_now();
+    }
+}
+
+/// Free detached threads that finished.
+fn reap_detached() {
+    while let Ok(Some(thread)) = with_scheduler(Scheduler::take_detached) {
+        drop(thread);
+    }
+}
+
+/// Run `f` in a new thread.
+pub fn spawn<F, T>(name: &'static str, f: F) -> Result<JoinHandle<T>, ThreadError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    reap_detached();
+
+    let result = Arc::new(spin::Mutex::new(None));
+    let output = result.clone();
+    let main: Box<dyn FnOnce()> = Box::new(move || {
+        *output.lock() = Some(f());
+    });
+    let arg = Box::into_raw(Box::new(main)) as usize;
+
+    let mut stack = Stack::new();
+    let context = context::init_stack(stack.as_mut_slice(), thread_entry, arg);
+
+    let mut thread = Some(Thread {
+        id: ThreadId(0),
+        name,
+        state: ThreadState::Ready,
+        context,
+        stack: Some(stack),
+        detached: false,
+        local: 0,
+    });
+    let id = with_scheduler(|scheduler| {
+        let slot = scheduler.threads.iter().position(Option::is_none)?;
+        let mut thread = thread.take().expect("should exist");
+        thread.id = ThreadId(scheduler.next_id);
+        scheduler.next_id += 1;
+        let id = thread.id;
+        scheduler.threads[slot] = Some(thread);
+        Some(id)
+    });
+
+    match id {
+        Ok(Some(id)) => Ok(JoinHandle { id, result }),
+        Ok(None) | Err(_) => {
+            // Free the stack and closure with interrupts enabled.
+            drop(thread);
+            drop(unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce()>) });
+            Err(id.err().unwrap_or(ThreadError::TooManyThreads))
+        }
+    }
+}
+
+/// Handle to wait for a thread and take its result.
+///
+/// Dropping it detaches the thread.
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    result: Arc<spin::Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        with_scheduler(|scheduler| scheduler.is_finished(self.id)).unwrap_or(true)
+    }
+
+    /// Wait for the thread to finish and return its result.
+    pub fn join(self) -> T {
+        let id = self.id;
+        loop {
+            let finished = with_scheduler(|scheduler| {
+                let slot = scheduler.slot(id)?;
+                if scheduler.threads[slot].as_ref().map(|t| t.state) == Some(ThreadState::Finished) {
+                    return scheduler.threads[slot].take();
+                }
+                scheduler.current().state = ThreadState::Joining(id);
+                None
+            });
+            match finished {
+                Ok(Some(thread)) => {
+                    drop(thread);
+                    break;
+                }
+                Ok(None) => yield_now(),
+                Err(_) => break,
+            }
+        }
+        self.result.lock().take().expect("thread finished without a result")
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        let id = self.id;
+        let _ = with_scheduler(|scheduler| {
+            if let Some(slot) = scheduler.slot(id) {
+                scheduler.threads[slot].as_mut().expect("should exist").detached = true;
+            }
+        });
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle").field("id", &self.id).finish()
+    }
+}
+
+/// Snapshot of a thread, see `threads`.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadInfo {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub state: ThreadState,
+}
+
+/// Snapshot of all threads.
+pub fn threads() -> Vec<ThreadInfo> {
+    let mut infos = Vec::with_capacity(MAX_THREADS);
+    let _ = with_scheduler(|scheduler| {
+        for thread in scheduler.threads.iter().flatten() {
+            infos.push(ThreadInfo {
+                id: thread.id,
+                name: thread.name,
+                state: thread.state,
+            });
+        }
+    });
+    infos
+}
@@ -1,8 +1,14 @@
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use spin::Mutex;
 use volatile::Volatile;
-use lazy_static::lazy_static;
+
+use crate::arch::{read_port_u8, write_port_u8};
+
+pub(crate) mod ansi;
+
+use ansi::{Parser, TextScreen, TextState};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,11 +32,38 @@ pub enum Color {
     White = 15,
 }
 
+impl Color {
+    /// Colors in ANSI order, the bright ones last.
+    const ANSI: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Brown,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::Yellow,
+        Color::LightBlue,
+        Color::Pink,
+        Color::LightCyan,
+        Color::White,
+    ];
+
+    /// Index in `ANSI`.
+    fn ansi_index(self) -> usize {
+        Color::ANSI.iter().position(|&color| color == self).unwrap_or(0)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct ColorCode(u8);
 
 impl ColorCode {
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
 }
@@ -45,73 +78,236 @@ struct ScreenChar {
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 
+/// Lines kept for scrolling back, the screen included.
+pub const SCROLLBACK_LINES: usize = 500;
+
+/// Yellow on black, as indices in `Color::ANSI`.
+const DEFAULT_FOREGROUND: usize = 11;
+const DEFAULT_BACKGROUND: usize = 0;
+
+/// Shown for characters missing from code page 437.
+const REPLACEMENT_CHARACTER: u8 = 0xfe;
+
+const CRTC_INDEX: u16 = 0x3d4;
+const CRTC_DATA: u16 = 0x3d5;
+const CRTC_CURSOR_START: u8 = 0x0a;
+const CRTC_CURSOR_END: u8 = 0x0b;
+const CRTC_CURSOR_HIGH: u8 = 0x0e;
+const CRTC_CURSOR_LOW: u8 = 0x0f;
+const CURSOR_DISABLED: u8 = 1 << 5;
+/// Scanlines of the underline cursor.
+const CURSOR_SCANLINES: (u8, u8) = (14, 15);
+
 struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
 }
 
+type Line = [ScreenChar; BUFFER_WIDTH];
+
+/// Text console on the VGA text buffer.
+///
+/// Everything written is kept in a scrollback ring of which the screen
+/// shows the last `BUFFER_HEIGHT` lines, unless scrolled back. Only the
+/// active one of the `TERMINALS` touches the screen.
 pub struct Writer {
-    column_position: usize,
-    color_code: ColorCode,
-    buffer: &'static mut Buffer,
+    text: TextState,
+    parser: Parser,
+    lines: [Line; SCROLLBACK_LINES],
+    /// Index in `lines` of the bottom row of the screen.
+    bottom: usize,
+    /// Number of valid lines in `lines`.
+    stored: usize,
+    /// Lines scrolled back, 0 shows the live screen.
+    view: usize,
+    active: bool,
+    buffer: *mut Buffer,
+}
+
+// The buffer is only accessed through the `WRITER` lock.
+unsafe impl Send for Writer {}
+
+const BLANK: ScreenChar = ScreenChar {
+    ascii_character: b' ',
+    color_code: ColorCode::new(Color::ANSI[DEFAULT_FOREGROUND], Color::ANSI[DEFAULT_BACKGROUND]),
+};
+
+fn color_code(text: &TextState) -> ColorCode {
+    let (foreground, background) = text.colors();
+    ColorCode::new(Color::ANSI[foreground], Color::ANSI[background])
 }
 
 impl Writer {
-    pub fn write_byte(&mut self, byte:u8) {
-        match byte {
-            b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
-                }
-
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-
-                let color_code = self.color_code;
-                self.buffer.chars[row][col].write(ScreenChar {
-                    ascii_character: byte,
-                    color_code,
-                });
-                self.column_position += 1;
+    const fn new(active: bool) -> Writer {
+        Writer {
+            text: TextState::new(BUFFER_HEIGHT - 1, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+            parser: Parser::new(),
+            lines: [[BLANK; BUFFER_WIDTH]; SCROLLBACK_LINES],
+            bottom: BUFFER_HEIGHT - 1,
+            stored: BUFFER_HEIGHT,
+            view: 0,
+            active,
+            buffer: 0xb8000 as *mut Buffer,
+        }
+    }
+
+    fn buffer(&mut self) -> &mut Buffer {
+        unsafe { &mut *self.buffer }
+    }
+
+    /// Index in `lines` of screen row `row`, `view` lines back.
+    fn line_index(&self, row: usize, view: usize) -> usize {
+        (self.bottom + SCROLLBACK_LINES * 2 - (BUFFER_HEIGHT - 1) - view + row) % SCROLLBACK_LINES
+    }
+
+    fn put_char(&mut self, row: usize, col: usize, character: ScreenChar) {
+        let index = self.line_index(row, 0);
+        self.lines[index][col] = character;
+        if self.active && self.view == 0 {
+            self.buffer().chars[row][col].write(character);
+        }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        if let Some(action) = self.parser.advance(byte) {
+            // Copied out, as the writer is the screen it draws on.
+            let mut text = self.text;
+            text.perform(self, action);
+            self.text = text;
+        }
+    }
+
+    /// Set the colors of what is written next, like SGR sequences do.
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.text.set_colors(foreground.ansi_index(), background.ansi_index());
+    }
+
+    /// Copy the lines in view to the screen.
+    fn redraw(&mut self) {
+        if !self.active {
+            return;
+        }
+        for row in 0..BUFFER_HEIGHT {
+            let line = self.lines[self.line_index(row, self.view)];
+            for (col, &character) in line.iter().enumerate() {
+                self.buffer().chars[row][col].write(character);
             }
         }
+        self.update_cursor();
+    }
+
+    /// Shown on the screen, see `switch_terminal`.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Write position, `(row, column)`.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.text.row, self.text.column)
+    }
+
+    /// Lines that can be scrolled back.
+    pub fn scrollback(&self) -> usize {
+        self.stored - BUFFER_HEIGHT
     }
 
-    fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row-1][col].write(character);
+    /// Show older lines, or newer ones for negative `lines`.
+    pub fn scroll(&mut self, lines: isize) {
+        let view = (self.view as isize + lines).max(0).min(self.scrollback() as isize) as usize;
+        if view != self.view {
+            self.view = view;
+            self.redraw();
+        }
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll(BUFFER_HEIGHT as isize - 1);
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll(-(BUFFER_HEIGHT as isize - 1));
+    }
+
+    /// Move the blinking CRTC cursor to the write position, hiding it while
+    /// scrolled back.
+    fn update_cursor(&mut self) {
+        if !self.active {
+            return;
+        }
+        if !self.text.cursor_visible || self.view != 0 {
+            crtc_write(CRTC_CURSOR_START, CURSOR_DISABLED);
+            return;
+        }
+        crtc_write(CRTC_CURSOR_START, CURSOR_SCANLINES.0);
+        crtc_write(CRTC_CURSOR_END, CURSOR_SCANLINES.1);
+        let position = self.text.row * BUFFER_WIDTH + self.text.column.min(BUFFER_WIDTH - 1);
+        crtc_write(CRTC_CURSOR_HIGH, (position >> 8) as u8);
+        crtc_write(CRTC_CURSOR_LOW, position as u8);
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        // New output brings the screen back.
+        if self.view != 0 {
+            self.view = 0;
+            self.redraw();
+        }
+        for c in s.chars() {
+            if c.is_ascii() {
+                self.write_byte(c as u8);
+            } else {
+                self.write_byte(REPLACEMENT_CHARACTER);
             }
         }
+        self.update_cursor();
+    }
+}
 
-        self.clear_row(BUFFER_HEIGHT - 1);
-        self.column_position = 0;
+impl TextScreen for Writer {
+    fn size(&self) -> (usize, usize) {
+        (BUFFER_WIDTH, BUFFER_HEIGHT)
     }
 
-    fn clear_row(&mut self, row: usize) {
+    fn put(&mut self, text: &TextState, row: usize, column: usize, byte: u8) {
+        let character = ScreenChar {
+            ascii_character: byte,
+            color_code: color_code(text),
+        };
+        self.put_char(row, column, character);
+    }
+
+    fn clear(&mut self, text: &TextState, row: usize, columns: core::ops::Range<usize>) {
         let blank = ScreenChar {
             ascii_character: b' ',
-            color_code: self.color_code,
+            color_code: color_code(text),
         };
-
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for column in columns {
+            self.put_char(row, column, blank);
         }
     }
 
-    pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
-            }
+    fn scroll_up(&mut self, text: &TextState) {
+        self.bottom = (self.bottom + 1) % SCROLLBACK_LINES;
+        self.stored = (self.stored + 1).min(SCROLLBACK_LINES);
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: color_code(text),
+        };
+        self.lines[self.bottom] = [blank; BUFFER_WIDTH];
+        if self.view == 0 {
+            self.redraw();
         }
     }
 }
 
+fn crtc_write(register: u8, value: u8) {
+    write_port_u8(CRTC_INDEX, register);
+    write_port_u8(CRTC_DATA, value);
+}
+
+fn crtc_read(register: u8) -> u8 {
+    write_port_u8(CRTC_INDEX, register);
+    read_port_u8(CRTC_DATA)
+}
+
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.write_string(s);
@@ -119,33 +315,78 @@ impl fmt::Write for Writer {
     }
 }
 
-lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+pub const TERMINAL_COUNT: usize = 6;
+
+/// Virtual terminals sharing the screen, the first one shown at boot.
+pub static TERMINALS: [Mutex<Writer>; TERMINAL_COUNT] = [
+    Mutex::new(Writer::new(true)),
+    Mutex::new(Writer::new(false)),
+    Mutex::new(Writer::new(false)),
+    Mutex::new(Writer::new(false)),
+    Mutex::new(Writer::new(false)),
+    Mutex::new(Writer::new(false)),
+];
+
+/// The kernel console `print!` writes to.
+pub static WRITER: &Mutex<Writer> = &TERMINALS[0];
+
+static ACTIVE_TERMINAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Index of the terminal on the screen.
+pub fn active_terminal() -> usize {
+    ACTIVE_TERMINAL.load(Ordering::SeqCst)
+}
+
+/// Show terminal `index`.
+///
+/// Panics if `index` is not below `TERMINAL_COUNT`.
+pub fn switch_terminal(index: usize) {
+    assert!(index < TERMINAL_COUNT, "no terminal {}", index);
+    crate::arch::no_interrupts(|| {
+        let previous = ACTIVE_TERMINAL.swap(index, Ordering::SeqCst);
+        if previous == index {
+            return;
+        }
+        TERMINALS[previous].lock().active = false;
+        let mut terminal = TERMINALS[index].lock();
+        terminal.active = true;
+        terminal.redraw();
+    });
+}
+
+/// Position of the blinking cursor on the screen, `(row, column)`.
+pub fn screen_cursor() -> (usize, usize) {
+    let position = crate::arch::no_interrupts(|| {
+        usize::from(crtc_read(CRTC_CURSOR_HIGH)) << 8 | usize::from(crtc_read(CRTC_CURSOR_LOW))
     });
+    (position / BUFFER_WIDTH, position % BUFFER_WIDTH)
+}
+
+/// Character shown at `row`, `col` of the screen.
+pub fn screen_byte(row: usize, col: usize) -> u8 {
+    crate::arch::no_interrupts(|| {
+        TERMINALS[active_terminal()].lock().buffer().chars[row][col].read().ascii_character
+    })
 }
 
-#[macro_export]
-macro_rules! print {
-    ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
+/// Scroll the terminal on the screen back a page.
+pub fn page_up() {
+    crate::arch::no_interrupts(|| TERMINALS[active_terminal()].lock().page_up());
 }
 
-#[macro_export]
-macro_rules! println {
-    () => ($crate::print!("\n"));
-    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+/// Scroll the terminal on the screen forward a page.
+pub fn page_down() {
+    crate::arch::no_interrupts(|| TERMINALS[active_terminal()].lock().page_down());
 }
 
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
+/// Write to terminal `index`, whether it is shown or not.
+pub fn print_to(index: usize, args: fmt::Arguments) {
     use core::fmt::Write;
 
     use crate::arch::no_interrupts;
 
     no_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
+        TERMINALS[index].lock().write_fmt(args).unwrap();
     });
 }
 
@@ -154,6 +395,10 @@ mod test {
     use super::*;
     use crate::{serial_println, serial_print};
 
+    fn screen_char(row: usize, col: usize) -> ScreenChar {
+        WRITER.lock().buffer().chars[row][col].read()
+    }
+
     #[test_case]
     fn test_println_simple() {
         serial_print!("testing println...");
@@ -177,11 +422,65 @@ mod test {
         let s = "Some test string that fits on a single line";
         println!("{}", s);
         for (i, c) in s.chars().enumerate() {
-            let screen_char = WRITER.lock().buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            let screen_char = screen_char(BUFFER_HEIGHT - 2, i);
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
 
         serial_println!("[ok]");
     }
-}
 
+    #[test_case]
+    fn test_ansi_colors_and_cursor() {
+        serial_print!("test_ansi_colors_and_cursor...");
+
+        println!("\x1b[31;1mred\x1b[0m plain\rP\tX\x1b[2Dy");
+        let row = BUFFER_HEIGHT - 2;
+        assert_eq!(screen_char(row, 0).ascii_character, b'P');
+        assert_eq!(screen_char(row, 1).color_code, ColorCode::new(Color::LightRed, Color::Black));
+        assert_eq!(screen_char(row, 4).color_code, ColorCode::new(Color::Yellow, Color::Black));
+        assert_eq!(screen_char(row, 7).ascii_character, b'y');
+        assert_eq!(screen_char(row, 8).ascii_character, b'X');
+
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_scrollback() {
+        serial_print!("test_scrollback...");
+
+        println!("scrolled away");
+        for _ in 0..BUFFER_HEIGHT {
+            println!();
+        }
+        crate::arch::no_interrupts(|| {
+            let mut writer = WRITER.lock();
+            writer.page_up();
+            writer.scroll(-(BUFFER_HEIGHT as isize - 3));
+        });
+        assert_eq!(screen_char(0, 0).ascii_character, b's');
+        page_down();
+        page_down();
+        assert_eq!(WRITER.lock().view, 0);
+        assert_eq!(screen_char(0, 0).ascii_character, b' ');
+
+        serial_println!("[ok]");
+    }
+
+    #[test_case]
+    fn test_virtual_terminals() {
+        serial_print!("test_virtual_terminals...");
+
+        let row = BUFFER_HEIGHT - 1;
+        print_to(1, format_args!("second"));
+        assert_eq!(screen_char(row, 0).ascii_character, b' ');
+        switch_terminal(1);
+        assert_eq!(active_terminal(), 1);
+        assert_eq!(screen_char(row, 0).ascii_character, b's');
+        println!("kernel");
+        assert_eq!(screen_char(row, 0).ascii_character, b's');
+        switch_terminal(0);
+        assert_eq!(screen_char(BUFFER_HEIGHT - 2, 0).ascii_character, b'k');
+
+        serial_println!("[ok]");
+    }
+}
@@ -0,0 +1,367 @@
+//! ANSI escape sequences understood by the consoles: a parser, and the
+//! cursor and color state its actions update.
+
+use core::ops::Range;
+
+const ESC: u8 = 0x1b;
+
+/// Parameters beyond this are dropped.
+pub const MAX_PARAMS: usize = 16;
+
+/// Control sequence, `ESC [ params final`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Csi {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    /// Starts with `?`, like the DEC private modes.
+    pub private: bool,
+    pub final_byte: u8,
+}
+
+impl Csi {
+    const fn new() -> Csi {
+        Csi {
+            params: [0; MAX_PARAMS],
+            len: 0,
+            private: false,
+            final_byte: 0,
+        }
+    }
+
+    /// Parameters as given, omitted ones are 0.
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /// Parameter `index`, or `default` if it was omitted or 0.
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Printable character.
+    Print(u8),
+    /// Control character, like `\n` or backspace.
+    Execute(u8),
+    Csi(Csi),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+pub struct Parser {
+    state: State,
+    csi: Csi,
+}
+
+impl Parser {
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            csi: Csi::new(),
+        }
+    }
+
+    /// Feed one byte, returning what to do once a character or sequence is
+    /// complete. Unsupported sequences are swallowed.
+    pub fn advance(&mut self, byte: u8) -> Option<Action> {
+        match self.state {
+            State::Ground => match byte {
+                ESC => {
+                    self.state = State::Escape;
+                    None
+                }
+                0x00..=0x1f | 0x7f => Some(Action::Execute(byte)),
+                _ => Some(Action::Print(byte)),
+            },
+            State::Escape => {
+                if byte == b'[' {
+                    self.csi = Csi::new();
+                    self.state = State::Csi;
+                } else {
+                    self.state = State::Ground;
+                }
+                None
+            }
+            State::Csi => self.advance_csi(byte),
+        }
+    }
+
+    fn advance_csi(&mut self, byte: u8) -> Option<Action> {
+        let csi = &mut self.csi;
+        match byte {
+            b'0'..=b'9' => {
+                if csi.len == 0 {
+                    csi.len = 1;
+                }
+                if let Some(param) = csi.params.get_mut(csi.len - 1) {
+                    *param = param.saturating_mul(10).saturating_add(u16::from(byte - b'0'));
+                }
+                None
+            }
+            b';' => {
+                // An empty first parameter still counts.
+                if csi.len == 0 {
+                    csi.len = 1;
+                }
+                if csi.len < MAX_PARAMS {
+                    csi.len += 1;
+                }
+                None
+            }
+            b'?' if csi.len == 0 => {
+                csi.private = true;
+                None
+            }
+            0x40..=0x7e => {
+                csi.final_byte = byte;
+                self.state = State::Ground;
+                Some(Action::Csi(*csi))
+            }
+            // Intermediate bytes of sequences we do not support.
+            0x20..=0x3f => None,
+            ESC => {
+                self.state = State::Escape;
+                None
+            }
+            _ => {
+                self.state = State::Ground;
+                Some(Action::Execute(byte))
+            }
+        }
+    }
+}
+
+/// Added to a color to get its bright variant.
+pub const BRIGHT: usize = 8;
+
+const TAB_WIDTH: usize = 8;
+
+/// Cells a `TextState` draws on.
+pub trait TextScreen {
+    /// Size in characters, `(columns, rows)`, neither of them 0.
+    fn size(&self) -> (usize, usize);
+    /// Draw `byte` at `row`, `column` with the colors of `state`.
+    fn put(&mut self, state: &TextState, row: usize, column: usize, byte: u8);
+    /// Clear `columns` of `row` to the background of `state`.
+    fn clear(&mut self, state: &TextState, row: usize, columns: Range<usize>);
+    /// Move all lines up by one, clearing the last one to the background of
+    /// `state`.
+    fn scroll_up(&mut self, state: &TextState);
+}
+
+/// Cursor and colors of a console, updated by the actions of a `Parser`.
+///
+/// Colors are indices in ANSI order, the bright ones last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextState {
+    pub row: usize,
+    pub column: usize,
+    pub foreground: usize,
+    pub background: usize,
+    pub bold: bool,
+    pub reverse: bool,
+    pub saved_position: (usize, usize),
+    pub cursor_visible: bool,
+    default_foreground: usize,
+    default_background: usize,
+}
+
+impl TextState {
+    /// Start at `row` with the default colors.
+    pub const fn new(row: usize, default_foreground: usize, default_background: usize) -> TextState {
+        TextState {
+            row,
+            column: 0,
+            foreground: default_foreground,
+            background: default_background,
+            bold: false,
+            reverse: false,
+            saved_position: (0, 0),
+            cursor_visible: true,
+            default_foreground,
+            default_background,
+        }
+    }
+
+    /// `(foreground, background)` to draw with, bold and reverse applied.
+    pub fn colors(&self) -> (usize, usize) {
+        let foreground = if self.bold { self.foreground | BRIGHT } else { self.foreground };
+        if self.reverse {
+            (self.background, foreground)
+        } else {
+            (foreground, self.background)
+        }
+    }
+
+    /// Set the colors, like SGR sequences do.
+    pub fn set_colors(&mut self, foreground: usize, background: usize) {
+        self.foreground = foreground;
+        self.background = background;
+        self.bold = false;
+    }
+
+    pub fn perform(&mut self, screen: &mut impl TextScreen, action: Action) {
+        let (columns, _) = screen.size();
+        match action {
+            Action::Print(byte) => self.print(screen, byte),
+            Action::Execute(b'\n') => self.new_line(screen),
+            Action::Execute(b'\r') => self.column = 0,
+            Action::Execute(0x08) => self.column = self.column.min(columns - 1).saturating_sub(1),
+            Action::Execute(b'\t') => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.column = next.min(columns - 1);
+            }
+            Action::Execute(_) => {}
+            Action::Csi(csi) => self.control_sequence(screen, &csi),
+        }
+    }
+
+    fn print(&mut self, screen: &mut impl TextScreen, byte: u8) {
+        let (columns, _) = screen.size();
+        if self.column >= columns {
+            self.new_line(screen);
+        }
+        screen.put(self, self.row, self.column, byte);
+        self.column += 1;
+    }
+
+    fn new_line(&mut self, screen: &mut impl TextScreen) {
+        let (_, rows) = screen.size();
+        if self.row < rows - 1 {
+            self.row += 1;
+        } else {
+            screen.scroll_up(self);
+        }
+        self.column = 0;
+    }
+
+    fn control_sequence(&mut self, screen: &mut impl TextScreen, csi: &Csi) {
+        let (columns, rows) = screen.size();
+        let count = usize::from(csi.param(0, 1));
+        match (csi.private, csi.final_byte) {
+            (false, b'm') => self.select_graphic_rendition(csi),
+            (false, b'A') => self.row = self.row.saturating_sub(count),
+            (false, b'B') => self.row = (self.row + count).min(rows - 1),
+            (false, b'C') => self.column = (self.column + count).min(columns - 1),
+            (false, b'D') => self.column = self.column.min(columns - 1).saturating_sub(count),
+            (false, b'G') => self.column = (count - 1).min(columns - 1),
+            (false, b'H') | (false, b'f') => {
+                self.row = (count - 1).min(rows - 1);
+                self.column = (usize::from(csi.param(1, 1)) - 1).min(columns - 1);
+            }
+            (false, b'J') => {
+                let (row, column) = (self.row, self.column.min(columns));
+                let (before, after) = match csi.param(0, 0) {
+                    0 => (false, true),
+                    1 => (true, false),
+                    _ => (true, true),
+                };
+                if before {
+                    for above in 0..row {
+                        screen.clear(self, above, 0..columns);
+                    }
+                    screen.clear(self, row, 0..(column + 1).min(columns));
+                }
+                if after {
+                    screen.clear(self, row, column..columns);
+                    for below in row + 1..rows {
+                        screen.clear(self, below, 0..columns);
+                    }
+                }
+            }
+            (false, b'K') => {
+                let (row, column) = (self.row, self.column.min(columns));
+                match csi.param(0, 0) {
+                    0 => screen.clear(self, row, column..columns),
+                    1 => screen.clear(self, row, 0..(column + 1).min(columns)),
+                    _ => screen.clear(self, row, 0..columns),
+                }
+            }
+            (false, b's') => self.saved_position = (self.row, self.column),
+            (false, b'u') => {
+                let (row, column) = self.saved_position;
+                self.row = row.min(rows - 1);
+                self.column = column.min(columns);
+            }
+            (true, b'h') if csi.param(0, 0) == 25 => self.cursor_visible = true,
+            (true, b'l') if csi.param(0, 0) == 25 => self.cursor_visible = false,
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, csi: &Csi) {
+        // `ESC [ m` resets too.
+        let params = if csi.params().is_empty() { &[0][..] } else { csi.params() };
+        for &param in params {
+            match param {
+                0 => {
+                    self.foreground = self.default_foreground;
+                    self.background = self.default_background;
+                    self.bold = false;
+                    self.reverse = false;
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                30..=37 => self.foreground = usize::from(param - 30),
+                39 => self.foreground = self.default_foreground,
+                40..=47 => self.background = usize::from(param - 40),
+                49 => self.background = self.default_background,
+                90..=97 => self.foreground = usize::from(param - 90) | BRIGHT,
+                100..=107 => self.background = usize::from(param - 100) | BRIGHT,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serial_print, serial_println};
+
+    /// Remembers the last cell drawn.
+    struct LastPut(Option<(usize, usize, u8, (usize, usize))>);
+
+    impl TextScreen for LastPut {
+        fn size(&self) -> (usize, usize) {
+            (10, 4)
+        }
+
+        fn put(&mut self, state: &TextState, row: usize, column: usize, byte: u8) {
+            self.0 = Some((row, column, byte, state.colors()));
+        }
+
+        fn clear(&mut self, _state: &TextState, _row: usize, _columns: Range<usize>) {}
+
+        fn scroll_up(&mut self, _state: &TextState) {}
+    }
+
+    #[test_case]
+    fn test_text_state() {
+        serial_print!("test_text_state...");
+        let mut parser = Parser::new();
+        let mut state = TextState::new(0, 7, 0);
+        let mut screen = LastPut(None);
+        for &byte in b"\x1b[2;3H\x1b[s\x1b[1;31;44mA\x1b[u\x1b[7mB" {
+            if let Some(action) = parser.advance(byte) {
+                state.perform(&mut screen, action);
+            }
+        }
+        // Restored to the saved position, bold red on blue reversed.
+        assert_eq!(screen.0, Some((1, 2, b'B', (4, 1 | BRIGHT))));
+        assert_eq!((state.row, state.column), (1, 3));
+        serial_println!("[ok]");
+    }
+}
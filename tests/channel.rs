@@ -0,0 +1,141 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+extern crate alloc;
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+use futures_util::{sink::SinkExt, stream::StreamExt};
+
+use rustos::cooperative::channel::{broadcast, mpsc, oneshot};
+use rustos::cooperative::executor::Executor;
+use rustos::{serial_print, serial_println};
+use rustos::test::{exit_qemu, QemuExitCode};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+#[test_case]
+pub fn bounded_applies_backpressure() {
+    serial_print!("testing bounded mpsc...");
+    let mut executor = Executor::new();
+    let (sender, receiver) = mpsc::channel(2);
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    executor.spawn(async move {
+        for i in 0..10 {
+            sender.send(i).await.unwrap();
+        }
+    }).unwrap();
+    let output = received.clone();
+    executor.spawn(async move {
+        let values: Vec<u32> = receiver.collect().await;
+        *output.borrow_mut() = values;
+    }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(*received.borrow(), (0..10).collect::<Vec<_>>());
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn sink_and_unbounded() {
+    serial_print!("testing mpsc Sink...");
+    let mut executor = Executor::new();
+    let (mut bounded, mut bounded_rx) = mpsc::channel(1);
+    let (unbounded, mut unbounded_rx) = mpsc::unbounded();
+
+    executor.spawn(async move {
+        bounded.send_all(&mut futures_util::stream::iter(0..5u8).map(Ok)).await.unwrap();
+        unbounded.send(7u8).unwrap();
+    }).unwrap();
+    executor.spawn(async move {
+        let mut sum = 0;
+        while let Some(value) = bounded_rx.next().await {
+            sum += value;
+        }
+        assert_eq!(sum, 10);
+        assert_eq!(unbounded_rx.recv().await, Some(7));
+        assert_eq!(unbounded_rx.recv().await, None);
+    }).unwrap();
+    executor.run_until_stalled();
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn irq_sender_does_not_wait() {
+    serial_print!("testing IrqSender...");
+    let (sender, mut receiver) = mpsc::channel(2);
+    let irq_sender = sender.irq_sender();
+
+    rustos::arch::no_interrupts(|| {
+        assert_eq!(irq_sender.try_send(1), Ok(()));
+        assert_eq!(irq_sender.try_send(2), Ok(()));
+        assert_eq!(irq_sender.try_send(3), Err(mpsc::TrySendError::Full(3)));
+    });
+    assert_eq!(receiver.try_recv(), Ok(1));
+    assert_eq!(receiver.try_recv(), Ok(2));
+    assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+
+    drop(receiver);
+    assert_eq!(irq_sender.try_send(4), Err(mpsc::TrySendError::Closed(4)));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn oneshot_delivers_or_cancels() {
+    serial_print!("testing oneshot...");
+    let mut executor = Executor::new();
+    let (sender, receiver) = oneshot::channel();
+    let (dropped, cancelled) = oneshot::channel::<u32>();
+
+    let output = Rc::new(RefCell::new(None));
+    let result = output.clone();
+    executor.spawn(async move {
+        *result.borrow_mut() = Some((receiver.await, cancelled.await));
+    }).unwrap();
+    executor.run_until_stalled();
+    assert!(output.borrow().is_none());
+
+    sender.send(42).unwrap();
+    drop(dropped);
+    executor.run_until_stalled();
+    assert_eq!(*output.borrow(), Some((Ok(42), Err(oneshot::RecvError))));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn broadcast_reports_lag() {
+    serial_print!("testing broadcast...");
+    let (sender, mut first) = broadcast::channel(2);
+    let mut second = sender.subscribe();
+
+    assert_eq!(sender.send(1), Ok(2));
+    assert_eq!(first.try_recv(), Ok(1));
+    sender.send(2).unwrap();
+    sender.send(3).unwrap();
+
+    assert_eq!(first.try_recv(), Ok(2));
+    assert_eq!(second.try_recv(), Err(broadcast::TryRecvError::Lagged(1)));
+    assert_eq!(second.try_recv(), Ok(2));
+    assert_eq!(second.try_recv(), Ok(3));
+
+    drop(sender);
+    assert_eq!(first.try_recv(), Ok(3));
+    assert_eq!(first.try_recv(), Err(broadcast::TryRecvError::Closed));
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
@@ -0,0 +1,136 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::arch::interrupts::SERIAL_IRQ_1;
+use rustos::arch::irq;
+use rustos::console::{self, ConsoleError, Sink, MAX_SINKS, RING};
+use rustos::serial::{self, ComPort};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{print, println, serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+/// Counts the bytes written to it.
+struct CountingSink(AtomicUsize);
+
+impl Sink for CountingSink {
+    fn write_str(&self, s: &str) {
+        self.0.fetch_add(s.len(), Ordering::SeqCst);
+    }
+}
+
+static COUNTER: CountingSink = CountingSink(AtomicUsize::new(0));
+
+/// vga, serial, framebuffer and ring.
+const DEFAULT_SINKS: usize = 4;
+
+/// Names to fill the free sink slots with.
+const EXTRA_NAMES: [&str; 16] = [
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p",
+];
+
+fn ring_ends_with(expected: &str) -> bool {
+    let mut buffer = [0; 64];
+    let len = RING.copy_to(&mut buffer);
+    buffer[..len].ends_with(expected.as_bytes())
+}
+
+#[test_case]
+pub fn default_sinks() {
+    serial_print!("testing default console sinks...");
+    for &name in ["vga", "serial", "framebuffer", "ring"].iter() {
+        assert_eq!(console::is_enabled(name), Some(true));
+    }
+    assert_eq!(console::is_enabled("printer"), None);
+    println!("to the ring");
+    assert!(ring_ends_with("to the ring\n"));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn toggle_sinks() {
+    serial_print!("testing console sink toggling...");
+    assert_eq!(console::set_enabled("ring", false), Ok(()));
+    print!("not kept");
+    assert_eq!(console::is_enabled("ring"), Some(false));
+    assert!(!ring_ends_with("not kept"));
+    assert_eq!(console::set_enabled("ring", true), Ok(()));
+    print!("kept");
+    assert!(ring_ends_with("kept"));
+    assert_eq!(console::set_enabled("printer", true), Err(ConsoleError::NoSuchSink));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn register_sinks() {
+    serial_print!("testing console sink registration...");
+    assert_eq!(console::register("counter", &COUNTER, true), Ok(()));
+    assert_eq!(
+        console::register("counter", &COUNTER, true),
+        Err(ConsoleError::AlreadyRegistered)
+    );
+    print!("12345");
+    assert_eq!(COUNTER.0.load(Ordering::SeqCst), 5);
+
+    // Fill the slots left after the default sinks and the counter.
+    let extra = &EXTRA_NAMES[..MAX_SINKS - DEFAULT_SINKS - 1];
+    for &name in extra {
+        assert_eq!(console::register(name, &COUNTER, false), Ok(()));
+    }
+    assert_eq!(
+        console::register(EXTRA_NAMES[extra.len()], &COUNTER, false),
+        Err(ConsoleError::TooManySinks)
+    );
+    print!("6");
+    assert_eq!(COUNTER.0.load(Ordering::SeqCst), 6);
+
+    assert_eq!(console::unregister("counter"), Ok(()));
+    for &name in extra {
+        assert_eq!(console::unregister(name), Ok(()));
+    }
+    assert_eq!(console::unregister("counter"), Err(ConsoleError::NoSuchSink));
+    print!("7");
+    assert_eq!(COUNTER.0.load(Ordering::SeqCst), 6);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn print_from_interrupt_context() {
+    serial_print!("testing console output with interrupts disabled...");
+    rustos::arch::no_interrupts(|| println!("quiet"));
+    assert!(ring_ends_with("quiet\n"));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn print_leaves_serial_output_queued() {
+    serial_print!("testing buffered console output...");
+    serial::flush(ComPort::Com1);
+    // Hold off the transmit interrupt so the ring cannot be drained before
+    // it is looked at.
+    irq::mask_irq(SERIAL_IRQ_1);
+    assert!(rustos::arch::interrupts_enabled());
+    println!("{:.<1$}", "", 256);
+    let queued = serial::queued(ComPort::Com1);
+    irq::unmask_irq(SERIAL_IRQ_1);
+    serial::flush(ComPort::Com1);
+    assert!(queued > 0);
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
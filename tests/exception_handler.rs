@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use rustos::interrupts::handlers::{self, Exception, RustosExcStackframe};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rustos::arch::initialize();
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+static BREAKPOINTS: AtomicUsize = AtomicUsize::new(0);
+static BREAKPOINT_IP: AtomicUsize = AtomicUsize::new(0);
+
+fn count_breakpoints(exception: Exception, frame: &mut RustosExcStackframe) {
+    assert_eq!(exception, Exception::Breakpoint);
+    assert_eq!(frame.errcode, None);
+    assert!(frame.registers.get("rsp").is_none());
+    assert!(frame.registers.get("rax").is_some());
+    BREAKPOINT_IP.store(frame.instruction_pointer, Ordering::SeqCst);
+    BREAKPOINTS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+pub fn custom_breakpoint_handler() {
+    serial_print!("testing custom breakpoint handler...");
+    handlers::set_exception_handler(Exception::Breakpoint, count_breakpoints);
+
+    x86_64::instructions::interrupts::int3();
+    x86_64::instructions::interrupts::int3();
+
+    handlers::reset_exception_handler(Exception::Breakpoint);
+    assert_eq!(BREAKPOINTS.load(Ordering::SeqCst), 2);
+    assert_ne!(BREAKPOINT_IP.load(Ordering::SeqCst), 0);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn default_breakpoint_handler_returns() {
+    serial_print!("testing default breakpoint handler...");
+    x86_64::instructions::interrupts::int3();
+    assert_eq!(BREAKPOINTS.load(Ordering::SeqCst), 2);
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
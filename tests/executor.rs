@@ -0,0 +1,344 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+extern crate alloc;
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::RefCell;
+use core::cell::Cell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::cooperative::executor::{self, Executor, ExecutorError, TaskState};
+use rustos::cooperative::join_handle::JoinError;
+use rustos::cooperative::task::{Priority, Task, TaskOptions};
+use rustos::{serial_print, serial_println};
+use rustos::test::{exit_qemu, QemuExitCode};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+async fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[test_case]
+pub fn join_returns_output() {
+    serial_print!("testing JoinHandle output...");
+    let mut executor = Executor::new();
+    let result = Rc::new(Cell::new(None));
+
+    let handle = executor.spawn(add(40, 2)).unwrap();
+    let joined = result.clone();
+    executor.spawn(async move {
+        joined.set(Some(handle.await));
+    }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(result.get(), Some(Ok(42)));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn dropped_handle_detaches() {
+    serial_print!("testing detached task...");
+    let mut executor = Executor::new();
+    let ran = Rc::new(Cell::new(false));
+
+    let flag = ran.clone();
+    let handle = executor.spawn(async move { flag.set(true) }).unwrap();
+    drop(handle);
+    executor.run_until_stalled();
+
+    assert!(ran.get());
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn dropped_task_is_cancelled() {
+    serial_print!("testing cancellation on drop...");
+    let mut executor = Executor::new();
+    let handle = executor.spawn(futures_util::future::pending::<()>()).unwrap();
+    executor.run_until_stalled();
+    assert!(!handle.is_finished());
+
+    drop(executor);
+    assert!(handle.is_finished());
+
+    let mut executor = Executor::new();
+    let result = Rc::new(Cell::new(None));
+    let joined = result.clone();
+    executor.spawn(async move {
+        joined.set(Some(handle.await));
+    }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(result.get(), Some(Err(JoinError::Cancelled)));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn abort_through_handle() {
+    serial_print!("testing abort through JoinHandle...");
+    let mut executor = Executor::new();
+    let handle = executor.spawn(futures_util::future::pending::<()>()).unwrap();
+    executor.run_until_stalled();
+
+    handle.abort();
+    executor.run_until_stalled();
+    assert!(handle.is_finished());
+
+    let result = Rc::new(Cell::new(None));
+    let joined = result.clone();
+    executor.spawn(async move {
+        joined.set(Some(handle.await));
+    }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(result.get(), Some(Err(JoinError::Cancelled)));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn abort_by_id() {
+    serial_print!("testing abort by TaskId...");
+    let mut executor = Executor::new();
+    let ran = Rc::new(Cell::new(false));
+
+    let flag = ran.clone();
+    let handle = executor.spawn(async move { flag.set(true) }).unwrap();
+    assert!(executor.abort(handle.task_id()));
+    executor.run_until_stalled();
+
+    assert!(!ran.get());
+    assert!(handle.is_finished());
+    assert!(!executor.abort(handle.task_id()));
+    serial_println!("[ok]");
+}
+
+/// Wakes itself `wakes` times on the first poll, then completes.
+struct WakeBurst {
+    wakes: usize,
+    polls: Rc<Cell<usize>>,
+}
+
+impl Future for WakeBurst {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.polls.set(self.polls.get() + 1);
+        if self.polls.get() > 1 {
+            return Poll::Ready(());
+        }
+        for _ in 0..self.wakes {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+#[test_case]
+pub fn duplicate_wakes_are_merged() {
+    serial_print!("testing wake deduplication...");
+    let mut executor = Executor::new();
+    let polls = Rc::new(Cell::new(0));
+    executor.spawn(WakeBurst { wakes: 1000, polls: polls.clone() }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(polls.get(), 2);
+    serial_println!("[ok]");
+}
+
+/// Returns `Pending` once, waking itself.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn options(priority: Priority) -> TaskOptions {
+    TaskOptions { priority, ..TaskOptions::default() }
+}
+
+#[test_case]
+pub fn higher_priority_runs_first() {
+    serial_print!("testing priority order...");
+    let mut executor = Executor::new();
+    let order = Rc::new(RefCell::new(Vec::new()));
+
+    for &priority in &[Priority::Background, Priority::Normal, Priority::BottomHalf] {
+        let order = order.clone();
+        executor.spawn_with(async move { order.borrow_mut().push(priority) }, options(priority))
+            .unwrap();
+    }
+    executor.run_until_stalled();
+
+    assert_eq!(
+        *order.borrow(),
+        [Priority::BottomHalf, Priority::Normal, Priority::Background]
+    );
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn busy_task_does_not_starve_background() {
+    serial_print!("testing poll budget...");
+    let mut executor = Executor::new();
+    executor.set_poll_budget(4);
+    let iterations = Rc::new(Cell::new(0));
+    let seen_by_background = Rc::new(Cell::new(None));
+
+    let counter = iterations.clone();
+    executor.spawn(async move {
+        for _ in 0..100 {
+            counter.set(counter.get() + 1);
+            YieldNow(false).await;
+        }
+    }).unwrap();
+    let counter = iterations.clone();
+    let seen = seen_by_background.clone();
+    executor.spawn_with(async move { seen.set(Some(counter.get())) }, options(Priority::Background))
+        .unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(iterations.get(), 100);
+    assert!(seen_by_background.get().unwrap() < 100);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn spawn_from_task() {
+    serial_print!("testing spawn from a task...");
+    let mut executor = Executor::new();
+    let result = Rc::new(Cell::new(None));
+
+    let joined = result.clone();
+    executor.spawn(async move {
+        let handle = executor::spawn(add(1, 2)).unwrap();
+        joined.set(Some(handle.await));
+    }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(result.get(), Some(Ok(3)));
+    assert!(executor::current_spawner().is_none());
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn spawn_after_shutdown() {
+    serial_print!("testing spawn after shutdown...");
+    let mut executor = Executor::new();
+    let spawner = executor.get_spawner();
+
+    executor.spawn(async move { spawner.shutdown() }).unwrap();
+    executor.run();
+
+    assert_eq!(executor.spawn(add(1, 2)).err(), Some(ExecutorError::ShutDown));
+    let spawner = executor.get_spawner();
+    drop(executor);
+    assert_eq!(spawner.spawn(add(1, 2)).err(), Some(ExecutorError::ShutDown));
+    serial_println!("[ok]");
+}
+
+static SPAWNED_FROM_INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+fn interrupt_task() -> Task {
+    Task::new(async {
+        SPAWNED_FROM_INTERRUPT.store(true, Ordering::SeqCst);
+    })
+}
+
+#[test_case]
+pub fn spawn_from_interrupt() {
+    serial_print!("testing spawn from interrupt...");
+    let mut executor = Executor::new();
+
+    rustos::arch::no_interrupts(|| executor::spawn_from_interrupt(interrupt_task)).unwrap();
+    executor.run_until_stalled();
+
+    assert!(SPAWNED_FROM_INTERRUPT.load(Ordering::SeqCst));
+    serial_println!("[ok]");
+}
+
+static SPAWNED_FOR_OWNER: AtomicBool = AtomicBool::new(false);
+
+fn owner_task() -> Task {
+    Task::new(async {
+        SPAWNED_FOR_OWNER.store(true, Ordering::SeqCst);
+    })
+}
+
+#[test_case]
+pub fn interrupt_spawns_run_on_one_executor() {
+    serial_print!("testing interrupt spawns with two executors...");
+    let mut owner = Executor::new();
+    let mut other = Executor::new();
+
+    rustos::arch::no_interrupts(|| executor::spawn_from_interrupt(owner_task)).unwrap();
+    other.run_until_stalled();
+    assert!(!SPAWNED_FOR_OWNER.load(Ordering::SeqCst));
+    owner.run_until_stalled();
+    assert!(SPAWNED_FOR_OWNER.load(Ordering::SeqCst));
+
+    drop(owner);
+    drop(other);
+    assert_eq!(
+        executor::spawn_from_interrupt(owner_task),
+        Err(ExecutorError::NoExecutor)
+    );
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn task_statistics() {
+    serial_print!("testing task statistics...");
+    let mut executor = Executor::new();
+    let options = TaskOptions { name: Some("sleeper"), ..TaskOptions::default() };
+
+    executor.spawn_with(async {
+        YieldNow(false).await;
+        YieldNow(false).await;
+        futures_util::future::pending::<()>().await;
+    }, options).unwrap();
+    executor.spawn(add(1, 2)).unwrap();
+    executor.run_until_stalled();
+
+    let tasks = executor.tasks();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].name, Some("sleeper"));
+    assert_eq!(tasks[0].state, TaskState::Pending);
+    assert_eq!(tasks[0].stats.polls, 3);
+    assert!(tasks[0].stats.last_wake.is_some());
+
+    let stats = executor.stats();
+    assert_eq!(stats.finished, 1);
+    assert_eq!(stats.polls, 4);
+    assert_eq!(executor.task_count(), 1);
+    executor.dump();
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
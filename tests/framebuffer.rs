@@ -0,0 +1,157 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::arch::bga;
+use rustos::framebuffer::console::{with_console, PALETTE};
+use rustos::framebuffer::font::{GLYPH_HEIGHT, GLYPH_WIDTH};
+use rustos::framebuffer::{self, with_framebuffer, FramebufferError, Rgb};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+const RED: Rgb = Rgb::new(0xff, 0, 0);
+const BLUE: Rgb = Rgb::new(0, 0, 0xff);
+
+#[test_case]
+pub fn unsupported_modes() {
+    serial_print!("testing unsupported framebuffer modes...");
+    assert!(bga::is_present());
+    assert_eq!(framebuffer::set_mode(0, 480), Err(FramebufferError::UnsupportedMode));
+    assert_eq!(
+        framebuffer::set_mode(bga::MAX_WIDTH + 1, 480),
+        Err(FramebufferError::UnsupportedMode)
+    );
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn pixels() {
+    serial_print!("testing framebuffer pixels...");
+    assert_eq!(framebuffer::set_mode(640, 480), Ok(()));
+    with_framebuffer(|fb| {
+        assert_eq!((fb.width(), fb.height()), (640, 480));
+        assert_eq!(fb.screen_pixel(639, 479), Some(Rgb::BLACK));
+        fb.set_pixel(10, 20, RED);
+        assert_eq!(fb.screen_pixel(10, 20), Some(RED));
+        assert_eq!(fb.screen_pixel(11, 20), Some(Rgb::BLACK));
+        // Clipped.
+        fb.set_pixel(640, 0, RED);
+        assert_eq!(fb.pixel(640, 0), None);
+    })
+    .unwrap();
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn rectangles_and_blits() {
+    serial_print!("testing framebuffer rectangles and blits...");
+    with_framebuffer(|fb| {
+        fb.clear(Rgb::BLACK);
+        fb.fill_rect(100, 100, 10, 5, BLUE);
+        assert_eq!(fb.screen_pixel(100, 100), Some(BLUE));
+        assert_eq!(fb.screen_pixel(109, 104), Some(BLUE));
+        assert_eq!(fb.screen_pixel(110, 104), Some(Rgb::BLACK));
+        assert_eq!(fb.screen_pixel(109, 105), Some(Rgb::BLACK));
+
+        let image = [RED, BLUE, Rgb::WHITE, RED, BLUE, Rgb::WHITE];
+        fb.blit(638, 0, 3, &image);
+        assert_eq!(fb.screen_pixel(638, 0), Some(RED));
+        assert_eq!(fb.screen_pixel(639, 1), Some(BLUE));
+        fb.blit(0, 0, 3, &image);
+        assert_eq!(fb.screen_pixel(2, 0), Some(Rgb::WHITE));
+        assert_eq!(fb.screen_pixel(0, 1), Some(RED));
+    })
+    .unwrap();
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn double_buffering() {
+    serial_print!("testing framebuffer double buffering...");
+    // Small enough for the back buffer to fit on the heap.
+    assert_eq!(framebuffer::set_mode(320, 200), Ok(()));
+    assert_eq!(framebuffer::enable_double_buffering(), Ok(()));
+    with_framebuffer(|fb| {
+        assert!(fb.is_double_buffered());
+        fb.fill_rect(0, 0, 4, 4, RED);
+        assert_eq!(fb.pixel(3, 3), Some(RED));
+        assert_eq!(fb.screen_pixel(3, 3), Some(Rgb::BLACK));
+        fb.present();
+        assert_eq!(fb.screen_pixel(3, 3), Some(RED));
+
+        fb.set_pixel(319, 199, BLUE);
+    })
+    .unwrap();
+    assert_eq!(framebuffer::disable_double_buffering(), Ok(()));
+    with_framebuffer(|fb| {
+        assert!(!fb.is_double_buffered());
+        assert_eq!(fb.screen_pixel(319, 199), Some(BLUE));
+    })
+    .unwrap();
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn text_console() {
+    serial_print!("testing framebuffer text console...");
+    assert_eq!(framebuffer::set_mode(640, 480), Ok(()));
+    with_console(|console| {
+        assert_eq!(console.size(), Some((80, 30)));
+        write!(console, "\x1b[31;44mA \x1b[0m").unwrap();
+    });
+
+    with_framebuffer(|fb| {
+        let cell = |column: usize| {
+            (0..GLYPH_HEIGHT).flat_map(move |y| {
+                (0..GLYPH_WIDTH).map(move |x| (column * GLYPH_WIDTH + x, y))
+            })
+        };
+        let background = PALETTE[4];
+        let foreground = PALETTE[1];
+        let colors = |column| cell(column).map(|(x, y)| fb.screen_pixel(x, y).unwrap());
+        assert!(colors(0).any(|color| color == foreground));
+        assert!(colors(0).any(|color| color == background));
+        assert!(colors(1).all(|color| color == background));
+        assert!(colors(2).all(|color| color == Rgb::BLACK));
+    })
+    .unwrap();
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn text_console_scrolls() {
+    serial_print!("testing framebuffer text console scrolling...");
+    with_console(|console| {
+        write!(console, "\x1b[2J\x1b[H").unwrap();
+        for _ in 0..30 {
+            write!(console, "\n").unwrap();
+        }
+        write!(console, "\x1b[47m \x1b[0m").unwrap();
+    });
+
+    with_framebuffer(|fb| {
+        // The last line, the others scrolled up.
+        assert_eq!(fb.screen_pixel(0, 480 - GLYPH_HEIGHT), Some(PALETTE[7]));
+        assert_eq!(fb.screen_pixel(0, 480 - GLYPH_HEIGHT - 1), Some(Rgb::BLACK));
+    })
+    .unwrap();
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+#![allow(unreachable_code)]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use rustos::test::{QemuExitCode, exit_qemu};
+use rustos::{test_panic, serial_println, serial_print};
+
+/// Reading a non-canonical address raises a general protection fault, whose
+/// default handler must print a report and panic instead of triple faulting.
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rustos::arch::initialize();
+    serial_print!("testing general protection fault...");
+    let value = unsafe { core::ptr::read_volatile(0xdead_beef_0000_0000usize as *const u64) };
+    serial_println!("[failed] read {}", value);
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+test_panic!(QemuExitCode::Success);
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use rustos::arch::interrupt_stats;
+use rustos::arch::interrupts::{TIMER_INTERRUPT_ID, TIMER_IRQ};
+use rustos::arch::irq::{self, IrqError, IrqReturn, FIRST_FREE_VECTOR};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rustos::arch::initialize();
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_ticks(_vector: u8) -> IrqReturn {
+    TICKS.fetch_add(1, Ordering::SeqCst);
+    IrqReturn::Handled
+}
+
+fn never_called(_vector: u8) -> IrqReturn {
+    IrqReturn::NotHandled
+}
+
+#[test_case]
+pub fn shared_timer_line() {
+    serial_print!("testing shared timer line...");
+    let handle = irq::request_irq(TIMER_IRQ, "test timer", count_ticks)
+        .expect("failed to share the timer line");
+    assert!(!irq::is_masked(TIMER_IRQ));
+
+    while TICKS.load(Ordering::SeqCst) < 3 {
+        rustos::arch::halt();
+    }
+
+    irq::free_irq(handle);
+    assert!(!irq::is_masked(TIMER_IRQ));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn claim_free_vector() {
+    serial_print!("testing free vector allocation...");
+    let handle = irq::allocate_vector("test vector", never_called).unwrap();
+    assert!(handle.vector() >= FIRST_FREE_VECTOR);
+    assert_eq!(
+        irq::request_vector(handle.vector(), "test vector", never_called),
+        Err(IrqError::VectorInUse)
+    );
+
+    let vector = handle.vector();
+    irq::free_irq(handle);
+    let handle = irq::request_vector(vector, "test vector", never_called).unwrap();
+    irq::free_irq(handle);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn invalid_requests() {
+    serial_print!("testing invalid IRQ requests...");
+    assert_eq!(
+        irq::request_irq(16, "test", never_called),
+        Err(IrqError::InvalidLine)
+    );
+    assert_eq!(
+        irq::request_vector(FIRST_FREE_VECTOR - 1, "test", never_called),
+        Err(IrqError::InvalidVector)
+    );
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn timer_statistics() {
+    serial_print!("testing interrupt statistics...");
+    let before = interrupt_stats::vector_stats(TIMER_INTERRUPT_ID);
+    for _ in 0..3 {
+        rustos::arch::halt();
+    }
+    let after = interrupt_stats::vector_stats(TIMER_INTERRUPT_ID);
+
+    assert!(after.count > before.count);
+    assert!(after.total_cycles > before.total_cycles);
+    assert!(after.max_cycles >= after.average_cycles());
+    interrupt_stats::print_report();
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+extern crate alloc;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::arch::interrupts::TIMER_IRQ;
+use rustos::arch::irq::{self, IrqReturn};
+use rustos::cooperative::irq_stream::{IrqSource, IrqStreamError, MAX_SUBSCRIBERS};
+use rustos::cooperative::keyboard::ScancodeStream;
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+static EVENTS: IrqSource<u32> = IrqSource::new("test events");
+
+#[test_case]
+pub fn subscribers_see_every_event() {
+    serial_print!("testing IrqSource subscribers...");
+    let mut first = EVENTS.subscribe(4).unwrap();
+    let mut second = EVENTS.subscribe(2).unwrap();
+
+    rustos::arch::no_interrupts(|| {
+        for event in 0..4 {
+            EVENTS.push(event);
+        }
+    });
+
+    assert_eq!(first.try_next(), Some(0));
+    assert_eq!(second.try_next(), Some(0));
+    assert_eq!(second.try_next(), Some(1));
+    assert_eq!(second.try_next(), None);
+    assert_eq!(first.overflows(), 0);
+    assert_eq!(second.overflows(), 2);
+    assert_eq!(EVENTS.overflows(), 2);
+
+    drop(second);
+    assert_eq!(EVENTS.subscriber_count(), 1);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn subscriber_limit() {
+    serial_print!("testing IrqSource subscriber limit...");
+    let mut streams = alloc::vec::Vec::new();
+    while let Ok(stream) = EVENTS.subscribe(1) {
+        streams.push(stream);
+    }
+    assert_eq!(streams.len(), MAX_SUBSCRIBERS);
+    assert_eq!(EVENTS.subscribe(1).err(), Some(IrqStreamError::TooManySubscribers));
+
+    streams.clear();
+    assert!(EVENTS.subscribe(1).is_ok());
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn scancode_stream_recreated() {
+    serial_print!("testing ScancodeStream re-creation...");
+    drop(ScancodeStream::new());
+    let _first = ScancodeStream::new();
+    let _second = ScancodeStream::new();
+    serial_println!("[ok]");
+}
+
+static TICKS: IrqSource<()> = IrqSource::new("test ticks");
+
+fn push_tick(_vector: u8) -> IrqReturn {
+    TICKS.push(());
+    IrqReturn::Handled
+}
+
+#[test_case]
+pub fn events_from_interrupt_handler() {
+    serial_print!("testing IrqSource from a handler...");
+    let mut ticks = TICKS.subscribe(16).unwrap();
+    let handle = irq::request_irq(TIMER_IRQ, "test ticks", push_tick).unwrap();
+
+    while ticks.try_next().is_none() {
+        x86_64::instructions::hlt();
+    }
+
+    irq::free_irq(handle);
+    assert!(TICKS.events() > 0);
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
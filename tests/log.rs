@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::log::{self, Level, LevelFilter, LogError, Record, MAX_MESSAGE_LEN, RECORD_CAPACITY};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{debug, error, info, serial_print, serial_println, trace, warn};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    // The tests expect the default levels, whatever `loglevel` option the
+    // kernel was built with.
+    log::clear_target_levels();
+    log::set_max_level(LevelFilter::Info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+fn last_record() -> Option<Record> {
+    let mut last = None;
+    log::for_each_record(|record| last = Some(*record));
+    last
+}
+
+#[test_case]
+pub fn records() {
+    serial_print!("testing log records...");
+    let before = log::next_sequence();
+    info!("answer {}", 42);
+    assert_eq!(log::next_sequence(), before + 1);
+
+    let record = last_record().unwrap();
+    assert_eq!(record.sequence(), before);
+    assert_eq!(record.level(), Level::Info);
+    assert_eq!(record.target(), module_path!());
+    assert_eq!(record.message(), "answer 42");
+
+    warn!(target: "disk", "slow");
+    let record = last_record().unwrap();
+    assert_eq!((record.level(), record.target()), (Level::Warn, "disk"));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn level_filtering() {
+    serial_print!("testing log level filtering...");
+    log::set_max_level(LevelFilter::Info);
+    assert_eq!(log::max_level(), LevelFilter::Info);
+    let before = log::next_sequence();
+    debug!("hidden");
+    trace!("hidden");
+    assert_eq!(log::next_sequence(), before);
+
+    log::set_max_level(LevelFilter::Error);
+    warn!("hidden");
+    error!("shown");
+    assert_eq!(log::next_sequence(), before + 1);
+
+    assert_eq!(log::parse_filters("warn,disk=trace,net=off"), Ok(()));
+    assert_eq!(log::max_level(), LevelFilter::Warn);
+    assert_eq!(log::level_for("disk::ata"), LevelFilter::Trace);
+    assert_eq!(log::level_for("diskless"), LevelFilter::Warn);
+    trace!(target: "disk::ata", "shown");
+    error!(target: "net", "hidden");
+    assert_eq!(log::next_sequence(), before + 2);
+
+    assert_eq!(log::parse_filters("loud"), Err(LogError::InvalidFilter));
+    log::clear_target_levels();
+    log::set_max_level(LevelFilter::Info);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn ring_keeps_recent_records() {
+    serial_print!("testing log ring...");
+    for i in 0..RECORD_CAPACITY + 10 {
+        info!("record {}", i);
+    }
+    let mut count = 0;
+    let mut first = None;
+    log::for_each_record(|record| {
+        first = first.or(Some(record.sequence()));
+        count += 1;
+    });
+    assert_eq!(count, RECORD_CAPACITY);
+    assert_eq!(first, Some(log::next_sequence() - RECORD_CAPACITY as u64));
+
+    info!("{:1$}", "é", MAX_MESSAGE_LEN + 1);
+    let message = last_record().unwrap().message().len();
+    assert!(message <= MAX_MESSAGE_LEN && message > 0);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn log_from_interrupt_context() {
+    serial_print!("testing logging with interrupts disabled...");
+    rustos::arch::no_interrupts(|| info!("from a handler"));
+    assert_eq!(last_record().unwrap().message(), "from a handler");
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::arch::ps2::{self, mouse, Port, Ps2Error};
+use rustos::cooperative::mouse::{MouseStream, MOUSE_BYTES};
+use rustos::mouse::PacketFormat;
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+#[test_case]
+pub fn mouse_detected() {
+    serial_print!("testing PS/2 mouse detection...");
+    assert!(ps2::is_dual_channel());
+    assert!(ps2::device(Port::Second).unwrap().is_mouse());
+    // QEMU emulates an IntelliMouse Explorer.
+    assert_ne!(mouse::packet_format(), PacketFormat::Standard);
+    assert_eq!(mouse::set_sample_rate(60), Ok(()));
+    assert_eq!(mouse::set_sample_rate(61), Err(Ps2Error::InvalidArgument));
+    assert_eq!(mouse::set_sample_rate(mouse::DEFAULT_SAMPLE_RATE), Ok(()));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn stream_decodes_packets() {
+    serial_print!("testing MouseStream...");
+    let mut stream = MouseStream::new();
+    let packet = [0x0a, 0x03, 0x04, 0x01];
+
+    rustos::arch::no_interrupts(|| {
+        for &byte in &packet[..mouse::packet_format().len()] {
+            MOUSE_BYTES.push(byte);
+        }
+    });
+
+    let event = stream.try_next().unwrap();
+    assert_eq!((event.dx, event.dy, event.wheel), (3, 4, 1));
+    assert!(event.buttons.right && !event.buttons.left);
+    assert_eq!(stream.try_next(), None);
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::arch::ps2::keyboard::{self, Leds, RepeatDelay, MAX_REPEAT_RATE};
+use rustos::arch::ps2::{self, Port, Ps2Error};
+use rustos::keyboard::ScancodeSet;
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+#[test_case]
+pub fn keyboard_detected() {
+    serial_print!("testing PS/2 keyboard detection...");
+    assert!(ps2::is_initialized());
+    assert!(ps2::translation());
+    assert!(ps2::device(Port::First).unwrap().is_keyboard());
+    assert_eq!(keyboard::received_scancode_set(), ScancodeSet::Set1);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn keyboard_commands() {
+    serial_print!("testing PS/2 keyboard commands...");
+    let leds = Leds {
+        caps_lock: true,
+        ..Leds::default()
+    };
+    assert_eq!(keyboard::set_leds(leds), Ok(()));
+    assert_eq!(keyboard::set_leds(Leds::default()), Ok(()));
+    assert_eq!(keyboard::set_typematic(RepeatDelay::Ms500, 0x0b), Ok(()));
+    assert_eq!(
+        keyboard::set_typematic(RepeatDelay::Ms250, MAX_REPEAT_RATE + 1),
+        Err(Ps2Error::InvalidArgument)
+    );
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn scancode_set_selection() {
+    serial_print!("testing PS/2 scancode set selection...");
+    assert_eq!(keyboard::set_scancode_set(ScancodeSet::Set2), Ok(()));
+    assert_eq!(keyboard::scancode_set(), Ok(ScancodeSet::Set2));
+    assert_eq!(
+        keyboard::set_scancode_set(ScancodeSet::Set1),
+        Err(Ps2Error::InvalidArgument)
+    );
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
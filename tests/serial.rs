@@ -0,0 +1,130 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+extern crate alloc;
+use alloc::{rc::Rc, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::arch::interrupts::{PIC_1_OFFSET, SERIAL_IRQ_1};
+use rustos::arch::irq;
+use rustos::cooperative::executor::Executor;
+use rustos::cooperative::serial::{LineReader, SerialStream, SERIAL_INPUTS};
+use rustos::serial::{self, ComPort, DataBits, Parity, SerialConfig, SerialError, StopBits};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+fn receive(bytes: &[u8]) {
+    rustos::arch::no_interrupts(|| {
+        for &byte in bytes {
+            SERIAL_INPUTS[ComPort::Com1.index()].push(byte);
+        }
+    });
+}
+
+#[test_case]
+pub fn handler_registered() {
+    serial_print!("testing serial IRQ registration...");
+    assert!(irq::handler_names(PIC_1_OFFSET + SERIAL_IRQ_1).any(|name| name == "serial"));
+    assert!(!irq::is_masked(SERIAL_IRQ_1));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn ports_detected() {
+    serial_print!("testing serial port detection...");
+    assert!(serial::is_present(ComPort::Com1));
+    assert_eq!(serial::config(ComPort::Com1), Some(SerialConfig::default()));
+    // QEMU only emulates the port given with -serial.
+    assert!(!serial::is_present(ComPort::Com4));
+    assert_eq!(
+        serial::configure(ComPort::Com4, SerialConfig::default()),
+        Err(SerialError::NotPresent)
+    );
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn line_settings() {
+    serial_print!("testing serial line settings...");
+    let config = SerialConfig {
+        baud_rate: 1000,
+        ..SerialConfig::default()
+    };
+    assert_eq!(serial::configure(ComPort::Com1, config), Err(SerialError::InvalidBaudRate));
+
+    let config = SerialConfig {
+        baud_rate: 38_400,
+        data_bits: DataBits::Seven,
+        parity: Parity::Even,
+        stop_bits: StopBits::Two,
+        fifo: None,
+    };
+    assert_eq!(serial::configure(ComPort::Com1, config), Ok(()));
+    assert_eq!(serial::config(ComPort::Com1), Some(config));
+    assert_eq!(serial::configure(ComPort::Com1, SerialConfig::default()), Ok(()));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn buffered_output() {
+    serial_print!("testing buffered serial output...");
+    // More than the transmit buffer holds, so the writer has to wait for
+    // the transmit interrupt.
+    for _ in 0..serial::TX_BUFFER_SIZE / 64 + 1 {
+        serial::write(ComPort::Com1, &[b'.'; 64]);
+    }
+    serial::flush(ComPort::Com1);
+    rustos::arch::no_interrupts(|| serial_print!("polled output..."));
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn stream_receives_bytes() {
+    serial_print!("testing SerialStream...");
+    let mut stream = SerialStream::new();
+    receive(b"ok");
+    assert_eq!(stream.try_next(), Some(b'o'));
+    assert_eq!(stream.try_next(), Some(b'k'));
+    assert_eq!(stream.try_next(), None);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn line_reader_edits_lines() {
+    serial_print!("testing LineReader...");
+    let mut executor = Executor::new();
+    let lines = Rc::new(RefCell::new(Vec::<String>::new()));
+
+    let mut reader = LineReader::new(SerialStream::new());
+    let read = lines.clone();
+    executor.spawn(async move {
+        while let Some(line) = reader.read_line().await {
+            read.borrow_mut().push(line);
+        }
+    }).unwrap();
+    executor.run_until_stalled();
+
+    receive(b"hellp\x08o\r\nworld\n\r\n");
+    executor.run_until_stalled();
+
+    assert_eq!(*lines.borrow(), ["hello", "world", ""]);
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
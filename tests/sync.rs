@@ -0,0 +1,173 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+extern crate alloc;
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::{Cell, RefCell};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::cooperative::executor::Executor;
+use rustos::cooperative::sync::{Barrier, Mutex, Notify, RwLock, Semaphore};
+use rustos::{serial_print, serial_println};
+use rustos::test::{exit_qemu, QemuExitCode};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+/// Returns `Pending` once, waking itself.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[test_case]
+pub fn mutex_held_across_await() {
+    serial_print!("testing async Mutex...");
+    let mut executor = Executor::new();
+    let mutex = Rc::new(Mutex::new(Vec::new()));
+
+    for id in 0..3 {
+        let mutex = mutex.clone();
+        executor.spawn(async move {
+            let mut guard = mutex.lock().await;
+            guard.push(id);
+            YieldNow(false).await;
+            guard.push(id);
+        }).unwrap();
+    }
+    executor.run_until_stalled();
+
+    assert_eq!(*mutex.try_lock().unwrap(), [0, 0, 1, 1, 2, 2]);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn rwlock_readers_share() {
+    serial_print!("testing async RwLock...");
+    let mut executor = Executor::new();
+    let lock = Rc::new(RwLock::new(0));
+    let max_readers = Rc::new(Cell::new(0));
+    let readers = Rc::new(Cell::new(0));
+
+    for _ in 0..3 {
+        let (lock, readers, max_readers) = (lock.clone(), readers.clone(), max_readers.clone());
+        executor.spawn(async move {
+            let _guard = lock.read().await;
+            readers.set(readers.get() + 1);
+            max_readers.set(core::cmp::max(max_readers.get(), readers.get()));
+            YieldNow(false).await;
+            readers.set(readers.get() - 1);
+        }).unwrap();
+    }
+    let writer = lock.clone();
+    executor.spawn(async move { *writer.write().await += 1 }).unwrap();
+    executor.run_until_stalled();
+
+    assert_eq!(max_readers.get(), 3);
+    assert_eq!(*lock.try_read().unwrap(), 1);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn semaphore_limits_tasks() {
+    serial_print!("testing Semaphore...");
+    let mut executor = Executor::new();
+    let semaphore = Rc::new(Semaphore::new(2));
+    let running = Rc::new(Cell::new(0));
+    let max_running = Rc::new(Cell::new(0));
+
+    for _ in 0..5 {
+        let (semaphore, running, max_running) =
+            (semaphore.clone(), running.clone(), max_running.clone());
+        executor.spawn(async move {
+            let _permit = semaphore.acquire().await;
+            running.set(running.get() + 1);
+            max_running.set(core::cmp::max(max_running.get(), running.get()));
+            YieldNow(false).await;
+            running.set(running.get() - 1);
+        }).unwrap();
+    }
+    executor.run_until_stalled();
+
+    assert_eq!(max_running.get(), 2);
+    assert_eq!(semaphore.available_permits(), 2);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn notify_wakes_waiters() {
+    serial_print!("testing Notify...");
+    let mut executor = Executor::new();
+    let notify = Rc::new(Notify::new());
+    let woken = Rc::new(RefCell::new(Vec::new()));
+
+    for id in 0..3 {
+        let (notify, woken) = (notify.clone(), woken.clone());
+        executor.spawn(async move {
+            notify.notified().await;
+            woken.borrow_mut().push(id);
+        }).unwrap();
+    }
+    executor.run_until_stalled();
+    assert!(woken.borrow().is_empty());
+
+    notify.notify_one();
+    executor.run_until_stalled();
+    assert_eq!(*woken.borrow(), [0]);
+
+    notify.notify_all();
+    executor.run_until_stalled();
+    assert_eq!(*woken.borrow(), [0, 1, 2]);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn barrier_releases_all() {
+    serial_print!("testing Barrier...");
+    let mut executor = Executor::new();
+    let barrier = Rc::new(Barrier::new(3));
+    let passed = Rc::new(Cell::new(0));
+    let leaders = Rc::new(Cell::new(0));
+
+    for _ in 0..3 {
+        let (barrier, passed, leaders) = (barrier.clone(), passed.clone(), leaders.clone());
+        executor.spawn(async move {
+            if barrier.wait().await.is_leader() {
+                leaders.set(leaders.get() + 1);
+            }
+            passed.set(passed.get() + 1);
+        }).unwrap();
+    }
+    executor.run_until_stalled();
+
+    assert_eq!(passed.get(), 3);
+    assert_eq!(leaders.get(), 1);
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
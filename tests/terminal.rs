@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+extern crate alloc;
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::cooperative::terminal::{dispatch_key, TerminalInput};
+use rustos::keyboard::{DecodedKey, KeyCode, Modifiers};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::vga_buffer::{self, TERMINALS, TERMINAL_COUNT};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+const ALT: Modifiers = Modifiers {
+    left_shift: false,
+    right_shift: false,
+    left_ctrl: false,
+    right_ctrl: false,
+    left_alt: true,
+    right_alt: false,
+    caps_lock: false,
+    num_lock: false,
+    scroll_lock: false,
+};
+
+#[test_case]
+pub fn switch_with_alt_function_keys() {
+    serial_print!("testing terminal switching...");
+    assert_eq!(vga_buffer::active_terminal(), 0);
+    dispatch_key(DecodedKey::RawKey(KeyCode::F3), &ALT);
+    assert_eq!(vga_buffer::active_terminal(), 2);
+    // Without Alt the key is input.
+    dispatch_key(DecodedKey::RawKey(KeyCode::F1), &Modifiers::default());
+    assert_eq!(vga_buffer::active_terminal(), 2);
+    dispatch_key(DecodedKey::RawKey(KeyCode::F1), &ALT);
+    assert_eq!(vga_buffer::active_terminal(), 0);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn input_goes_to_the_active_terminal() {
+    serial_print!("testing terminal input...");
+    let mut first = TerminalInput::try_new(0).unwrap();
+    let mut second = TerminalInput::try_new(1).unwrap();
+
+    dispatch_key(DecodedKey::Unicode('a'), &Modifiers::default());
+    dispatch_key(DecodedKey::RawKey(KeyCode::F2), &ALT);
+    dispatch_key(DecodedKey::Unicode('b'), &Modifiers::default());
+    dispatch_key(DecodedKey::RawKey(KeyCode::F1), &ALT);
+
+    assert_eq!(first.try_next(), Some(DecodedKey::Unicode('a')));
+    assert_eq!(first.try_next(), None);
+    assert_eq!(second.try_next(), Some(DecodedKey::Unicode('b')));
+    assert_eq!(second.try_next(), None);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn terminals_keep_their_cursor() {
+    serial_print!("testing terminal state...");
+    // Each terminal writes its name at its own place.
+    for index in 0..TERMINAL_COUNT {
+        vga_buffer::print_to(
+            index,
+            format_args!("\x1b[H\x1b[2J\x1b[{};{}Hterminal {}", index + 2, index * 3 + 1, index),
+        );
+    }
+    for index in 0..TERMINAL_COUNT {
+        vga_buffer::switch_terminal(index);
+        assert_eq!(vga_buffer::active_terminal(), index);
+        assert!(TERMINALS[index].lock().is_active());
+
+        let text = alloc::format!("terminal {}", index);
+        let (row, column) = (index + 1, index * 3);
+        for (i, byte) in text.bytes().enumerate() {
+            assert_eq!(vga_buffer::screen_byte(row, column + i), byte);
+        }
+        let cursor = (row, column + text.len());
+        assert_eq!(TERMINALS[index].lock().cursor(), cursor);
+        assert_eq!(vga_buffer::screen_cursor(), cursor);
+    }
+    vga_buffer::switch_terminal(0);
+    assert!(!TERMINALS[TERMINAL_COUNT - 1].lock().is_active());
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rustos::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use bootloader::{bootinfo::BootInfo, entry_point};
+
+use rustos::cooperative::executor;
+use rustos::thread::{self, ThreadId};
+use rustos::test::{exit_qemu, QemuExitCode};
+use rustos::{serial_print, serial_println};
+
+entry_point!(test_kmain);
+
+pub fn test_kmain(boot_info: &'static BootInfo) -> ! {
+    rustos::init(boot_info);
+    test_main();
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
+
+#[test_case]
+pub fn join_returns_result() {
+    serial_print!("testing thread join...");
+    let handle = thread::spawn("adder", || 40 + 2).unwrap();
+    assert_ne!(handle.id(), ThreadId::BOOT);
+    assert_eq!(handle.join(), 42);
+    assert_eq!(thread::current(), ThreadId::BOOT);
+    serial_println!("[ok]");
+}
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static SPINS: AtomicU64 = AtomicU64::new(0);
+
+#[test_case]
+pub fn busy_thread_is_preempted() {
+    serial_print!("testing preemption...");
+    let handle = thread::spawn("spinner", || {
+        while !STOP.load(Ordering::SeqCst) {
+            SPINS.fetch_add(1, Ordering::SeqCst);
+        }
+    }).unwrap();
+
+    // Only returns if the spinning thread gets preempted.
+    thread::sleep(2);
+    STOP.store(true, Ordering::SeqCst);
+    handle.join();
+    assert!(SPINS.load(Ordering::SeqCst) > 0);
+    serial_println!("[ok]");
+}
+
+#[test_case]
+pub fn sleep_waits_for_ticks() {
+    serial_print!("testing thread sleep...");
+    let start = thread::ticks();
+    thread::sleep(3);
+    assert!(thread::ticks() >= start + 3);
+    serial_println!("[ok]");
+}
+
+static RAN: AtomicBool = AtomicBool::new(false);
+
+#[test_case]
+pub fn executor_in_thread() {
+    serial_print!("testing executor thread...");
+    let handle = executor::spawn_executor_thread("executor", |executor| {
+        let spawner = executor.get_spawner();
+        executor.spawn(async move {
+            RAN.store(true, Ordering::SeqCst);
+            spawner.shutdown();
+        }).unwrap();
+    }).unwrap();
+
+    handle.join();
+    assert!(RAN.load(Ordering::SeqCst));
+    serial_println!("[ok]");
+}
+
+rustos::test_panic!(QemuExitCode::Failed);